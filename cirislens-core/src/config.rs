@@ -0,0 +1,711 @@
+//! Runtime pipeline configuration.
+//!
+//! Operator-tunable policies and limits for trace ingestion. A single
+//! global `PipelineConfig` is held behind a lock (same pattern as the
+//! schema and key caches); each batch takes a snapshot in
+//! `BatchContext::new` so a config change mid-batch can't produce
+//! inconsistent decisions across traces.
+//!
+//! Python updates the config with a partial JSON object via
+//! `configure_pipeline` — only the keys present are changed, everything
+//! else keeps its current value.
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::extraction::json_path::ControlCharMode;
+use crate::extraction::metadata::get_action_taxonomy;
+use crate::pipeline::consent::agent_consent_count;
+use crate::pipeline::known_malformed::DEFAULT_KNOWN_MALFORMED_CAPACITY;
+use crate::pipeline::recent_rejections::{DEFAULT_RECENT_REJECTIONS, DEFAULT_REJECTION_PREVIEW_CHARS};
+use crate::security::pii::{
+    get_always_scrub_fields, get_pii_target_fields, CardFormat, PiiMode, RedactionFormat, UrlFormat,
+};
+use crate::security::sanitizer::{get_scan_excluded_fields, OversizeMode, XssMode};
+use crate::routing::sampling::check_sample_rate;
+use crate::storage::queries::DEFAULT_QUARANTINE_TABLE;
+use crate::validation::schema::{get_schema_cache, CACHE_TTL_SECS};
+use crate::validation::signature::{get_key_cache, KEY_CACHE_TTL_SECS};
+use crate::validation::replay_detection::DEFAULT_REPLAY_WINDOW;
+use crate::validation::verification_cache::DEFAULT_VERIFICATION_CACHE_CAPACITY;
+
+/// Cache refresh settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Seconds before the schema cache reports it needs a refresh.
+    pub schema_ttl_secs: u64,
+    /// Seconds before the public key cache reports it needs a refresh.
+    pub key_ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            schema_ttl_secs: CACHE_TTL_SECS,
+            key_ttl_secs: KEY_CACHE_TTL_SECS,
+        }
+    }
+}
+
+/// Handling of a value outside a field's allowed-values set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnumViolationAction {
+    /// Log the violation and store the value as-is.
+    #[default]
+    Keep,
+    /// Store the `unknown` sentinel instead of the value.
+    Unknown,
+    /// Store the value and list the column in `enum_violations`.
+    Flag,
+}
+
+/// How strictly required fields are checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequiredFieldCheck {
+    /// A required field only has to be present.
+    #[default]
+    Present,
+    /// A present but empty value (`null`, `""`, `[]`, `{}`) counts as
+    /// missing and isn't stored.
+    NonEmpty,
+    /// As `NonEmpty`, and reject traces missing a required field with
+    /// reason `missing_required_field`.
+    Reject,
+}
+
+/// Thresholds for deriving `risk_bucket` from the IDMA columns.
+///
+/// `high` when the fragility flag is set or either metric crosses its
+/// high threshold; `medium` when either crosses its medium threshold;
+/// `low` otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiskBucketConfig {
+    /// `idma_k_eff` below this is high risk (few independent sources).
+    pub high_k_eff_below: f64,
+    /// `idma_k_eff` below this is medium risk.
+    pub medium_k_eff_below: f64,
+    /// `idma_correlation_risk` at or above this is high risk.
+    pub high_correlation_risk: f64,
+    /// `idma_correlation_risk` at or above this is medium risk.
+    pub medium_correlation_risk: f64,
+}
+
+impl Default for RiskBucketConfig {
+    fn default() -> Self {
+        Self {
+            high_k_eff_below: 2.0,
+            medium_k_eff_below: 3.0,
+            high_correlation_risk: 0.7,
+            medium_correlation_risk: 0.4,
+        }
+    }
+}
+
+/// Field extraction settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractionConfig {
+    /// Handling of C0 control characters in extracted string values.
+    pub control_chars: ControlCharMode,
+    /// Handling of values outside a field's allowed-values set.
+    pub enum_violation: EnumViolationAction,
+    /// Whether required fields must be non-empty, and whether traces
+    /// missing one are rejected.
+    pub required_fields: RequiredFieldCheck,
+    pub risk_bucket: RiskBucketConfig,
+    /// Maximum extracted metadata entries kept per trace; 0 = unlimited.
+    /// Flags the pipeline adds after extraction are not counted.
+    pub max_metadata_entries: usize,
+    /// Add `schema_match_confidence` (0.0-1.0) to the metadata.
+    pub schema_match_confidence: bool,
+    /// Add the matched schema's `schema_status` (current / supported /
+    /// deprecated) to the metadata.
+    pub schema_status: bool,
+    /// Model-name / API-base patterns for the derived `providers` column,
+    /// checked in order.
+    pub provider_patterns: Vec<ProviderPattern>,
+    /// Return `extracted_metadata` in column order, plus a deterministic
+    /// `extracted_metadata_json`, so output diffs cleanly across runs.
+    pub sorted_output: bool,
+    /// Component paths tried in order for the event type (e.g. `type`,
+    /// `data.event_type`); the first string found wins.
+    pub event_type_keys: Vec<String>,
+}
+
+/// Case-insensitive substring of a model name or API base, and the
+/// provider it indicates.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderPattern {
+    pub pattern: String,
+    pub provider: String,
+}
+
+impl ProviderPattern {
+    fn new(pattern: &str, provider: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            provider: provider.to_string(),
+        }
+    }
+}
+
+/// Default provider patterns.
+pub fn default_provider_patterns() -> Vec<ProviderPattern> {
+    [
+        ("claude", "anthropic"),
+        ("anthropic", "anthropic"),
+        ("gpt", "openai"),
+        ("openai", "openai"),
+        ("gemini", "google"),
+        ("googleapis", "google"),
+        ("llama", "meta"),
+        ("mistral", "mistral"),
+        ("groq", "groq"),
+        ("together", "together"),
+        ("openrouter", "openrouter"),
+    ]
+    .into_iter()
+    .map(|(pattern, provider)| ProviderPattern::new(pattern, provider))
+    .collect()
+}
+
+/// Signature verification settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SignatureConfig {
+    /// Distinct registered keys that must verify a trace. Schemas may
+    /// override this via their `signature_quorum` option.
+    pub quorum_threshold: usize,
+    /// Match key ids case-insensitively. Takes effect on the next public
+    /// key load.
+    pub case_insensitive_key_ids: bool,
+    /// Accept hex-encoded signatures (128 hex chars) besides base64.
+    pub accept_hex_signatures: bool,
+    /// Handling of accepted traces whose signature bytes also appear on
+    /// another trace in the same batch (a replay signal).
+    pub duplicate_signature_action: DuplicateSignatureAction,
+    /// Pre-verify single-signature traces' 1.9.9 canonical for the whole
+    /// batch with one `verify_batch` call; traces that don't verify there
+    /// fall through to the per-trace format cascade.
+    pub batch_verify: bool,
+    /// Single-signature verification results remembered, so retried
+    /// traces skip the format cascade; 0 disables the cache.
+    pub verification_cache_capacity: usize,
+    /// Verified signatures remembered so a failing trace carrying a
+    /// signature copied from another trace is reported as a suspected
+    /// replay; 0 disables the check.
+    pub replay_window: usize,
+    /// Domain-separation tag (e.g. `"CIRIS-TRACE-v1\n"`) tried before the
+    /// canonical message for every key, after the bare message; keys can
+    /// also carry their own via `domain_prefix` key metadata.
+    pub domain_prefix: Option<String>,
+}
+
+impl Default for SignatureConfig {
+    fn default() -> Self {
+        Self {
+            quorum_threshold: 1,
+            case_insensitive_key_ids: false,
+            accept_hex_signatures: false,
+            duplicate_signature_action: DuplicateSignatureAction::Ignore,
+            batch_verify: false,
+            verification_cache_capacity: DEFAULT_VERIFICATION_CACHE_CAPACITY,
+            replay_window: DEFAULT_REPLAY_WINDOW,
+            domain_prefix: None,
+        }
+    }
+}
+
+/// What to do with traces sharing identical signature bytes in one batch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateSignatureAction {
+    /// No duplicate detection.
+    #[default]
+    Ignore,
+    /// Keep normal routing; mark the traces with
+    /// `duplicate_signature_in_batch=true`.
+    Flag,
+    /// Mark the traces and route them to the `review` destination.
+    Review,
+}
+
+/// Fast-path rejection settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FastRejectConfig {
+    /// Recently seen malformed payload hashes to remember; 0 disables.
+    pub known_malformed_capacity: usize,
+    /// Deepest object/array nesting accepted before parsing; deeper bodies
+    /// are rejected with `excessive_nesting`. 0 disables the check.
+    pub max_nesting_depth: usize,
+    /// Most object keys (counted at every depth) a single component may
+    /// carry before `wide_component_action` applies. 0 disables the check.
+    pub max_fields_per_component: usize,
+    pub wide_component_action: WideComponentAction,
+}
+
+impl Default for FastRejectConfig {
+    fn default() -> Self {
+        Self {
+            known_malformed_capacity: DEFAULT_KNOWN_MALFORMED_CAPACITY,
+            max_nesting_depth: 64,
+            max_fields_per_component: 5_000,
+            wide_component_action: WideComponentAction::Flag,
+        }
+    }
+}
+
+/// What to do with a trace carrying a component over
+/// `max_fields_per_component`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WideComponentAction {
+    /// Keep normal routing; mark the trace with `component_too_wide=true`.
+    #[default]
+    Flag,
+    /// Reject the trace as malformed with reason `component_too_wide`.
+    Reject,
+}
+
+/// What to do with a trace whose PII scrub replaced too much of a field.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverScrubAction {
+    /// Keep normal routing; mark the trace with `pii_over_scrubbed=true`.
+    #[default]
+    Flag,
+    /// Mark the trace and route it to the `review` destination.
+    Review,
+}
+
+/// PII scrubbing sanity-check settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PiiConfig {
+    /// Percentage of a single field replaced by placeholders above which
+    /// the scrub is considered a likely pattern misfire.
+    pub max_replaced_pct: f64,
+    pub over_scrub_action: OverScrubAction,
+    /// Redact (default), encrypt or pseudonymize matched PII.
+    pub mode: PiiMode,
+    /// Card number replacement in redact mode: `[CREDIT_CARD]` (default)
+    /// or `[CARD:****1234]`.
+    pub card_format: CardFormat,
+    /// URL replacement in redact mode: `[URL]` (default) or
+    /// `https://host/[REDACTED]`.
+    pub url_format: UrlFormat,
+    /// Categories (`email`, `phone`, `credit_card`, ...) whose redaction
+    /// placeholders are padded to the match length, e.g.
+    /// `[EMAIL]XXXXXXX`.
+    pub preserve_length: Vec<String>,
+}
+
+impl Default for PiiConfig {
+    fn default() -> Self {
+        Self {
+            max_replaced_pct: 90.0,
+            over_scrub_action: OverScrubAction::Flag,
+            mode: PiiMode::Redact,
+            card_format: CardFormat::Redact,
+            url_format: UrlFormat::Redact,
+            preserve_length: Vec::new(),
+        }
+    }
+}
+
+impl PiiConfig {
+    /// Placeholder format for redact mode.
+    pub fn redaction_format(&self) -> RedactionFormat<'_> {
+        RedactionFormat {
+            card_format: self.card_format,
+            url_format: self.url_format,
+            preserve_length: &self.preserve_length,
+        }
+    }
+}
+
+/// Backpressure thresholds reported back to the API layer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackpressureConfig {
+    /// Whole-batch processing time above which the core reports overload.
+    pub max_batch_ms: u64,
+    /// Single-trace processing time above which the core reports overload.
+    pub max_trace_ms: u64,
+    /// Backoff suggested to the caller when overloaded.
+    pub backoff_ms: u64,
+}
+
+impl Default for BackpressureConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_ms: 5_000,
+            max_trace_ms: 1_000,
+            backoff_ms: 1_000,
+        }
+    }
+}
+
+impl Default for ExtractionConfig {
+    fn default() -> Self {
+        Self {
+            control_chars: ControlCharMode::default(),
+            enum_violation: EnumViolationAction::default(),
+            required_fields: RequiredFieldCheck::default(),
+            risk_bucket: RiskBucketConfig::default(),
+            max_metadata_entries: 256,
+            schema_match_confidence: false,
+            schema_status: false,
+            provider_patterns: default_provider_patterns(),
+            sorted_output: false,
+            event_type_keys: vec!["event_type".to_string()],
+        }
+    }
+}
+
+/// Security sanitizer settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SanitizerConfig {
+    /// Handling of string fields over the per-field size limit.
+    pub oversize_mode: OversizeMode,
+    /// Log XSS detections only, or also neutralize them in the stored
+    /// trace.
+    pub xss_mode: XssMode,
+}
+
+/// Live-debugging aids.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiagnosticsConfig {
+    /// Recent rejected traces kept for `get_recent_rejections`; 0 disables.
+    pub recent_rejections: usize,
+    /// Characters of the raw body kept in each rejection preview.
+    pub rejection_preview_chars: usize,
+    /// Record per-key verification outcomes for `get_key_activity`.
+    pub key_activity: bool,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            recent_rejections: DEFAULT_RECENT_REJECTIONS,
+            rejection_preview_chars: DEFAULT_REJECTION_PREVIEW_CHARS,
+            key_activity: true,
+        }
+    }
+}
+
+/// Timestamp comparison settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimestampConfig {
+    /// Clock skew allowed, in either direction, in every timestamp
+    /// comparison (consent, staleness, future-batch).
+    pub clock_skew_tolerance_secs: u64,
+    /// Reject traces with a component timestamp older than this many
+    /// seconds before the batch timestamp (`stale_trace`). 0 disables.
+    pub max_trace_age_secs: u64,
+    /// Reject batches timestamped after the server clock (`future_batch`)
+    /// and traces with a component timestamp after the batch timestamp
+    /// (`future_timestamp`).
+    pub reject_future_timestamps: bool,
+}
+
+impl Default for TimestampConfig {
+    fn default() -> Self {
+        Self {
+            clock_skew_tolerance_secs: 300,
+            max_trace_age_secs: 0,
+            reject_future_timestamps: false,
+        }
+    }
+}
+
+/// Per-agent `seq` checks.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SequenceConfig {
+    /// Agents whose last accepted `seq` is remembered so gaps and
+    /// regressions are flagged; 0 (the default) disables the checks.
+    pub tracked_agents: usize,
+}
+
+/// Batch-level settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatchConfig {
+    /// Maximum events in a compressed batch blob; together with
+    /// `MAX_TRACE_SIZE` this bounds how far a blob may inflate.
+    pub max_batch_events: usize,
+    /// Return a `batch_hash` chaining each batch's trace hashes to the
+    /// previous batch's hash, for tamper evidence on stored traces.
+    pub hash_chain: bool,
+    /// Mark accepted traces with `consent_conflict` when the traces in a
+    /// batch embed differing consent timestamps.
+    pub flag_consent_conflicts: bool,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_events: 100,
+            hash_chain: false,
+            flag_consent_conflicts: false,
+        }
+    }
+}
+
+/// What to do with traces declaring a `schema_version` that isn't loaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnsupportedSchemaAction {
+    /// Keep normal routing; mark the trace with
+    /// `schema_version_unsupported=true`.
+    #[default]
+    Flag,
+    /// Mark the trace and route it to the `quarantine` destination.
+    Quarantine,
+}
+
+/// Which schema wins for traces matching both the connectivity schema and
+/// a regular one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MixedConnectivityAction {
+    /// Use the regular schema, so the trace still goes through signature
+    /// verification and field extraction.
+    #[default]
+    RegularWins,
+    /// Treat the trace as a connectivity event.
+    ConnectivityWins,
+    /// Reject the trace as `ambiguous_connectivity_trace`.
+    Reject,
+}
+
+/// Routing policy settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoutingConfig {
+    /// Handling of traces whose declared schema version isn't loaded,
+    /// whatever version event-type detection falls back to.
+    pub unsupported_schema_action: UnsupportedSchemaAction,
+    /// Table quarantined traces are written to (see
+    /// `build_quarantine_insert`).
+    pub quarantine_table: String,
+    /// Handling of traces carrying both connectivity events and a
+    /// regular schema's signature events.
+    pub mixed_connectivity: MixedConnectivityAction,
+    /// Fraction of production traces stored (1.0 keeps all); the rest
+    /// are routed to `sampled_out`.
+    pub production_sample_rate: f64,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            unsupported_schema_action: UnsupportedSchemaAction::default(),
+            quarantine_table: DEFAULT_QUARANTINE_TABLE.to_string(),
+            mixed_connectivity: MixedConnectivityAction::default(),
+            production_sample_rate: 1.0,
+        }
+    }
+}
+
+/// Top-level pipeline configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipelineConfig {
+    pub cache: CacheConfig,
+    pub extraction: ExtractionConfig,
+    pub signature: SignatureConfig,
+    pub fast_reject: FastRejectConfig,
+    pub pii: PiiConfig,
+    pub backpressure: BackpressureConfig,
+    pub sanitizer: SanitizerConfig,
+    pub diagnostics: DiagnosticsConfig,
+    pub timestamps: TimestampConfig,
+    pub batch: BatchConfig,
+    pub routing: RoutingConfig,
+    pub sequence: SequenceConfig,
+}
+
+impl PipelineConfig {
+    /// Turn off every tracker that writes process-wide state (rejection
+    /// buffer, known-malformed set, replay window, verification cache,
+    /// key activity, agent sequences), so a run under this config leaves
+    /// no trace on live ingestion. Used by lint and benchmark runs.
+    ///
+    /// Per-format signature metrics still count the run's attempts.
+    pub fn isolate(&mut self) {
+        self.diagnostics.recent_rejections = 0;
+        self.diagnostics.key_activity = false;
+        self.fast_reject.known_malformed_capacity = 0;
+        self.signature.replay_window = 0;
+        self.signature.verification_cache_capacity = 0;
+        self.sequence.tracked_agents = 0;
+    }
+
+    /// Apply a partial JSON update on top of this config.
+    ///
+    /// Objects are merged recursively; any other value replaces the
+    /// current one. Unknown keys and type mismatches are errors so a
+    /// typo in an operator's config doesn't silently do nothing.
+    pub fn apply_json(&mut self, update: &serde_json::Value) -> Result<(), String> {
+        let mut current =
+            serde_json::to_value(&*self).map_err(|e| format!("config serialize: {}", e))?;
+        merge_json(&mut current, update, "")?;
+        let updated: Self =
+            serde_json::from_value(current).map_err(|e| format!("invalid config: {}", e))?;
+        check_sample_rate(updated.routing.production_sample_rate)?;
+        *self = updated;
+        Ok(())
+    }
+}
+
+/// Recursively merge `update` into `target`, rejecting keys `target` lacks.
+fn merge_json(
+    target: &mut serde_json::Value,
+    update: &serde_json::Value,
+    path: &str,
+) -> Result<(), String> {
+    match (target, update) {
+        (serde_json::Value::Object(target_map), serde_json::Value::Object(update_map)) => {
+            for (key, value) in update_map {
+                let key_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                match target_map.get_mut(key) {
+                    Some(existing) => merge_json(existing, value, &key_path)?,
+                    None => return Err(format!("unknown config key: {}", key_path)),
+                }
+            }
+            Ok(())
+        }
+        (target, update) => {
+            *target = update.clone();
+            Ok(())
+        }
+    }
+}
+
+/// Effective configuration snapshot for audit/support: every config value
+/// plus the current state of the schema and key caches.
+///
+/// Read-only; takes the cache read locks briefly.
+pub fn effective_config(config: &PipelineConfig) -> serde_json::Value {
+    let schema_cache = get_schema_cache();
+    let key_cache = get_key_cache();
+
+    let mut schema_versions = schema_cache.schema_versions();
+    schema_versions.sort();
+    let mut scan_excluded_fields: Vec<String> = get_scan_excluded_fields().into_iter().collect();
+    scan_excluded_fields.sort();
+    let mut always_scrub_fields: Vec<String> = get_always_scrub_fields().into_iter().collect();
+    always_scrub_fields.sort();
+    let mut pii_target_fields: Vec<String> = get_pii_target_fields().into_iter().collect();
+    pii_target_fields.sort();
+    let action_taxonomy: BTreeMap<String, String> = get_action_taxonomy().into_iter().collect();
+
+    serde_json::json!({
+        "config": config,
+        "caches": {
+            "schemas_loaded": schema_cache.is_loaded(),
+            "schema_versions": schema_versions,
+            "schema_cache_age_secs": schema_cache.cache_age_secs(),
+            "schema_needs_refresh": schema_cache.needs_refresh_after(config.cache.schema_ttl_secs),
+            "public_key_count": key_cache.key_count(),
+            "key_cache_age_secs": key_cache.cache_age_secs(),
+            "keys_need_refresh": key_cache.needs_refresh_after(config.cache.key_ttl_secs),
+        },
+        "db_lists": {
+            "scan_excluded_fields": scan_excluded_fields,
+            "always_scrub_fields": always_scrub_fields,
+            "pii_target_fields": pii_target_fields,
+            "action_taxonomy": action_taxonomy,
+            "agent_consent_count": agent_consent_count(),
+        },
+    })
+}
+
+lazy_static! {
+    static ref PIPELINE_CONFIG: RwLock<PipelineConfig> = RwLock::new(PipelineConfig::default());
+}
+
+/// Get a read-only reference to the global pipeline config.
+pub fn get_pipeline_config() -> std::sync::RwLockReadGuard<'static, PipelineConfig> {
+    PIPELINE_CONFIG
+        .read()
+        .expect("Pipeline config lock poisoned")
+}
+
+/// Get a mutable reference to the global pipeline config.
+pub fn get_pipeline_config_mut() -> std::sync::RwLockWriteGuard<'static, PipelineConfig> {
+    PIPELINE_CONFIG
+        .write()
+        .expect("Pipeline config lock poisoned")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_apply_partial_update() {
+        let mut config = PipelineConfig::default();
+        config
+            .apply_json(&json!({"extraction": {"control_chars": "strip"}}))
+            .unwrap();
+        assert_eq!(config.extraction.control_chars, ControlCharMode::Strip);
+    }
+
+    #[test]
+    fn test_apply_rejects_unknown_key() {
+        let mut config = PipelineConfig::default();
+        let err = config
+            .apply_json(&json!({"extraction": {"no_such_knob": true}}))
+            .unwrap_err();
+        assert!(err.contains("extraction.no_such_knob"));
+        assert_eq!(config.extraction.control_chars, ControlCharMode::Keep);
+    }
+
+    #[test]
+    fn test_effective_config_reflects_updates() {
+        let mut config = PipelineConfig::default();
+        config
+            .apply_json(&json!({
+                "cache": {"schema_ttl_secs": 60},
+                "extraction": {"control_chars": "escape"}
+            }))
+            .unwrap();
+
+        let effective = effective_config(&config);
+        assert_eq!(effective["config"]["cache"]["schema_ttl_secs"], 60);
+        assert_eq!(effective["config"]["cache"]["key_ttl_secs"], KEY_CACHE_TTL_SECS);
+        assert_eq!(effective["config"]["extraction"]["control_chars"], "escape");
+        assert!(effective["caches"]["public_key_count"].is_u64());
+    }
+
+    #[test]
+    fn test_apply_rejects_bad_value() {
+        let mut config = PipelineConfig::default();
+        assert!(config
+            .apply_json(&json!({"extraction": {"control_chars": "shred"}}))
+            .is_err());
+        assert!(config
+            .apply_json(&json!({"routing": {"production_sample_rate": 1.5}}))
+            .is_err());
+        assert_eq!(config.routing.production_sample_rate, 1.0);
+    }
+}
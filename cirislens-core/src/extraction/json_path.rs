@@ -1,38 +1,149 @@
 //! JSON path resolution.
 //!
 //! Resolves dot-notation paths like "csdma.plausibility_score" to values in JSON.
+//!
+//! Grammar: dot-separated keys (`a.b`, numeric parts index arrays as in
+//! `items.0.name`) plus bracket selectors on arrays:
+//! - `[N]` — element N
+//! - `[*]` — every element; the rest of the path is applied to each and
+//!   the results collected into an array (elements it doesn't resolve in
+//!   are skipped)
+//! - `[key=value]` — the first object whose `key` has that value (compared
+//!   as its stored string form, so `[depth=2]` matches a number)
+
+use std::borrow::Cow;
 
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+/// Handling of C0 control characters (U+0000–U+001F) in extracted strings.
+///
+/// Tab and newline are always preserved; everything else in the C0 range
+/// (NUL, backspace, carriage return, ...) breaks TSV exports and log
+/// rendering downstream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlCharMode {
+    /// Store the string as-is.
+    #[default]
+    Keep,
+    /// Drop control characters.
+    Strip,
+    /// Replace each control character with a visible `\uXXXX` escape.
+    Escape,
+}
+
 /// Resolve a dot-notation path to a value in JSON.
 ///
 /// # Examples
 /// ```
+/// use cirislens_core::extraction::resolve_json_path;
 /// use serde_json::json;
 /// let data = json!({"csdma": {"plausibility_score": 0.95}});
 /// let value = resolve_json_path(&data, "csdma.plausibility_score");
 /// assert_eq!(value, Some(&json!(0.95)));
 /// ```
+///
+/// Paths with a `[*]` selector build a new array and resolve to None
+/// here; use `select_json_path` for those.
 pub fn resolve_json_path<'a>(data: &'a Value, path: &str) -> Option<&'a Value> {
+    match select_json_path(data, path)? {
+        Cow::Borrowed(value) => Some(value),
+        Cow::Owned(_) => None,
+    }
+}
+
+/// Resolve a path, including `[*]` selectors.
+///
+/// # Examples
+/// ```
+/// use cirislens_core::extraction::select_json_path;
+/// use serde_json::json;
+/// let data = json!({"components": [{"event_type": "A"}, {"event_type": "B"}]});
+/// let value = select_json_path(&data, "components[*].event_type");
+/// assert_eq!(value.as_deref(), Some(&json!(["A", "B"])));
+/// ```
+pub fn select_json_path<'a>(data: &'a Value, path: &str) -> Option<Cow<'a, Value>> {
     if path.is_empty() {
-        return Some(data);
+        return Some(Cow::Borrowed(data));
     }
+    let segments = parse_path(path)?;
+    select_segments(data, &segments)
+}
 
-    let mut current = data;
-    for part in path.split('.') {
-        match current {
-            Value::Object(obj) => {
-                current = obj.get(part)?;
+/// One step of a parsed path.
+#[derive(Debug, PartialEq)]
+enum PathSegment<'p> {
+    /// Object key, or array index when numeric.
+    Key(&'p str),
+    Index(usize),
+    Wildcard,
+    Filter(&'p str, &'p str),
+}
+
+/// Split a path into segments; None when brackets are malformed.
+fn parse_path(path: &str) -> Option<Vec<PathSegment<'_>>> {
+    let mut segments = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        if let Some(inner) = rest.strip_prefix('[') {
+            let end = inner.find(']')?;
+            let selector = &inner[..end];
+            segments.push(match selector {
+                "*" => PathSegment::Wildcard,
+                _ => match selector.split_once('=') {
+                    Some((key, value)) if !key.is_empty() => PathSegment::Filter(key, value),
+                    Some(_) => return None,
+                    None => PathSegment::Index(selector.parse().ok()?),
+                },
+            });
+            rest = &inner[end + 1..];
+        } else {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            if end == 0 {
+                return None;
             }
-            Value::Array(arr) => {
-                // Support array indexing like "items.0.name"
-                let index: usize = part.parse().ok()?;
-                current = arr.get(index)?;
+            segments.push(PathSegment::Key(&rest[..end]));
+            rest = &rest[end..];
+        }
+        // A '.' may follow any segment, but must lead somewhere
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            if after_dot.is_empty() {
+                return None;
             }
-            _ => return None,
+            rest = after_dot;
         }
     }
-    Some(current)
+    Some(segments)
+}
+
+fn select_segments<'a>(data: &'a Value, segments: &[PathSegment]) -> Option<Cow<'a, Value>> {
+    let Some((segment, rest)) = segments.split_first() else {
+        return Some(Cow::Borrowed(data));
+    };
+    let next = match (segment, data) {
+        (PathSegment::Key(key), Value::Object(obj)) => obj.get(*key)?,
+        // Support array indexing like "items.0.name"
+        (PathSegment::Key(key), Value::Array(arr)) => arr.get(key.parse::<usize>().ok()?)?,
+        (PathSegment::Index(index), Value::Array(arr)) => arr.get(*index)?,
+        (PathSegment::Filter(key, wanted), Value::Array(arr)) => arr
+            .iter()
+            .find(|item| item.get(*key).is_some_and(|v| value_to_string(v) == *wanted))?,
+        (PathSegment::Wildcard, Value::Array(arr)) => {
+            let collected: Vec<Value> = arr
+                .iter()
+                .filter_map(|item| select_segments(item, rest))
+                .map(Cow::into_owned)
+                .collect();
+            return if collected.is_empty() {
+                None
+            } else {
+                Some(Cow::Owned(Value::Array(collected)))
+            };
+        }
+        _ => return None,
+    };
+    select_segments(next, rest)
 }
 
 /// Convert a JSON value to a string representation for database storage.
@@ -46,6 +157,41 @@ pub fn value_to_string(value: &Value) -> String {
     }
 }
 
+/// Strip or escape C0 control characters (except tab/newline) per `mode`.
+///
+/// Returns the cleaned string and the number of characters cleaned.
+pub fn clean_control_chars(s: &str, mode: ControlCharMode) -> (String, usize) {
+    let is_target = |c: char| c < '\u{20}' && c != '\t' && c != '\n';
+
+    if mode == ControlCharMode::Keep || !s.chars().any(is_target) {
+        return (s.to_string(), 0);
+    }
+
+    let mut cleaned = String::with_capacity(s.len());
+    let mut count = 0;
+    for c in s.chars() {
+        if is_target(c) {
+            count += 1;
+            if mode == ControlCharMode::Escape {
+                cleaned.push_str(&format!("\\u{:04x}", c as u32));
+            }
+        } else {
+            cleaned.push(c);
+        }
+    }
+    (cleaned, count)
+}
+
+/// Convert a JSON value to a string, cleaning control characters per `mode`.
+///
+/// Returns the string and the number of control characters cleaned.
+pub fn value_to_string_cleaned(value: &Value, mode: ControlCharMode) -> (String, usize) {
+    match value {
+        Value::String(s) => clean_control_chars(s, mode),
+        _ => (value_to_string(value), 0),
+    }
+}
+
 /// Convert a JSON value to a float if possible.
 pub fn value_to_float(value: &Value) -> Option<f64> {
     match value {
@@ -78,6 +224,28 @@ pub fn value_to_bool(value: &Value) -> Option<bool> {
     }
 }
 
+/// Convert an object-form timestamp to RFC3339 (UTC).
+///
+/// Accepts `{"seconds": N, "nanos": N}` (protobuf style) or
+/// `{"secs": N, "micros": N}`; the sub-second part is optional. Returns
+/// None for any other value, including strings.
+pub fn object_timestamp_to_rfc3339(value: &Value) -> Option<String> {
+    let obj = value.as_object()?;
+    let secs = obj
+        .get("seconds")
+        .or_else(|| obj.get("secs"))
+        .and_then(value_to_int)?;
+    let nanos = match (obj.get("nanos"), obj.get("micros")) {
+        (Some(n), _) => value_to_int(n)?,
+        (None, Some(us)) => value_to_int(us)?.checked_mul(1_000)?,
+        (None, None) => 0,
+    };
+    let nanos = u32::try_from(nanos).ok().filter(|n| *n < 1_000_000_000)?;
+
+    chrono::DateTime::from_timestamp(secs, nanos)
+        .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -149,6 +317,51 @@ mod tests {
         assert_eq!(resolve_json_path(&data, ""), Some(&data));
     }
 
+    #[test]
+    fn test_wildcard_path() {
+        let data = json!({
+            "components": [
+                {"event_type": "THOUGHT_START", "data": {"confidence": 0.5}},
+                {"event_type": "ACTION_RESULT"},
+                {"event_type": "DMA_RESULTS", "data": {"confidence": 0.9}}
+            ]
+        });
+        assert_eq!(
+            select_json_path(&data, "components[*].event_type").as_deref(),
+            Some(&json!(["THOUGHT_START", "ACTION_RESULT", "DMA_RESULTS"]))
+        );
+        // Elements the rest of the path misses are skipped
+        assert_eq!(
+            select_json_path(&data, "components[*].data.confidence").as_deref(),
+            Some(&json!([0.5, 0.9]))
+        );
+        assert_eq!(select_json_path(&data, "components[*].missing"), None);
+        // The borrowing form can't return a built array
+        assert_eq!(resolve_json_path(&data, "components[*].event_type"), None);
+    }
+
+    #[test]
+    fn test_predicate_and_index_selectors() {
+        let data = json!({
+            "components": [
+                {"event_type": "THOUGHT_START", "depth": 1},
+                {"event_type": "ACTION_RESULT", "data": {"success": true}},
+                {"event_type": "ACTION_RESULT", "data": {"success": false}}
+            ]
+        });
+        assert_eq!(
+            resolve_json_path(&data, "components[event_type=ACTION_RESULT].data.success"),
+            Some(&json!(true))
+        );
+        assert_eq!(resolve_json_path(&data, "components[depth=1].event_type"), Some(&json!("THOUGHT_START")));
+        assert_eq!(resolve_json_path(&data, "components[event_type=NOPE]"), None);
+        assert_eq!(resolve_json_path(&data, "components[2].data.success"), Some(&json!(false)));
+
+        for bad in ["components[", "components[x]", "components[=a]", "components.", "a..b"] {
+            assert_eq!(resolve_json_path(&data, bad), None, "{}", bad);
+        }
+    }
+
     #[test]
     fn test_value_conversions() {
         assert_eq!(value_to_float(&json!(1.5)), Some(1.5));
@@ -158,4 +371,50 @@ mod tests {
         assert_eq!(value_to_bool(&json!("true")), Some(true));
         assert_eq!(value_to_bool(&json!(1)), Some(true));
     }
+
+    #[test]
+    fn test_object_timestamp_to_rfc3339() {
+        assert_eq!(
+            object_timestamp_to_rfc3339(&json!({"seconds": 1700000000, "nanos": 500000000})),
+            Some("2023-11-14T22:13:20.500Z".to_string())
+        );
+        assert_eq!(
+            object_timestamp_to_rfc3339(&json!({"secs": 1700000000, "micros": 250})),
+            Some("2023-11-14T22:13:20.000250Z".to_string())
+        );
+        assert_eq!(
+            object_timestamp_to_rfc3339(&json!({"seconds": 1700000000})),
+            Some("2023-11-14T22:13:20Z".to_string())
+        );
+        assert_eq!(object_timestamp_to_rfc3339(&json!({"nanos": 5})), None);
+        assert_eq!(
+            object_timestamp_to_rfc3339(&json!({"seconds": 1, "nanos": 2_000_000_000u64})),
+            None
+        );
+        assert_eq!(object_timestamp_to_rfc3339(&json!("2023-11-14T22:13:20Z")), None);
+    }
+
+    #[test]
+    fn test_clean_control_chars() {
+        let raw = "null\u{0}byte\u{8}\ttab\nline";
+
+        let (kept, n) = clean_control_chars(raw, ControlCharMode::Keep);
+        assert_eq!(kept, raw);
+        assert_eq!(n, 0);
+
+        let (stripped, n) = clean_control_chars(raw, ControlCharMode::Strip);
+        assert_eq!(stripped, "nullbyte\ttab\nline");
+        assert_eq!(n, 2);
+
+        let (escaped, n) = clean_control_chars(raw, ControlCharMode::Escape);
+        assert_eq!(escaped, "null\\u0000byte\\u0008\ttab\nline");
+        assert_eq!(n, 2);
+    }
+
+    #[test]
+    fn test_value_to_string_cleaned_non_string() {
+        let (s, n) = value_to_string_cleaned(&json!(42), ControlCharMode::Strip);
+        assert_eq!(s, "42");
+        assert_eq!(n, 0);
+    }
 }
@@ -3,19 +3,92 @@
 //! Dynamic field extraction based on schema definitions from database.
 //! Uses JSON path resolution to extract values and convert to target types.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::RwLock;
 
+use lazy_static::lazy_static;
+use serde::Serialize;
 use serde_json::Value;
 
-use crate::extraction::json_path::{resolve_json_path, value_to_bool, value_to_float, value_to_int, value_to_string};
-use crate::logging::structured::LogContext;
-use crate::validation::schema::get_schema_cache;
+use crate::config::{EnumViolationAction, ExtractionConfig, ProviderPattern, RequiredFieldCheck, RiskBucketConfig};
+use crate::extraction::json_path::{object_timestamp_to_rfc3339, resolve_json_path, select_json_path, value_to_bool, value_to_float, value_to_int, value_to_string_cleaned, ControlCharMode};
+use crate::logging::structured::{safe_truncate, LogContext};
+use crate::routing::mock_detection::parse_models_used;
+use crate::storage::queries::get_trace_columns;
+use crate::validation::schema::{get_schema_cache, FieldExtractionRule, SchemaCache};
+
+lazy_static! {
+    /// Raw `selected_action` variant (normalized key) -> canonical action,
+    /// loaded via `load_action_taxonomy_from_db`.
+    static ref ACTION_TAXONOMY: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
+}
+
+/// Replace the `selected_action` normalization map.
+///
+/// Keys are matched case-insensitively and ignoring surrounding whitespace.
+pub fn set_action_taxonomy(mapping: HashMap<String, String>) {
+    let mut taxonomy = ACTION_TAXONOMY
+        .write()
+        .expect("Action taxonomy lock poisoned");
+    *taxonomy = mapping
+        .into_iter()
+        .map(|(raw, canonical)| (taxonomy_key(&raw), canonical))
+        .collect();
+}
+
+/// Get a copy of the `selected_action` normalization map.
+pub fn get_action_taxonomy() -> HashMap<String, String> {
+    ACTION_TAXONOMY
+        .read()
+        .expect("Action taxonomy lock poisoned")
+        .clone()
+}
+
+fn taxonomy_key(raw: &str) -> String {
+    raw.trim().to_lowercase()
+}
+
+/// Add `selected_action_normalized` next to the raw `selected_action`.
+///
+/// Values missing from the map pass through unchanged and are flagged
+/// with `selected_action_unmapped=true`. Does nothing until a map is
+/// loaded, so deployments without one don't flag every trace.
+fn normalize_selected_action(
+    metadata: &mut HashMap<String, String>,
+    taxonomy: &HashMap<String, String>,
+    ctx: &LogContext,
+) {
+    if taxonomy.is_empty() {
+        return;
+    }
+    let Some(raw) = metadata.get("selected_action") else {
+        return;
+    };
+
+    let normalized = match taxonomy.get(&taxonomy_key(raw)) {
+        Some(canonical) => canonical.clone(),
+        None => {
+            log::debug!("{} ACTION_UNMAPPED value={:?}", ctx, raw);
+            metadata.insert("selected_action_unmapped".to_string(), "true".to_string());
+            metadata["selected_action"].clone()
+        }
+    };
+    metadata.insert("selected_action_normalized".to_string(), normalized);
+}
+
+/// A component's event type: the first of `keys` (JSON paths) that
+/// resolves to a string.
+pub fn component_event_type<'a>(component: &'a Value, keys: &[String]) -> Option<&'a str> {
+    keys.iter()
+        .find_map(|key| resolve_json_path(component, key).and_then(|v| v.as_str()))
+}
 
 /// Extract metadata from a trace using schema-defined field rules.
 ///
 /// # Arguments
 /// * `trace` - The trace JSON
 /// * `schema_version` - The detected schema version
+/// * `config` - Extraction settings from the batch config snapshot
 /// * `ctx` - Logging context
 ///
 /// # Returns
@@ -23,9 +96,45 @@ use crate::validation::schema::get_schema_cache;
 pub fn extract_trace_metadata(
     trace: &Value,
     schema_version: &str,
+    config: &ExtractionConfig,
+    ctx: &LogContext,
+) -> HashMap<String, String> {
+    let mut issues = ExtractionIssues::default();
+    extract_trace_metadata_with_issues(trace, schema_version, config, &mut issues, ctx)
+}
+
+/// Like `extract_trace_metadata`, also recording extraction issues
+/// (type mismatches, missing required fields, ...) into `issues`.
+pub fn extract_trace_metadata_with_issues(
+    trace: &Value,
+    schema_version: &str,
+    config: &ExtractionConfig,
+    issues: &mut ExtractionIssues,
+    ctx: &LogContext,
+) -> HashMap<String, String> {
+    let cache = get_schema_cache();
+
+    if !cache.is_loaded() {
+        log::warn!("{} EXTRACT_SKIP reason=schema_cache_not_loaded", ctx);
+        return HashMap::new();
+    }
+
+    extract_with_cache(trace, schema_version, &cache, config, issues, ctx)
+}
+
+fn extract_with_cache(
+    trace: &Value,
+    schema_version: &str,
+    cache: &SchemaCache,
+    config: &ExtractionConfig,
+    issues: &mut ExtractionIssues,
     ctx: &LogContext,
 ) -> HashMap<String, String> {
     let mut metadata = HashMap::new();
+    let mut control_chars_cleaned = 0;
+    let mut enum_violations: Vec<String> = Vec::new();
+    // db_column -> raw value that could not be coerced to its data type
+    let mut extraction_errors: BTreeMap<String, String> = BTreeMap::new();
 
     log::debug!(
         "{} EXTRACT_START schema_version={}",
@@ -40,25 +149,17 @@ pub fn extract_trace_metadata(
         .cloned()
         .unwrap_or_default();
 
-    // Get schema cache
-    let cache = get_schema_cache();
-
-    if !cache.is_loaded() {
-        log::warn!("{} EXTRACT_SKIP reason=schema_cache_not_loaded", ctx);
-        return metadata;
-    }
-
     // Extract trace-level fields
     if let Some(trace_id) = trace.get("trace_id").and_then(|v| v.as_str()) {
         metadata.insert("trace_id".to_string(), trace_id.to_string());
     }
 
     // Process each component
+    let mut event_types = Vec::with_capacity(components.len());
     for component in &components {
-        let event_type = component
-            .get("event_type")
-            .and_then(|e| e.as_str())
-            .unwrap_or("unknown");
+        let event_type =
+            component_event_type(component, &config.event_type_keys).unwrap_or("unknown");
+        event_types.push(event_type);
 
         let data = component.get("data").unwrap_or(component);
 
@@ -99,11 +200,66 @@ pub fn extract_trace_metadata(
 
         // Extract each field
         for rule in field_rules {
-            let value = resolve_json_path(data, &rule.json_path);
+            let value = select_json_path(data, &rule.json_path);
 
-            match value {
+            match value.as_deref() {
+                Some(v)
+                    if rule.required
+                        && config.required_fields != RequiredFieldCheck::Present
+                        && is_empty_value(v) =>
+                {
+                    log::warn!(
+                        "{} FIELD_REQUIRED_EMPTY field={} event_type={} col={}",
+                        ctx,
+                        rule.field_name,
+                        event_type,
+                        rule.db_column
+                    );
+                    issues.record("missing_required", &rule.db_column);
+                }
                 Some(v) => {
-                    let extracted = convert_value(v, &rule.data_type);
+                    if let Some(issue) = type_issue(v, &rule.data_type) {
+                        log::warn!(
+                            "{} {} field={} col={} data_type={} value={:?}",
+                            ctx,
+                            issue.log_tag(),
+                            rule.field_name,
+                            rule.db_column,
+                            rule.data_type,
+                            v
+                        );
+                        issues.record(issue.as_str(), &rule.db_column);
+                    }
+                    let Some(extracted) = convert_value(
+                        v,
+                        &rule.data_type,
+                        config.control_chars,
+                        &mut control_chars_cleaned,
+                    ) else {
+                        record_coercion_failure(&mut extraction_errors, rule, v, ctx);
+                        continue;
+                    };
+                    let extracted = match check_allowed_value(rule, &extracted) {
+                        Ok(()) => extracted,
+                        Err(()) => {
+                            log::warn!(
+                                "{} FIELD_ENUM_VIOLATION col={} value={:?} action={:?}",
+                                ctx,
+                                rule.db_column,
+                                extracted,
+                                config.enum_violation
+                            );
+                            issues.record("enum_violation", &rule.db_column);
+                            match config.enum_violation {
+                                EnumViolationAction::Keep => extracted,
+                                EnumViolationAction::Unknown => ENUM_UNKNOWN_SENTINEL.to_string(),
+                                EnumViolationAction::Flag => {
+                                    enum_violations.push(rule.db_column.clone());
+                                    extracted
+                                }
+                            }
+                        }
+                    };
                     metadata.insert(rule.db_column.clone(), extracted.clone());
 
                     log::debug!(
@@ -115,6 +271,30 @@ pub fn extract_trace_metadata(
                         extracted
                     );
                 }
+                None if rule.default_value.is_some() => {
+                    let default = rule.default_value.as_deref().unwrap_or_default();
+                    // Defaults are written as JSON literals where they parse
+                    // (`false`, `0.5`), else taken as plain strings
+                    let default_value = serde_json::from_str(default)
+                        .unwrap_or_else(|_| Value::String(default.to_string()));
+                    let Some(extracted) = convert_value(
+                        &default_value,
+                        &rule.data_type,
+                        config.control_chars,
+                        &mut control_chars_cleaned,
+                    ) else {
+                        record_coercion_failure(&mut extraction_errors, rule, &default_value, ctx);
+                        continue;
+                    };
+                    log::debug!(
+                        "{} FIELD_DEFAULTED field={} db_col={} value={:?}",
+                        ctx,
+                        rule.field_name,
+                        rule.db_column,
+                        extracted
+                    );
+                    metadata.insert(rule.db_column.clone(), extracted);
+                }
                 None => {
                     if rule.required {
                         log::warn!(
@@ -123,6 +303,7 @@ pub fn extract_trace_metadata(
                             rule.field_name,
                             event_type
                         );
+                        issues.record("missing_required", &rule.db_column);
                     }
                 }
             }
@@ -132,6 +313,56 @@ pub fn extract_trace_metadata(
         store_full_component(&mut metadata, event_type, data);
     }
 
+    if control_chars_cleaned > 0 {
+        log::warn!(
+            "{} CONTROL_CHARS_CLEANED count={} mode={:?}",
+            ctx,
+            control_chars_cleaned,
+            config.control_chars
+        );
+        metadata.insert(
+            "control_chars_cleaned".to_string(),
+            control_chars_cleaned.to_string(),
+        );
+    }
+
+    normalize_selected_action(&mut metadata, &get_action_taxonomy(), ctx);
+
+    if let Some(bucket) = derive_risk_bucket(&metadata, &config.risk_bucket) {
+        log::debug!("{} RISK_BUCKET bucket={}", ctx, bucket);
+        metadata.insert("risk_bucket".to_string(), bucket.to_string());
+    }
+
+    if let Some(path) = derive_decision_path(&event_types, &metadata) {
+        log::debug!("{} DECISION_PATH path={}", ctx, path);
+        metadata.insert("decision_path".to_string(), path);
+    }
+
+    if let Some(providers) = derive_providers(&metadata, &config.provider_patterns) {
+        log::debug!("{} PROVIDERS providers={:?}", ctx, providers);
+        metadata.insert(
+            "providers".to_string(),
+            serde_json::to_string(&providers).unwrap_or_default(),
+        );
+    }
+
+    if !enum_violations.is_empty() {
+        enum_violations.sort();
+        enum_violations.dedup();
+        metadata.insert("enum_violations".to_string(), enum_violations.join(","));
+    }
+
+    if !extraction_errors.is_empty() {
+        metadata.insert(
+            "extraction_error_count".to_string(),
+            extraction_errors.len().to_string(),
+        );
+        metadata.insert(
+            "extraction_errors".to_string(),
+            serde_json::to_string(&extraction_errors).unwrap_or_default(),
+        );
+    }
+
     log::debug!(
         "{} EXTRACT_COMPLETE fields_populated={}",
         ctx,
@@ -141,24 +372,203 @@ pub fn extract_trace_metadata(
     metadata
 }
 
-/// Convert a JSON value to a string based on target data type.
-fn convert_value(value: &Value, data_type: &str) -> String {
+/// Longest raw value kept per coercion failure.
+const MAX_COERCION_ERROR_VALUE_CHARS: usize = 64;
+
+/// Log a value that could not be coerced to its rule's data type and
+/// note it in `errors` (db_column -> raw value).
+fn record_coercion_failure(
+    errors: &mut BTreeMap<String, String>,
+    rule: &FieldExtractionRule,
+    value: &Value,
+    ctx: &LogContext,
+) {
+    let raw = value.to_string();
+    let raw = safe_truncate(&raw, MAX_COERCION_ERROR_VALUE_CHARS);
+    log::warn!(
+        "{} FIELD_COERCION_FAILED field={} col={} data_type={} value={}",
+        ctx,
+        rule.field_name,
+        rule.db_column,
+        rule.data_type,
+        raw
+    );
+    errors.insert(rule.db_column.clone(), raw.to_string());
+}
+
+/// Cap the number of metadata entries, keeping known `accord_traces`
+/// columns first and then other keys in alphabetical order, so the same
+/// input always keeps the same entries.
+///
+/// Returns the number of entries dropped. A cap of 0 means unlimited.
+pub fn cap_metadata_entries(
+    metadata: &mut HashMap<String, String>,
+    cap: usize,
+    ctx: &LogContext,
+) -> usize {
+    if cap == 0 || metadata.len() <= cap {
+        return 0;
+    }
+
+    let known: HashSet<&str> = get_trace_columns().into_iter().map(|(name, _)| name).collect();
+    let mut keys: Vec<String> = metadata.keys().cloned().collect();
+    keys.sort_by(|a, b| {
+        (!known.contains(a.as_str()), a).cmp(&(!known.contains(b.as_str()), b))
+    });
+
+    let dropped_keys = keys.split_off(cap);
+    for key in &dropped_keys {
+        metadata.remove(key);
+    }
+
+    log::warn!(
+        "{} METADATA_CAP_EXCEEDED cap={} dropped={} first_dropped={:?}",
+        ctx,
+        cap,
+        dropped_keys.len(),
+        dropped_keys.first()
+    );
+    dropped_keys.len()
+}
+
+/// Value stored for an enum-validated column when the extracted value is
+/// outside its allowed set (with `EnumViolationAction::Unknown`).
+pub const ENUM_UNKNOWN_SENTINEL: &str = "unknown";
+
+/// A value that carries nothing: `null`, `""`, `[]` or `{}`.
+fn is_empty_value(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        Value::Array(arr) => arr.is_empty(),
+        Value::Object(obj) => obj.is_empty(),
+        _ => false,
+    }
+}
+
+/// Check an extracted value against the rule's allowed-values set.
+///
+/// Empty values (missing or null source) are not violations.
+fn check_allowed_value(rule: &FieldExtractionRule, extracted: &str) -> Result<(), ()> {
+    match rule.allowed_values {
+        Some(ref allowed) if !extracted.is_empty() && !allowed.contains(extracted) => Err(()),
+        _ => Ok(()),
+    }
+}
+
+/// Count and affected columns of one kind of extraction issue.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct IssueSummary {
+    pub count: usize,
+    pub columns: BTreeSet<String>,
+}
+
+/// Extraction issues aggregated by issue type (`type_mismatch`,
+/// `nonfinite`, `missing_required`, `enum_violation`).
+///
+/// Serializes to `{issue_type: {count, columns}}`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct ExtractionIssues(BTreeMap<String, IssueSummary>);
+
+impl ExtractionIssues {
+    pub fn record(&mut self, issue: &str, column: &str) {
+        let summary = self.0.entry(issue.to_string()).or_default();
+        summary.count += 1;
+        summary.columns.insert(column.to_string());
+    }
+
+    pub fn merge(&mut self, other: &ExtractionIssues) {
+        for (issue, theirs) in &other.0 {
+            let ours = self.0.entry(issue.clone()).or_default();
+            ours.count += theirs.count;
+            ours.columns.extend(theirs.columns.iter().cloned());
+        }
+    }
+
+    pub fn get(&self, issue: &str) -> Option<&IssueSummary> {
+        self.0.get(issue)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A non-null value that does not fit its rule's data type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TypeIssue {
+    Mismatch,
+    NonFinite,
+}
+
+impl TypeIssue {
+    fn as_str(self) -> &'static str {
+        match self {
+            TypeIssue::Mismatch => "type_mismatch",
+            TypeIssue::NonFinite => "nonfinite",
+        }
+    }
+
+    fn log_tag(self) -> &'static str {
+        match self {
+            TypeIssue::Mismatch => "FIELD_TYPE_MISMATCH",
+            TypeIssue::NonFinite => "FIELD_NONFINITE",
+        }
+    }
+}
+
+/// Check a value against a numeric or boolean data type.
+///
+/// Null is treated as absent, not as a mismatch.
+fn type_issue(value: &Value, data_type: &str) -> Option<TypeIssue> {
+    if value.is_null() {
+        return None;
+    }
     match data_type {
-        "float" => value_to_float(value)
-            .map(|f| f.to_string())
-            .unwrap_or_default(),
-        "int" => value_to_int(value)
-            .map(|i| i.to_string())
-            .unwrap_or_default(),
-        "boolean" => value_to_bool(value)
-            .map(|b| b.to_string())
-            .unwrap_or_default(),
-        "json" => value.to_string(),
-        "timestamp" => value_to_string(value),
-        _ => value_to_string(value), // string and default
+        "float" => match value_to_float(value) {
+            None => Some(TypeIssue::Mismatch),
+            Some(f) if !f.is_finite() => Some(TypeIssue::NonFinite),
+            Some(_) => None,
+        },
+        "int" => value_to_int(value).is_none().then_some(TypeIssue::Mismatch),
+        "boolean" => value_to_bool(value).is_none().then_some(TypeIssue::Mismatch),
+        _ => None,
     }
 }
 
+/// Convert a JSON value to a string based on target data type.
+///
+/// String-typed values have C0 control characters handled per
+/// `control_chars`; the number cleaned is added to `cleaned`.
+///
+/// Returns `None` when a non-null value cannot be coerced to a float, int
+/// or boolean `data_type`; null converts to an empty string.
+fn convert_value(
+    value: &Value,
+    data_type: &str,
+    control_chars: ControlCharMode,
+    cleaned: &mut usize,
+) -> Option<String> {
+    let mut to_clean_string = |v: &Value| {
+        let (s, n) = value_to_string_cleaned(v, control_chars);
+        *cleaned += n;
+        s
+    };
+
+    let coerced = match data_type {
+        "float" => value_to_float(value).map(|f| f.to_string()),
+        "int" => value_to_int(value).map(|i| i.to_string()),
+        "boolean" => value_to_bool(value).map(|b| b.to_string()),
+        "json" => return Some(value.to_string()),
+        "timestamp" => {
+            return Some(object_timestamp_to_rfc3339(value).unwrap_or_else(|| to_clean_string(value)))
+        }
+        _ => return Some(to_clean_string(value)), // string and default
+    };
+    coerced.or_else(|| value.is_null().then(String::new))
+}
+
 /// Extract observation weight fields - numeric metrics about observation complexity.
 /// These are privacy-safe as they contain no text content.
 fn extract_observation_weight(
@@ -228,6 +638,118 @@ fn extract_observation_weight(
     }
 }
 
+/// Derive a coarse `low`/`medium`/`high` risk bucket from the extracted
+/// IDMA columns. Returns None when none of them were extracted.
+fn derive_risk_bucket(
+    metadata: &HashMap<String, String>,
+    config: &RiskBucketConfig,
+) -> Option<&'static str> {
+    let number = |col: &str| metadata.get(col).and_then(|v| v.parse::<f64>().ok());
+    let k_eff = number("idma_k_eff");
+    let correlation_risk = number("idma_correlation_risk");
+    let fragile = metadata
+        .get("idma_fragility_flag")
+        .and_then(|v| value_to_bool(&Value::String(v.clone())));
+
+    if k_eff.is_none() && correlation_risk.is_none() && fragile.is_none() {
+        return None;
+    }
+
+    let bucket = if fragile == Some(true)
+        || k_eff.is_some_and(|k| k < config.high_k_eff_below)
+        || correlation_risk.is_some_and(|r| r >= config.high_correlation_risk)
+    {
+        "high"
+    } else if k_eff.is_some_and(|k| k < config.medium_k_eff_below)
+        || correlation_risk.is_some_and(|r| r >= config.medium_correlation_risk)
+    {
+        "medium"
+    } else {
+        "low"
+    };
+    Some(bucket)
+}
+
+/// Derive a compact summary of the trace's steps, e.g.
+/// `THOUGHT→DMA→ASPDMA(speak)→CONSCIENCE(pass)→ACTION(success)`.
+///
+/// Steps follow component order, minus the snapshot context. ASPDMA,
+/// CONSCIENCE and ACTION carry the extracted `selected_action`,
+/// `conscience_passed` and `action_success` when present. Returns None
+/// when the trace has no steps.
+fn derive_decision_path(event_types: &[&str], metadata: &HashMap<String, String>) -> Option<String> {
+    let flag = |col: &str, yes: &'static str, no: &'static str| {
+        metadata
+            .get(col)
+            .and_then(|v| value_to_bool(&Value::String(v.clone())))
+            .map(|b| if b { yes } else { no }.to_string())
+    };
+
+    let steps: Vec<String> = event_types
+        .iter()
+        .filter(|&&event_type| event_type != "SNAPSHOT_AND_CONTEXT")
+        .map(|&event_type| {
+            let step = ["_RESULTS", "_RESULT", "_START"]
+                .iter()
+                .find_map(|suffix| event_type.strip_suffix(suffix))
+                .unwrap_or(event_type);
+            let detail = match step {
+                "ASPDMA" => metadata
+                    .get("selected_action_normalized")
+                    .or_else(|| metadata.get("selected_action"))
+                    .filter(|action| !action.is_empty())
+                    .cloned(),
+                "CONSCIENCE" => flag("conscience_passed", "pass", "fail"),
+                "ACTION" => flag("action_success", "success", "failure"),
+                _ => None,
+            };
+            match detail {
+                Some(detail) => format!("{}({})", step, detail),
+                None => step.to_string(),
+            }
+        })
+        .collect();
+
+    (!steps.is_empty()).then(|| steps.join("→"))
+}
+
+/// Provider for a model name or API base: the first matching pattern.
+fn classify_provider<'a>(name: &str, patterns: &'a [ProviderPattern]) -> Option<&'a str> {
+    let name = name.to_lowercase();
+    patterns
+        .iter()
+        .find(|p| name.contains(&p.pattern.to_lowercase()))
+        .map(|p| p.provider.as_str())
+}
+
+/// Derive the sorted, distinct providers behind the extracted
+/// `models_used` and `api_bases_used` arrays.
+///
+/// Models matching no pattern count as `unknown`; unmatched API bases are
+/// ignored (self-hosted endpoints say nothing about the model's origin).
+/// Returns None when neither column was extracted.
+fn derive_providers(
+    metadata: &HashMap<String, String>,
+    patterns: &[ProviderPattern],
+) -> Option<Vec<String>> {
+    let models = metadata.get("models_used").map(|v| parse_models_used(v));
+    let api_bases = metadata.get("api_bases_used").map(|v| parse_models_used(v));
+    if models.is_none() && api_bases.is_none() {
+        return None;
+    }
+
+    let mut providers = BTreeSet::new();
+    for model in models.iter().flatten() {
+        providers.insert(classify_provider(model, patterns).unwrap_or("unknown"));
+    }
+    for api_base in api_bases.iter().flatten() {
+        if let Some(provider) = classify_provider(api_base, patterns) {
+            providers.insert(provider);
+        }
+    }
+    Some(providers.into_iter().map(|p| p.to_string()).collect())
+}
+
 /// Store full component data for certain event types.
 fn store_full_component(metadata: &mut HashMap<String, String>, event_type: &str, data: &Value) {
     let key = match event_type {
@@ -307,10 +829,422 @@ mod tests {
 
     #[test]
     fn test_convert_value() {
-        assert_eq!(convert_value(&json!(1.5), "float"), "1.5");
-        assert_eq!(convert_value(&json!(42), "int"), "42");
-        assert_eq!(convert_value(&json!(true), "boolean"), "true");
-        assert_eq!(convert_value(&json!("test"), "string"), "test");
+        let mut cleaned = 0;
+        let mode = ControlCharMode::Keep;
+        assert_eq!(convert_value(&json!(1.5), "float", mode, &mut cleaned).unwrap(), "1.5");
+        assert_eq!(convert_value(&json!(42), "int", mode, &mut cleaned).unwrap(), "42");
+        assert_eq!(convert_value(&json!(true), "boolean", mode, &mut cleaned).unwrap(), "true");
+        assert_eq!(convert_value(&json!("test"), "string", mode, &mut cleaned).unwrap(), "test");
+        assert_eq!(convert_value(&json!(null), "float", mode, &mut cleaned).unwrap(), "");
+        assert!(convert_value(&json!("fast"), "float", mode, &mut cleaned).is_none());
+        assert_eq!(cleaned, 0);
+    }
+
+    #[test]
+    fn test_type_issue() {
+        assert_eq!(type_issue(&json!("fast"), "float"), Some(TypeIssue::Mismatch));
+        assert_eq!(type_issue(&json!("NaN"), "float"), Some(TypeIssue::NonFinite));
+        assert_eq!(type_issue(&json!(1.5), "int"), Some(TypeIssue::Mismatch));
+        assert_eq!(type_issue(&json!("maybe"), "boolean"), Some(TypeIssue::Mismatch));
+        assert_eq!(type_issue(&json!(null), "float"), None);
+        assert_eq!(type_issue(&json!("3"), "int"), None);
+        assert_eq!(type_issue(&json!("anything"), "string"), None);
+    }
+
+    #[test]
+    fn test_extraction_issues_aggregate_over_batch() {
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows(
+            vec![(
+                "1.9.3".to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["IDMA_RESULT".to_string()],
+            )],
+            vec![
+                (
+                    "1.9.3".to_string(),
+                    "IDMA_RESULT".to_string(),
+                    "k_eff".to_string(),
+                    "k_eff".to_string(),
+                    "float".to_string(),
+                    false,
+                    "idma_k_eff".to_string(),
+                    None,
+                ),
+                (
+                    "1.9.3".to_string(),
+                    "IDMA_RESULT".to_string(),
+                    "phase".to_string(),
+                    "phase".to_string(),
+                    "string".to_string(),
+                    true,
+                    "idma_phase".to_string(),
+                    None,
+                ),
+            ],
+        );
+
+        let config = ExtractionConfig::default();
+        let ctx = LogContext::new("test-batch");
+        let mut batch_issues = ExtractionIssues::default();
+        for i in 0..3 {
+            let trace = json!({
+                "trace_id": format!("t-{}", i),
+                "components": [{"event_type": "IDMA_RESULT", "data": {"k_eff": "high"}}]
+            });
+            let mut issues = ExtractionIssues::default();
+            let metadata = extract_with_cache(&trace, "1.9.3", &cache, &config, &mut issues, &ctx);
+            assert!(!metadata.contains_key("idma_k_eff"));
+            batch_issues.merge(&issues);
+        }
+
+        let mismatch = batch_issues.get("type_mismatch").unwrap();
+        assert_eq!(mismatch.count, 3);
+        assert_eq!(mismatch.columns, BTreeSet::from(["idma_k_eff".to_string()]));
+        assert_eq!(batch_issues.get("missing_required").unwrap().count, 3);
+        assert!(batch_issues.get("nonfinite").is_none());
+        assert_eq!(
+            serde_json::to_value(&batch_issues).unwrap()["type_mismatch"],
+            json!({"count": 3, "columns": ["idma_k_eff"]})
+        );
+    }
+
+    #[test]
+    fn test_required_field_empty_counts_as_missing() {
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows(
+            vec![(
+                "1.9.3".to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["IDMA_RESULT".to_string()],
+            )],
+            vec![
+                (
+                    "1.9.3".to_string(),
+                    "IDMA_RESULT".to_string(),
+                    "phase".to_string(),
+                    "phase".to_string(),
+                    "string".to_string(),
+                    true,
+                    "idma_phase".to_string(),
+                    None,
+                ),
+                (
+                    "1.9.3".to_string(),
+                    "IDMA_RESULT".to_string(),
+                    "sources".to_string(),
+                    "sources".to_string(),
+                    "json".to_string(),
+                    true,
+                    "idma_sources".to_string(),
+                    None,
+                ),
+            ],
+        );
+        let trace = json!({
+            "components": [{"event_type": "IDMA_RESULT", "data": {"phase": "", "sources": []}}]
+        });
+        let ctx = LogContext::new("test-batch");
+
+        let mut config = ExtractionConfig::default();
+        let mut issues = ExtractionIssues::default();
+        let metadata = extract_with_cache(&trace, "1.9.3", &cache, &config, &mut issues, &ctx);
+        assert_eq!(metadata["idma_phase"], "");
+        assert!(issues.get("missing_required").is_none());
+
+        config.required_fields = RequiredFieldCheck::NonEmpty;
+        let mut issues = ExtractionIssues::default();
+        let metadata = extract_with_cache(&trace, "1.9.3", &cache, &config, &mut issues, &ctx);
+        assert!(!metadata.contains_key("idma_phase"));
+        assert!(!metadata.contains_key("idma_sources"));
+        let missing = issues.get("missing_required").unwrap();
+        assert_eq!(missing.count, 2);
+        assert_eq!(
+            missing.columns,
+            BTreeSet::from(["idma_phase".to_string(), "idma_sources".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_absent_field_uses_default() {
+        let field = |name: &str, data_type: &str, required: bool, default: Option<&str>| {
+            (
+                "1.9.3".to_string(),
+                "CONSCIENCE_RESULT".to_string(),
+                name.to_string(),
+                name.to_string(),
+                data_type.to_string(),
+                required,
+                format!("conscience_{}", name),
+                default.map(|d| d.to_string()),
+            )
+        };
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows(
+            vec![(
+                "1.9.3".to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["CONSCIENCE_RESULT".to_string()],
+            )],
+            vec![
+                field("override", "boolean", true, Some("false")),
+                field("passed", "boolean", false, Some("true")),
+                field("reason", "string", true, None),
+            ],
+        );
+        let trace = json!({
+            "components": [{"event_type": "CONSCIENCE_RESULT", "data": {"passed": false}}]
+        });
+
+        let mut issues = ExtractionIssues::default();
+        let metadata = extract_with_cache(
+            &trace,
+            "1.9.3",
+            &cache,
+            &ExtractionConfig::default(),
+            &mut issues,
+            &LogContext::new("test-batch"),
+        );
+        assert_eq!(metadata["conscience_override"], "false");
+        // Present values win over the default
+        assert_eq!(metadata["conscience_passed"], "false");
+        assert!(!metadata.contains_key("conscience_reason"));
+        let missing = issues.get("missing_required").unwrap();
+        assert_eq!(missing.columns, BTreeSet::from(["conscience_reason".to_string()]));
+    }
+
+    #[test]
+    fn test_decision_path_for_full_trace() {
+        let field = |event_type: &str, path: &str, data_type: &str, column: &str| {
+            (
+                "1.9.3".to_string(),
+                event_type.to_string(),
+                column.to_string(),
+                path.to_string(),
+                data_type.to_string(),
+                false,
+                column.to_string(),
+                None,
+            )
+        };
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows(
+            vec![(
+                "1.9.3".to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["THOUGHT_START".to_string()],
+            )],
+            vec![
+                field("ASPDMA_RESULT", "selected_action", "string", "selected_action"),
+                field("CONSCIENCE_RESULT", "conscience_passed", "boolean", "conscience_passed"),
+                field("ACTION_RESULT", "success", "boolean", "action_success"),
+            ],
+        );
+        let trace = json!({
+            "components": [
+                {"event_type": "THOUGHT_START", "data": {}},
+                {"event_type": "SNAPSHOT_AND_CONTEXT", "data": {}},
+                {"event_type": "DMA_RESULTS", "data": {}},
+                {"event_type": "ASPDMA_RESULT", "data": {"selected_action": "speak"}},
+                {"event_type": "CONSCIENCE_RESULT", "data": {"conscience_passed": true}},
+                {"event_type": "ACTION_RESULT", "data": {"success": true}}
+            ]
+        });
+
+        let extract = |trace: &Value| {
+            extract_with_cache(
+                trace,
+                "1.9.3",
+                &cache,
+                &ExtractionConfig::default(),
+                &mut ExtractionIssues::default(),
+                &LogContext::new("test-batch"),
+            )
+        };
+        let metadata = extract(&trace);
+        assert_eq!(
+            metadata["decision_path"],
+            "THOUGHT→DMA→ASPDMA(speak)→CONSCIENCE(pass)→ACTION(success)"
+        );
+
+        // Missing components and fields leave their steps out or bare
+        let partial = json!({
+            "components": [
+                {"event_type": "THOUGHT_START", "data": {}},
+                {"event_type": "CONSCIENCE_RESULT", "data": {}}
+            ]
+        });
+        assert_eq!(extract(&partial)["decision_path"], "THOUGHT→CONSCIENCE");
+        assert!(!extract(&json!({"components": []})).contains_key("decision_path"));
+    }
+
+    #[test]
+    fn test_string_in_float_column_reported() {
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows(
+            vec![(
+                "1.9.3".to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["DMA_RESULTS".to_string()],
+            )],
+            vec![(
+                "1.9.3".to_string(),
+                "DMA_RESULTS".to_string(),
+                "csdma_plausibility".to_string(),
+                "csdma.plausibility_score".to_string(),
+                "float".to_string(),
+                false,
+                "csdma_plausibility_score".to_string(),
+                None,
+            )],
+        );
+        let trace = json!({
+            "components": [{"event_type": "DMA_RESULTS", "data": {"csdma": {"plausibility_score": "high"}}}]
+        });
+
+        let mut issues = ExtractionIssues::default();
+        let metadata = extract_with_cache(
+            &trace,
+            "1.9.3",
+            &cache,
+            &ExtractionConfig::default(),
+            &mut issues,
+            &LogContext::new("test-batch"),
+        );
+        assert!(!metadata.contains_key("csdma_plausibility_score"));
+        assert_eq!(metadata["extraction_error_count"], "1");
+        let errors: BTreeMap<String, String> = serde_json::from_str(&metadata["extraction_errors"]).unwrap();
+        assert_eq!(errors["csdma_plausibility_score"], r#""high""#);
+        assert!(issues.get("type_mismatch").is_some());
+    }
+
+    #[test]
+    fn test_convert_timestamp_forms() {
+        let mut cleaned = 0;
+        let mode = ControlCharMode::Keep;
+        let object_form = json!({"seconds": 1700000000, "nanos": 500000000});
+        let string_form = json!("2023-11-14T22:13:20.500Z");
+
+        assert_eq!(
+            convert_value(&object_form, "timestamp", mode, &mut cleaned).as_deref(),
+            Some("2023-11-14T22:13:20.500Z")
+        );
+        assert_eq!(
+            convert_value(&string_form, "timestamp", mode, &mut cleaned).as_deref(),
+            Some("2023-11-14T22:13:20.500Z")
+        );
+    }
+
+    #[test]
+    fn test_derive_providers() {
+        let patterns = crate::config::default_provider_patterns();
+        let metadata = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+
+        let models = metadata(&[("models_used", r#"["claude-3", "gpt-4"]"#)]);
+        assert_eq!(derive_providers(&models, &patterns).unwrap(), ["anthropic", "openai"]);
+
+        let unknown = metadata(&[
+            ("models_used", r#"["in-house-7b"]"#),
+            ("api_bases_used", r#"["https://api.together.xyz/v1", "http://10.0.0.5:8000"]"#),
+        ]);
+        assert_eq!(derive_providers(&unknown, &patterns).unwrap(), ["together", "unknown"]);
+
+        assert!(derive_providers(&HashMap::new(), &patterns).is_none());
+    }
+
+    #[test]
+    fn test_derive_risk_bucket() {
+        let config = RiskBucketConfig::default();
+        let metadata = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+
+        let low = metadata(&[
+            ("idma_k_eff", "4.5"),
+            ("idma_correlation_risk", "0.1"),
+            ("idma_fragility_flag", "false"),
+        ]);
+        assert_eq!(derive_risk_bucket(&low, &config), Some("low"));
+
+        let medium = metadata(&[("idma_k_eff", "2.5"), ("idma_correlation_risk", "0.1")]);
+        assert_eq!(derive_risk_bucket(&medium, &config), Some("medium"));
+
+        let high = metadata(&[("idma_k_eff", "4.5"), ("idma_fragility_flag", "true")]);
+        assert_eq!(derive_risk_bucket(&high, &config), Some("high"));
+
+        let high = metadata(&[("idma_correlation_risk", "0.85")]);
+        assert_eq!(derive_risk_bucket(&high, &config), Some("high"));
+
+        let no_idma = metadata(&[("selected_action", "speak")]);
+        assert_eq!(derive_risk_bucket(&no_idma, &config), None);
+    }
+
+    #[test]
+    fn test_cap_metadata_entries_keeps_known_columns() {
+        let ctx = LogContext::new("test-batch");
+        let mut metadata: HashMap<String, String> = (0..10)
+            .map(|i| (format!("extra_{:02}", i), i.to_string()))
+            .collect();
+        metadata.insert("trace_id".to_string(), "t".to_string());
+        metadata.insert("selected_action".to_string(), "speak".to_string());
+
+        let dropped = cap_metadata_entries(&mut metadata, 5, &ctx);
+
+        assert_eq!(dropped, 7);
+        let mut kept: Vec<&str> = metadata.keys().map(|k| k.as_str()).collect();
+        kept.sort();
+        assert_eq!(
+            kept,
+            ["extra_00", "extra_01", "extra_02", "selected_action", "trace_id"]
+        );
+
+        // Under the cap, or unlimited: untouched
+        assert_eq!(cap_metadata_entries(&mut metadata, 5, &ctx), 0);
+        assert_eq!(cap_metadata_entries(&mut metadata, 0, &ctx), 0);
+    }
+
+    #[test]
+    fn test_check_allowed_value() {
+        let rule = FieldExtractionRule {
+            field_name: "selected_action".to_string(),
+            json_path: "selected_action".to_string(),
+            data_type: "string".to_string(),
+            required: false,
+            db_column: "selected_action".to_string(),
+            allowed_values: Some(["speak".to_string(), "ponder".to_string()].into()),
+            default_value: None,
+        };
+
+        assert!(check_allowed_value(&rule, "speak").is_ok());
+        assert!(check_allowed_value(&rule, "").is_ok());
+        assert!(check_allowed_value(&rule, "self_destruct").is_err());
+
+        let unrestricted = FieldExtractionRule {
+            allowed_values: None,
+            ..rule
+        };
+        assert!(check_allowed_value(&unrestricted, "self_destruct").is_ok());
+    }
+
+    #[test]
+    fn test_convert_value_cleans_nul_byte() {
+        let mut cleaned = 0;
+        let value = json!("agent\u{0}name");
+
+        let out = convert_value(&value, "string", ControlCharMode::Strip, &mut cleaned).unwrap();
+        assert_eq!(out, "agentname");
+        assert_eq!(cleaned, 1);
+
+        let out = convert_value(&value, "string", ControlCharMode::Keep, &mut cleaned).unwrap();
+        assert_eq!(out, "agent\u{0}name");
+        assert_eq!(cleaned, 1);
     }
 
     #[test]
@@ -362,4 +1296,33 @@ mod tests {
 
         assert_eq!(metadata.get("conscience_checks_count"), Some(&"4".to_string()));
     }
+
+    #[test]
+    fn test_normalize_selected_action() {
+        let taxonomy: HashMap<String, String> = [("speak", "speak"), ("action.speak", "speak")]
+            .into_iter()
+            .map(|(raw, canonical)| (taxonomy_key(raw), canonical.to_string()))
+            .collect();
+        let metadata = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        let ctx = LogContext::new("test-batch");
+
+        for variant in ["SPEAK", "speak", " action.speak "] {
+            let mut meta = metadata(&[("selected_action", variant)]);
+            normalize_selected_action(&mut meta, &taxonomy, &ctx);
+            assert_eq!(meta["selected_action"], variant);
+            assert_eq!(meta["selected_action_normalized"], "speak");
+            assert!(!meta.contains_key("selected_action_unmapped"));
+        }
+
+        let mut meta = metadata(&[("selected_action", "dance")]);
+        normalize_selected_action(&mut meta, &taxonomy, &ctx);
+        assert_eq!(meta["selected_action_normalized"], "dance");
+        assert_eq!(meta["selected_action_unmapped"], "true");
+
+        let mut meta = metadata(&[("selected_action", "dance")]);
+        normalize_selected_action(&mut meta, &HashMap::new(), &ctx);
+        assert!(!meta.contains_key("selected_action_normalized"));
+    }
 }
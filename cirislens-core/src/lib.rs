@@ -10,6 +10,7 @@
 //! ## Architecture
 //!
 //! The crate is organized into modules:
+//! - `config` - Runtime pipeline configuration (policies, limits)
 //! - `pipeline` - Main ingestion orchestrator
 //! - `validation` - Schema detection and validation (DB-driven)
 //! - `security` - Sanitization, PII scrubbing, signature verification
@@ -18,9 +19,12 @@
 //! - `storage` - SQL query builders and models
 //! - `logging` - Structured logging with trace context
 
+use std::collections::HashMap;
+
 use pyo3::prelude::*;
 use pyo3::types::{PyDict, PyList};
 
+pub mod config;
 pub mod extraction;
 pub mod logging;
 pub mod pipeline;
@@ -31,14 +35,81 @@ pub mod storage;
 pub mod validation;
 
 use pipeline::context::BatchContext;
-use pipeline::ingestion::process_batch;
+use pipeline::ingestion::{process_batch, TraceResult};
+
+/// Convert a JSON value into the equivalent Python object.
+fn json_to_py(py: Python<'_>, value: &serde_json::Value) -> PyResult<Py<PyAny>> {
+    use serde_json::Value;
 
-/// Initialize the module-level logger
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.into_py(py),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => u.into_py(py),
+            (None, Some(i)) => i.into_py(py),
+            _ => n.as_f64().unwrap_or(f64::NAN).into_py(py),
+        },
+        Value::String(s) => s.into_py(py),
+        Value::Array(arr) => {
+            let list = PyList::empty(py);
+            for item in arr {
+                list.append(json_to_py(py, item)?)?;
+            }
+            list.into()
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new(py);
+            for (key, item) in map {
+                dict.set_item(key, json_to_py(py, item)?)?;
+            }
+            dict.into()
+        }
+    })
+}
+
+/// Initialize the module-level logger.
+///
+/// env_logger passes every level through; the effective level is `log`'s
+/// max level, taken from `CIRISLENS_LOG_LEVEL` on first init and changed
+/// later via `set_log_level`.
 fn init_logger() {
-    let _ = env_logger::builder()
-        .filter_level(log::LevelFilter::Info)
-        .format_timestamp_millis()
+    let installed = env_logger::builder()
+        .filter_level(log::LevelFilter::Trace)
+        .format(|buf, record| logging::write_record(buf, record))
         .try_init();
+    if installed.is_ok() {
+        log::set_max_level(logging::level::initial_log_level());
+    }
+}
+
+/// Switch log output between text lines and one JSON object per record.
+///
+/// # Arguments
+/// * `format` - "text" or "json"
+///
+/// # Raises
+/// - `ValueError` for an unknown format
+#[pyfunction]
+fn set_log_format(format: &str) -> PyResult<()> {
+    init_logger();
+    let format = logging::set_log_format(format).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    log::info!("LOG_FORMAT_CHANGED format={:?}", format);
+    Ok(())
+}
+
+/// Change the log level at runtime.
+///
+/// # Arguments
+/// * `level` - One of trace, debug, info, warn or error
+///
+/// # Raises
+/// - `ValueError` for an unknown level
+#[pyfunction]
+fn set_log_level(level: &str) -> PyResult<()> {
+    init_logger();
+    let filter = logging::level::set_log_level(level).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    log::info!("LOG_LEVEL_CHANGED level={}", filter);
+    Ok(())
 }
 
 /// Process a batch of traces.
@@ -57,11 +128,30 @@ fn init_logger() {
 /// * `consent_timestamp` - When user consented to telemetry
 /// * `trace_level` - "generic", "detailed", or "full_traces"
 /// * `correlation_metadata` - Optional correlation data
+/// * `extraction_enabled` - When false (throughput mode), skip field
+///   extraction; signature verification and routing still run. Routing
+///   then sees only the signature fields, so mock traces (detected via
+///   the extracted `models_used`) route to production — run mock
+///   detection in the later extraction job.
+/// * `fail_fast` - Stop at the first rejected trace and mark the result
+///   `aborted`, for all-or-nothing callers that roll back on abort
+/// * `previous_batch_hash` - With `batch.hash_chain` set, the previous
+///   result's `batch_hash` (None starts a new chain)
+/// * `validate_only` - Dry run for "will this batch be accepted?" checks:
+///   schema, signature and sanitizer checks and routing run as usual, but
+///   field extraction is skipped (unless `extraction.required_fields` is
+///   `reject`) and every `extracted_metadata` is empty
 ///
 /// # Returns
-/// BatchResult with routing decisions and extracted metadata for each trace
+/// BatchResult with routing decisions and extracted metadata for each trace,
+/// plus `processing_overloaded` / `suggested_backoff_ms` for backpressure
+/// and `extraction_issues` (issue type -> `{count, columns}`) for the batch,
+/// and `metrics` (`total_us`, `signature_us`, `pii_scrub_us`,
+/// `extraction_us`) for where the batch's time went, and `batch_hash`
+/// (None unless `batch.hash_chain` is set)
 #[pyfunction]
-#[pyo3(signature = (events, batch_timestamp, consent_timestamp=None, trace_level="detailed".to_string(), correlation_metadata=None))]
+#[pyo3(signature = (events, batch_timestamp, consent_timestamp=None, trace_level="detailed".to_string(), correlation_metadata=None, extraction_enabled=true, fail_fast=false, previous_batch_hash=None, validate_only=false))]
+#[allow(clippy::too_many_arguments)]
 fn process_trace_batch(
     py: Python<'_>,
     events: Vec<String>,
@@ -69,24 +159,96 @@ fn process_trace_batch(
     consent_timestamp: Option<String>,
     trace_level: String,
     correlation_metadata: Option<String>,
+    extraction_enabled: bool,
+    fail_fast: bool,
+    previous_batch_hash: Option<String>,
+    validate_only: bool,
 ) -> PyResult<Py<PyAny>> {
     init_logger();
 
-    let ctx = BatchContext::new(
+    let mut ctx = BatchContext::new(
         &batch_timestamp,
         consent_timestamp.as_deref(),
         &trace_level,
         correlation_metadata.as_deref(),
     );
+    ctx.extraction_enabled = extraction_enabled;
+    ctx.fail_fast = fail_fast;
+    ctx.previous_batch_hash = previous_batch_hash;
+    ctx.validate_only = validate_only;
+
+    run_batch(py, &ctx, events)
+}
 
+/// Process a batch sent as a single compressed blob.
+///
+/// The blob is inflated to the JSON array of traces, then processed as
+/// by `process_trace_batch`.
+///
+/// # Arguments
+/// * `blob` - Compressed JSON array of traces
+/// * `encoding` - `Content-Encoding`: "gzip" or "deflate"
+/// * Remaining arguments as for `process_trace_batch`
+///
+/// # Raises
+/// - `ValueError` for an unsupported encoding, a corrupt blob, or a blob
+///   that inflates past `batch.max_batch_events` events (or
+///   `max_batch_events * MAX_TRACE_SIZE` bytes)
+#[pyfunction]
+#[pyo3(signature = (blob, encoding, batch_timestamp, consent_timestamp=None, trace_level="detailed".to_string(), correlation_metadata=None, extraction_enabled=true, fail_fast=false, previous_batch_hash=None, validate_only=false))]
+#[allow(clippy::too_many_arguments)]
+fn process_trace_batch_compressed(
+    py: Python<'_>,
+    blob: Vec<u8>,
+    encoding: String,
+    batch_timestamp: String,
+    consent_timestamp: Option<String>,
+    trace_level: String,
+    correlation_metadata: Option<String>,
+    extraction_enabled: bool,
+    fail_fast: bool,
+    previous_batch_hash: Option<String>,
+    validate_only: bool,
+) -> PyResult<Py<PyAny>> {
+    init_logger();
+
+    let max_batch_events = config::get_pipeline_config().batch.max_batch_events;
+    let events = pipeline::decompress::inflate_batch(&blob, &encoding, max_batch_events)
+        .map_err(pyo3::exceptions::PyValueError::new_err)?;
     log::info!(
-        "BATCH_RECEIVED batch_id={} traces={} level={}",
+        "BATCH_INFLATED encoding={} compressed_bytes={} traces={}",
+        encoding,
+        blob.len(),
+        events.len()
+    );
+
+    let mut ctx = BatchContext::new(
+        &batch_timestamp,
+        consent_timestamp.as_deref(),
+        &trace_level,
+        correlation_metadata.as_deref(),
+    );
+    ctx.extraction_enabled = extraction_enabled;
+    ctx.fail_fast = fail_fast;
+    ctx.previous_batch_hash = previous_batch_hash;
+    ctx.validate_only = validate_only;
+
+    run_batch(py, &ctx, events)
+}
+
+/// Run the pipeline over a batch and convert the result to a Python dict.
+fn run_batch(py: Python<'_>, ctx: &BatchContext, events: Vec<String>) -> PyResult<Py<PyAny>> {
+    log::info!(
+        "BATCH_RECEIVED batch_id={} traces={} level={} extraction={} fail_fast={} validate_only={}",
         ctx.batch_id,
         events.len(),
-        trace_level
+        ctx.trace_level,
+        ctx.extraction_enabled,
+        ctx.fail_fast,
+        ctx.validate_only
     );
 
-    let result = process_batch(&ctx, events);
+    let result = process_batch(ctx, events);
 
     // Convert to Python dict
     let py_result = PyDict::new(py);
@@ -94,32 +256,157 @@ fn process_trace_batch(
     py_result.set_item("received_count", result.received_count)?;
     py_result.set_item("accepted_count", result.accepted_count)?;
     py_result.set_item("rejected_count", result.rejected_count)?;
+    py_result.set_item("duplicate_count", result.duplicate_count)?;
+    py_result.set_item("bytes_received", result.bytes_received)?;
+    py_result.set_item("bytes_stored", result.bytes_stored)?;
+    py_result.set_item("processing_overloaded", result.processing_overloaded)?;
+    py_result.set_item("suggested_backoff_ms", result.suggested_backoff_ms)?;
+    py_result.set_item("aborted", result.aborted)?;
+    let issues = serde_json::to_value(&result.extraction_issues)
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+    py_result.set_item("extraction_issues", json_to_py(py, &issues)?)?;
+    let metrics = serde_json::to_value(result.metrics)
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+    py_result.set_item("metrics", json_to_py(py, &metrics)?)?;
+    py_result.set_item("batch_hash", result.batch_hash.as_deref())?;
 
     // Convert trace results to Python list of dicts
     let traces_list = PyList::empty(py);
-    for trace in result.traces {
-        let trace_dict = PyDict::new(py);
-        trace_dict.set_item("trace_id", &trace.trace_id)?;
-        trace_dict.set_item("destination", &trace.destination)?;
-        trace_dict.set_item("schema_version", &trace.schema_version)?;
-        trace_dict.set_item("accepted", trace.accepted)?;
-
-        if let Some(reason) = &trace.rejection_reason {
-            trace_dict.set_item("rejection_reason", reason)?;
-        }
+    for trace in &result.traces {
+        traces_list.append(trace_to_py(py, ctx, trace)?)?;
+    }
+    py_result.set_item("traces", traces_list)?;
+
+    Ok(py_result.into())
+}
+
+/// Convert one trace result to a Python dict.
+fn trace_to_py<'py>(py: Python<'py>, ctx: &BatchContext, trace: &TraceResult) -> PyResult<&'py PyDict> {
+    let trace_dict = PyDict::new(py);
+    trace_dict.set_item("trace_id", &trace.trace_id)?;
+    trace_dict.set_item("destination", &trace.destination)?;
+    trace_dict.set_item("schema_version", &trace.schema_version)?;
+    trace_dict.set_item("accepted", trace.accepted)?;
+
+    if let Some(reason) = &trace.rejection_reason {
+        trace_dict.set_item("rejection_reason", reason)?;
+    }
+    trace_dict.set_item("signature_format", &trace.signature_format)?;
+    trace_dict.set_item("signature_formats_tried", &trace.signature_formats_tried)?;
 
-        // Convert extracted metadata to Python dict
-        let metadata_dict = PyDict::new(py);
+    // Convert extracted metadata to Python dict (dicts keep insertion
+    // order, so sorted output survives the conversion)
+    let metadata_dict = PyDict::new(py);
+    if ctx.config.extraction.sorted_output {
+        for (key, value) in trace.sorted_metadata() {
+            metadata_dict.set_item(key, value)?;
+        }
+        trace_dict.set_item("extracted_metadata_json", trace.extracted_metadata_json())?;
+    } else {
         for (key, value) in &trace.extracted_metadata {
             metadata_dict.set_item(key, value)?;
         }
-        trace_dict.set_item("extracted_metadata", metadata_dict)?;
-
-        traces_list.append(trace_dict)?;
     }
-    py_result.set_item("traces", traces_list)?;
+    trace_dict.set_item("extracted_metadata", metadata_dict)?;
 
-    Ok(py_result.into())
+    Ok(trace_dict)
+}
+
+/// Replay a stored trace through the current pipeline.
+///
+/// Processes one trace exactly as `process_trace_batch` would, but skips
+/// the known-malformed fast path and tags the result `replayed=true` in
+/// its extracted metadata. Use it to re-run traces from the malformed
+/// table once the missing key, schema or config has been loaded.
+///
+/// # Arguments
+/// * `event_json` - The stored trace JSON
+/// * `trace_level` - "generic", "detailed", or "full_traces"
+///
+/// # Returns
+/// Dict shaped like one entry of `process_trace_batch`'s `traces`
+#[pyfunction]
+#[pyo3(signature = (event_json, trace_level="detailed".to_string()))]
+fn reprocess_event(py: Python<'_>, event_json: &str, trace_level: String) -> PyResult<Py<PyAny>> {
+    init_logger();
+
+    let batch_timestamp = chrono::Utc::now().to_rfc3339();
+    let ctx = BatchContext::new(&batch_timestamp, None, &trace_level, None);
+    let trace = pipeline::ingestion::reprocess_event(&ctx, event_json);
+    Ok(trace_to_py(py, &ctx, &trace)?.into())
+}
+
+/// Benchmark the pipeline on a supplied batch.
+///
+/// Runs the full pipeline `iterations` times without building per-trace
+/// results, for capacity planning on real data.
+///
+/// # Arguments
+/// * `events` - Raw trace JSON strings
+/// * `trace_level` - "generic", "detailed", or "full_traces"
+/// * `iterations` - Number of passes over the batch
+///
+/// # Returns
+/// Dict with `traces_per_sec`, `p50/p95/p99/max_trace_micros`,
+/// `total_micros` and accepted/rejected counts
+#[pyfunction]
+#[pyo3(signature = (events, trace_level="detailed".to_string(), iterations=10))]
+fn benchmark_batch(
+    py: Python<'_>,
+    events: Vec<String>,
+    trace_level: String,
+    iterations: usize,
+) -> PyResult<Py<PyAny>> {
+    init_logger();
+
+    let batch_timestamp = chrono::Utc::now().to_rfc3339();
+    let ctx = BatchContext::new(&batch_timestamp, None, &trace_level, None);
+    let stats = pipeline::benchmark::benchmark_batch(&ctx, &events, iterations);
+    let value = serde_json::to_value(stats)
+        .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
+    json_to_py(py, &value)
+}
+
+/// Dry-run candidate policies on a batch.
+///
+/// Processes the batch under the current config and under the current
+/// config with `policy_json` applied, without writing the rejection
+/// buffer or known-malformed set, and returns the decisions and delta.
+///
+/// # Arguments
+/// * `events` - Raw trace JSON strings
+/// * `policy_json` - Partial config JSON, as for `configure_pipeline`
+/// * `batch_timestamp` - Timestamp for the batch (default: now)
+/// * `trace_level` - "generic", "detailed", or "full_traces"
+///
+/// # Returns
+/// Dict with `traces` (per-trace `current`/`candidate` decisions) and the
+/// `newly_rejected`, `newly_accepted` and `rerouted` trace indices
+///
+/// # Errors
+/// - `ValueError` if the policy JSON is invalid or names an unknown key
+#[pyfunction]
+#[pyo3(signature = (events, policy_json, batch_timestamp=None, trace_level="detailed".to_string()))]
+fn lint_batch(
+    py: Python<'_>,
+    events: Vec<String>,
+    policy_json: &str,
+    batch_timestamp: Option<String>,
+    trace_level: String,
+) -> PyResult<Py<PyAny>> {
+    use pyo3::exceptions::PyValueError;
+
+    init_logger();
+
+    let policy: serde_json::Value = serde_json::from_str(policy_json)
+        .map_err(|e| PyValueError::new_err(format!("invalid policy JSON: {e}")))?;
+    let batch_timestamp = batch_timestamp.unwrap_or_else(|| chrono::Utc::now().to_rfc3339());
+    let ctx = BatchContext::new(&batch_timestamp, None, &trace_level, None);
+    let report =
+        pipeline::lint::lint_batch(&ctx, &policy, &events).map_err(PyValueError::new_err)?;
+    let value = serde_json::to_value(report)
+        .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
+    json_to_py(py, &value)
 }
 
 /// Load schemas from database into cache.
@@ -130,15 +417,32 @@ fn process_trace_batch(
 /// # Arguments
 /// * `schemas` - List of schema rows from trace_schemas table
 /// * `fields` - List of field rows from trace_schema_fields table
+/// * `schema_options` - Optional map of schema version -> options JSON
+///   (e.g. `{"required_event_types": [...]}`)
+///
+/// # Errors
+/// - `ValueError` if any options JSON is invalid or has unknown keys
 #[pyfunction]
+#[pyo3(signature = (schemas, fields, schema_options=None))]
 fn load_schemas_from_db(
     schemas: Vec<(String, String, String, Vec<String>)>, // (version, description, status, signature_events)
-    fields: Vec<(String, String, String, String, String, bool, String)>, // (schema_ver, event_type, field_name, json_path, data_type, required, db_column)
+    fields: Vec<validation::schema::FieldRow>, // (schema_ver, event_type, field_name, json_path, data_type, required, db_column, default_value)
+    schema_options: Option<HashMap<String, String>>,
 ) -> PyResult<()> {
+    use pyo3::exceptions::PyValueError;
+
     init_logger();
 
+    let mut options = HashMap::new();
+    for (version, options_json) in schema_options.unwrap_or_default() {
+        let parsed: validation::schema::SchemaOptions = serde_json::from_str(&options_json)
+            .map_err(|e| PyValueError::new_err(format!("invalid options for schema {version}: {e}")))?;
+        options.insert(version, parsed);
+    }
+
     let mut cache = validation::schema::get_schema_cache_mut();
-    cache.load_from_db_rows(schemas, fields);
+    cache.load_from_db_rows_with_options(schemas, fields, options);
+    pipeline::known_malformed::clear_known_malformed();
 
     log::info!(
         "SCHEMA_CACHE_LOADED_FROM_DB schemas={:?}",
@@ -155,6 +459,7 @@ fn load_schemas_from_db(
 fn refresh_schema_cache() -> PyResult<()> {
     init_logger();
     validation::schema::get_schema_cache_mut().clear();
+    pipeline::known_malformed::clear_known_malformed();
     log::info!("SCHEMA_CACHE_CLEARED");
     Ok(())
 }
@@ -166,28 +471,78 @@ fn get_loaded_schemas() -> PyResult<Vec<String>> {
     Ok(cache.schema_versions())
 }
 
+/// Get the accord_traces columns a loaded schema populates.
+///
+/// All distinct `db_column`s across the schema's field rules plus the
+/// standard columns filled for every trace, sorted, so operators can
+/// check a table matches before creating it.
+///
+/// # Raises
+/// - `ValueError` if the schema version isn't loaded
+#[pyfunction]
+fn get_schema_columns(version: &str) -> PyResult<Vec<String>> {
+    validation::schema::get_schema_cache()
+        .schema_columns(version)
+        .ok_or_else(|| pyo3::exceptions::PyValueError::new_err(format!("unknown schema version: {}", version)))
+}
+
 /// Load public keys from database into cache.
 ///
+/// Key ids are trimmed of surrounding whitespace, and lowercased when the
+/// `signature.case_insensitive_key_ids` config option is set.
+///
 /// # Arguments
 /// * `keys` - List of (key_id, public_key_base64) tuples
+/// * `key_metadata` - Optional key_id -> `{"algorithm", "not_before",
+///   "not_after", "domain_prefix"}` (all optional; timestamps RFC3339).
+///   Traces signed by a key outside its window at the batch timestamp are
+///   rejected with `key_expired` / `key_not_yet_valid`. A `domain_prefix`
+///   is tried prepended to the canonical message when the bare message
+///   fails.
 #[pyfunction]
-fn load_public_keys_from_db(keys: Vec<(String, String)>) -> PyResult<()> {
+#[pyo3(signature = (keys, key_metadata=None))]
+fn load_public_keys_from_db(
+    keys: Vec<(String, String)>,
+    key_metadata: Option<HashMap<String, HashMap<String, String>>>,
+) -> PyResult<()> {
     init_logger();
 
+    let case_insensitive = config::get_pipeline_config()
+        .signature
+        .case_insensitive_key_ids;
     let mut cache = validation::signature::get_key_cache_mut();
     cache.clear();
+    cache.set_case_insensitive_ids(case_insensitive);
+    validation::verification_cache::clear_verification_cache();
+    validation::signature::clear_key_activity();
 
     let mut loaded = 0;
     let mut errors = Vec::new();
 
+    let key_metadata = key_metadata.unwrap_or_default();
     for (key_id, public_key_base64) in keys {
-        match cache.load_key(&key_id, &public_key_base64) {
+        let metadata = match key_metadata.get(&key_id) {
+            Some(fields) => validation::signature::KeyMetadata::parse(
+                fields.get("algorithm").map(String::as_str),
+                fields.get("not_before").map(String::as_str),
+                fields.get("not_after").map(String::as_str),
+            )
+            .map(|metadata| validation::signature::KeyMetadata {
+                domain_prefix: fields.get("domain_prefix").cloned(),
+                ..metadata
+            }),
+            None => Ok(validation::signature::KeyMetadata::default()),
+        };
+        let loaded_key = metadata
+            .and_then(|metadata| cache.load_key_with_metadata(&key_id, &public_key_base64, metadata));
+        match loaded_key {
             Ok(()) => loaded += 1,
             Err(e) => errors.push(format!("{}: {}", key_id, e)),
         }
     }
 
     cache.mark_loaded();
+    pipeline::known_malformed::clear_known_malformed();
 
     log::info!(
         "PUBLIC_KEY_CACHE_LOADED keys={} errors={}",
@@ -202,11 +557,183 @@ fn load_public_keys_from_db(keys: Vec<(String, String)>) -> PyResult<()> {
     Ok(())
 }
 
+/// Load the list of fields excluded from security scanning.
+///
+/// The value subtree of any object key in this list is skipped by the
+/// sanitizer. Replaces the previous list; pass an empty list to scan
+/// everything again.
+///
+/// # Arguments
+/// * `fields` - Field names to exclude (e.g. `system_snapshot`)
+#[pyfunction]
+fn load_scan_exclusions_from_db(fields: Vec<String>) -> PyResult<()> {
+    init_logger();
+    log::info!("SCAN_EXCLUSIONS_LOADED fields={:?}", fields);
+    security::sanitizer::set_scan_excluded_fields(fields);
+    Ok(())
+}
+
+/// Load the fields that are PII-scrubbed at every trace level.
+///
+/// When a trace contains any of these fields, their values are scrubbed
+/// even at `generic`/`detailed` (defense in depth).
+///
+/// # Arguments
+/// * `fields` - Field names to always scrub
+#[pyfunction]
+fn load_always_scrub_fields_from_db(fields: Vec<String>) -> PyResult<()> {
+    init_logger();
+    log::info!("ALWAYS_SCRUB_FIELDS_LOADED fields={:?}", fields);
+    security::pii::set_always_scrub_fields(fields);
+    Ok(())
+}
+
+/// Load the PII target fields from the database.
+///
+/// Replaces the built-in `PII_TARGET_FIELDS` list; an empty list restores
+/// it. Schemas with their own target fields merge the list in at scrub
+/// time, so this takes effect whether it runs before or after schemas load.
+///
+/// # Arguments
+/// * `fields` - Field names scrubbed for PII at full_traces
+#[pyfunction]
+fn load_pii_fields_from_db(fields: Vec<String>) -> PyResult<()> {
+    init_logger();
+    log::info!("PII_FIELDS_LOADED fields={:?}", fields);
+    security::pii::set_pii_fields(fields);
+    Ok(())
+}
+
+/// Refresh the PII target fields.
+///
+/// Restores the built-in list; call `load_pii_fields_from_db` again
+/// after modifying the fields in the database.
+#[pyfunction]
+fn refresh_pii_fields() -> PyResult<()> {
+    init_logger();
+    security::pii::set_pii_fields(Vec::new());
+    log::info!("PII_FIELDS_CLEARED");
+    Ok(())
+}
+
+/// Load the `selected_action` normalization map from the database.
+///
+/// Extraction then stores `selected_action_normalized` alongside the raw
+/// value; values missing from the map pass through and are flagged with
+/// `selected_action_unmapped`.
+///
+/// # Arguments
+/// * `mapping` - Raw variant (e.g. `SPEAK`, `action.speak`) -> canonical action
+#[pyfunction]
+fn load_action_taxonomy_from_db(mapping: HashMap<String, String>) -> PyResult<()> {
+    init_logger();
+    log::info!("ACTION_TAXONOMY_LOADED entries={}", mapping.len());
+    extraction::metadata::set_action_taxonomy(mapping);
+    Ok(())
+}
+
+/// Set the fraction of production traces stored (1.0 keeps all).
+///
+/// Production traces whose `trace_id` hashes outside the rate are routed
+/// to `sampled_out` (accepted, not stored); the same trace_id is always
+/// kept or always dropped. Mock, connectivity and malformed traces are
+/// never sampled. Shorthand for `routing.production_sample_rate` in
+/// `configure_pipeline`; applies to batches started after this call.
+///
+/// # Raises
+/// - `ValueError` if `rate` is not within 0.0–1.0
+#[pyfunction]
+fn set_production_sample_rate(rate: f64) -> PyResult<()> {
+    init_logger();
+    routing::sampling::check_sample_rate(rate).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    config::get_pipeline_config_mut().routing.production_sample_rate = rate;
+    log::info!("PRODUCTION_SAMPLE_RATE_SET rate={}", rate);
+    Ok(())
+}
+
+/// Load per-agent consent state from the database.
+///
+/// Traces whose `agent_id_hash` is in the map are rejected with
+/// `no_consent` unless the agent is consented with a consent timestamp
+/// not after the batch timestamp. Agents missing from the map fall back
+/// to the batch-level consent. Replaces the previous map.
+///
+/// # Arguments
+/// * `rows` - (agent_id_hash, consented, consent_timestamp RFC3339 or None)
+///
+/// # Raises
+/// - `ValueError` if a consent timestamp is not valid RFC3339
+#[pyfunction]
+fn load_agent_consent_from_db(rows: Vec<(String, bool, Option<String>)>) -> PyResult<()> {
+    init_logger();
+
+    let mut consent = HashMap::with_capacity(rows.len());
+    for (agent_id_hash, consented, consent_timestamp) in rows {
+        let consent_timestamp = consent_timestamp
+            .map(|ts| {
+                chrono::DateTime::parse_from_rfc3339(&ts)
+                    .map(|dt| dt.with_timezone(&chrono::Utc))
+                    .map_err(|e| {
+                        pyo3::exceptions::PyValueError::new_err(format!(
+                            "invalid consent_timestamp for {}: {}",
+                            agent_id_hash, e
+                        ))
+                    })
+            })
+            .transpose()?;
+        consent.insert(
+            agent_id_hash,
+            pipeline::consent::AgentConsent { consented, consent_timestamp },
+        );
+    }
+
+    log::info!("AGENT_CONSENT_LOADED agents={}", consent.len());
+    pipeline::consent::set_agent_consent(consent);
+    pipeline::known_malformed::clear_known_malformed();
+    Ok(())
+}
+
+/// Load the AES-256-GCM key used when `pii.mode` is `encrypt`.
+///
+/// # Arguments
+/// * `key_base64` - 32-byte key, base64-encoded
+///
+/// # Raises
+/// - `ValueError` if the key is not valid base64 or not 32 bytes
+#[pyfunction]
+fn load_pii_encryption_key(key_base64: &str) -> PyResult<()> {
+    init_logger();
+    security::pii::set_pii_encryption_key(key_base64).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    log::info!("PII_ENCRYPTION_KEY_LOADED");
+    Ok(())
+}
+
+/// Load the HMAC key used when `pii.mode` is `pseudonymize`.
+///
+/// Keep the key stable within a deployment: tokens only correlate across
+/// traces scrubbed under the same key.
+///
+/// # Arguments
+/// * `key_base64` - Secret key of at least 32 bytes, base64-encoded
+///
+/// # Raises
+/// - `ValueError` if the key is not valid base64 or shorter than 32 bytes
+#[pyfunction]
+fn load_pii_pseudonym_key(key_base64: &str) -> PyResult<()> {
+    init_logger();
+    security::pii::set_pii_pseudonym_key(key_base64).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    log::info!("PII_PSEUDONYM_KEY_LOADED");
+    Ok(())
+}
+
 /// Refresh the public key cache.
 #[pyfunction]
 fn refresh_public_key_cache() -> PyResult<()> {
     init_logger();
     validation::signature::get_key_cache_mut().clear();
+    validation::verification_cache::clear_verification_cache();
+    validation::signature::clear_key_activity();
+    pipeline::known_malformed::clear_known_malformed();
     Ok(())
 }
 
@@ -217,6 +744,131 @@ fn get_public_key_count() -> PyResult<usize> {
     Ok(cache.key_count())
 }
 
+/// Get a loaded key's algorithm, validity window and domain prefix.
+///
+/// Returns `{"key_id", "algorithm", "not_before", "not_after",
+/// "domain_prefix", "domain_prefixes"}` (bounds are RFC3339 or None), or
+/// None for an unknown key. `domain_prefix` is the key's own tag;
+/// `domain_prefixes` lists every tag verification tries after the bare
+/// message, including the global one.
+#[pyfunction]
+fn get_key_metadata(py: Python<'_>, key_id: &str) -> PyResult<Option<Py<PyAny>>> {
+    let cache = validation::signature::get_key_cache();
+    let metadata = match cache.get_key_metadata(key_id) {
+        Some(metadata) => metadata,
+        None => return Ok(None),
+    };
+    let value = serde_json::json!({
+        "key_id": key_id,
+        "algorithm": metadata.algorithm,
+        "not_before": metadata.not_before.map(|t| t.to_rfc3339()),
+        "not_after": metadata.not_after.map(|t| t.to_rfc3339()),
+        "domain_prefix": metadata.domain_prefix,
+        "domain_prefixes": cache.domain_prefixes(key_id),
+    });
+    json_to_py(py, &value).map(Some)
+}
+
+/// Dump the canonical bytes Lens would verify for a trace.
+///
+/// For debugging signature failures: returns the exact bytes of each
+/// canonical format candidate (base64) with their lengths and SHA256
+/// hashes, without attempting verification.
+///
+/// # Arguments
+/// * `event_json` - The trace JSON as received
+/// * `trace_level` - Batch trace level (part of the 1.9.9 canonical form)
+///
+/// # Errors
+/// - `ValueError` if the JSON is invalid or has no components
+#[pyfunction]
+fn dump_canonical(py: Python<'_>, event_json: &str, trace_level: &str) -> PyResult<Py<PyAny>> {
+    let dump = pipeline::ingestion::canonical_candidates(event_json, trace_level)
+        .map_err(pyo3::exceptions::PyValueError::new_err)?;
+    json_to_py(py, &dump)
+}
+
+/// Compute the canonical string of a components array in each format.
+///
+/// Paste an agent's components to diff the Rust canonicalization against
+/// the agent's own when signatures fail.
+///
+/// # Arguments
+/// * `components_json` - The trace's `components` array as JSON
+/// * `trace_level` - Trace level for the 1.9.9 wrapper
+///
+/// # Returns
+/// Dict of format name (`1.9.9`, `1.9.8`, `1.9.7`, `pre-1.9.7`) to
+/// `{"canonical": str, "sha256": hex}`
+///
+/// # Errors
+/// - `ValueError` if the JSON is invalid
+#[pyfunction]
+fn compute_canonical_forms(py: Python<'_>, components_json: &str, trace_level: &str) -> PyResult<Py<PyAny>> {
+    let forms = pipeline::ingestion::canonical_forms(components_json, trace_level)
+        .map_err(pyo3::exceptions::PyValueError::new_err)?;
+    json_to_py(py, &forms)
+}
+
+/// Get the most recently rejected traces, oldest first.
+///
+/// Each entry has `trace_id`, `reason`, `content_hash`, `event_types`,
+/// `key_id` and a truncated `preview` of the raw body. The buffer size is
+/// `diagnostics.recent_rejections` in the pipeline config.
+#[pyfunction]
+fn get_recent_rejections(py: Python<'_>) -> PyResult<Py<PyAny>> {
+    let records = pipeline::recent_rejections::get_recent_rejections();
+    let value = serde_json::to_value(records)
+        .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
+    json_to_py(py, &value)
+}
+
+/// Clear the recent rejections buffer.
+#[pyfunction]
+fn clear_recent_rejections() -> PyResult<()> {
+    pipeline::recent_rejections::clear_recent_rejections();
+    Ok(())
+}
+
+/// Get per-format signature verification metrics.
+///
+/// Returns `{format: {attempts, verified, total_micros}}` accumulated since
+/// process start, so operators can see what each canonical format in the
+/// verify cascade costs before deciding which to retire.
+#[pyfunction]
+fn get_signature_metrics(py: Python<'_>) -> PyResult<Py<PyAny>> {
+    let result = PyDict::new(py);
+    for (format, metrics) in validation::signature::get_signature_metrics() {
+        let entry = PyDict::new(py);
+        entry.set_item("attempts", metrics.attempts)?;
+        entry.set_item("verified", metrics.verified)?;
+        entry.set_item("total_micros", metrics.total_micros)?;
+        result.set_item(format, entry)?;
+    }
+    Ok(result.into())
+}
+
+/// Get per-key verification activity since the last key refresh.
+///
+/// One dict per key that has been checked, ordered by key id:
+/// `key_id`, `last_seen` (RFC3339), `verified`, `failed` and the
+/// `formats` its signatures verified with — for spotting a key that went
+/// silent or started failing.
+#[pyfunction]
+fn get_key_activity(py: Python<'_>) -> PyResult<Py<PyAny>> {
+    let result = PyList::empty(py);
+    for activity in validation::signature::get_key_activity() {
+        let entry = PyDict::new(py);
+        entry.set_item("key_id", activity.key_id)?;
+        entry.set_item("last_seen", activity.last_seen.to_rfc3339())?;
+        entry.set_item("verified", activity.verified)?;
+        entry.set_item("failed", activity.failed)?;
+        entry.set_item("formats", activity.formats.into_iter().collect::<Vec<_>>())?;
+        result.append(entry)?;
+    }
+    Ok(result.into())
+}
+
 /// Check if caches need refresh (TTL expired).
 ///
 /// Returns (schema_needs_refresh, keys_need_refresh)
@@ -225,14 +877,90 @@ fn check_cache_status() -> PyResult<(bool, bool, Option<u64>, Option<u64>)> {
     let schema_cache = validation::schema::get_schema_cache();
     let key_cache = validation::signature::get_key_cache();
 
+    let config = config::get_pipeline_config();
+
     Ok((
-        schema_cache.needs_refresh(),
-        key_cache.needs_refresh(),
+        schema_cache.needs_refresh_after(config.cache.schema_ttl_secs),
+        key_cache.needs_refresh_after(config.cache.key_ttl_secs),
         schema_cache.cache_age_secs(),
         key_cache.cache_age_secs(),
     ))
 }
 
+/// Update the pipeline configuration.
+///
+/// Takes a partial JSON object; only the keys present are changed. New
+/// values apply to batches started after this call.
+///
+/// # Errors
+/// - `ValueError` if the JSON is invalid, names an unknown key, a value
+///   has the wrong type, or `routing.production_sample_rate` is outside
+///   0.0–1.0
+#[pyfunction]
+fn configure_pipeline(config_json: &str) -> PyResult<()> {
+    use pyo3::exceptions::PyValueError;
+
+    init_logger();
+
+    let update: serde_json::Value = serde_json::from_str(config_json)
+        .map_err(|e| PyValueError::new_err(format!("invalid config JSON: {e}")))?;
+
+    let mut config = config::get_pipeline_config_mut();
+    config.apply_json(&update).map_err(PyValueError::new_err)?;
+    let mut key_cache = validation::signature::get_key_cache_mut();
+    key_cache.set_accept_hex_signatures(config.signature.accept_hex_signatures);
+    key_cache.set_domain_prefix(config.signature.domain_prefix.clone());
+    drop(key_cache);
+    validation::verification_cache::clear_verification_cache();
+    pipeline::known_malformed::clear_known_malformed();
+
+    log::info!("PIPELINE_CONFIG_UPDATED update={}", update);
+
+    Ok(())
+}
+
+/// Build a multi-row INSERT query for accord_traces.
+///
+/// Lets a batch be stored in one statement instead of one per trace.
+/// Row `i` uses placeholders `$(i*60+1)..$(i*60+60)`, so pass each row's
+/// 60 parameters in column order, row after row.
+///
+/// # Returns
+/// `(sql, placeholder_count)`
+///
+/// # Raises
+/// - `ValueError` if `row_count` is 0 or the statement would exceed
+///   PostgreSQL's 65535 bind parameters
+#[pyfunction]
+fn build_trace_insert_batch(row_count: usize) -> PyResult<(String, usize)> {
+    storage::queries::build_trace_insert_batch(row_count).map_err(pyo3::exceptions::PyValueError::new_err)
+}
+
+/// Build an UPDATE query refreshing a subset of accord_traces columns.
+///
+/// For reprocessing stored traces after extraction rules change. Pass
+/// `trace_id` as `$1`, then the new values in `columns` order.
+///
+/// # Raises
+/// - `ValueError` for an empty list, a repeated column, `trace_id`, or a
+///   column accord_traces doesn't have
+#[pyfunction]
+fn build_trace_update(columns: Vec<String>) -> PyResult<String> {
+    let columns: Vec<&str> = columns.iter().map(|c| c.as_str()).collect();
+    storage::queries::build_trace_update(&columns).map_err(pyo3::exceptions::PyValueError::new_err)
+}
+
+/// Get the effective configuration snapshot.
+///
+/// Returns `{"config": {...}, "caches": {...}}`: every active pipeline
+/// config value plus schema/key cache state, for audit and support
+/// tickets. Read-only and side-effect free.
+#[pyfunction]
+fn get_effective_config(py: Python<'_>) -> PyResult<Py<PyAny>> {
+    let config = config::get_pipeline_config().clone();
+    json_to_py(py, &config::effective_config(&config))
+}
+
 /// Scrubbing v2 entry point — the only path to persistence for trace text.
 ///
 /// Takes a JSON-serialized trace and a level string, runs the scrubber, and
@@ -342,13 +1070,40 @@ fn scrub_traces_batch<'a>(
 #[pymodule]
 fn cirislens_core(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(process_trace_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(process_trace_batch_compressed, m)?)?;
+    m.add_function(wrap_pyfunction!(reprocess_event, m)?)?;
     m.add_function(wrap_pyfunction!(load_schemas_from_db, m)?)?;
     m.add_function(wrap_pyfunction!(refresh_schema_cache, m)?)?;
     m.add_function(wrap_pyfunction!(get_loaded_schemas, m)?)?;
+    m.add_function(wrap_pyfunction!(get_schema_columns, m)?)?;
     m.add_function(wrap_pyfunction!(load_public_keys_from_db, m)?)?;
     m.add_function(wrap_pyfunction!(refresh_public_key_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(benchmark_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(lint_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(load_scan_exclusions_from_db, m)?)?;
+    m.add_function(wrap_pyfunction!(load_always_scrub_fields_from_db, m)?)?;
+    m.add_function(wrap_pyfunction!(load_pii_fields_from_db, m)?)?;
+    m.add_function(wrap_pyfunction!(refresh_pii_fields, m)?)?;
+    m.add_function(wrap_pyfunction!(load_action_taxonomy_from_db, m)?)?;
+    m.add_function(wrap_pyfunction!(load_agent_consent_from_db, m)?)?;
+    m.add_function(wrap_pyfunction!(set_production_sample_rate, m)?)?;
+    m.add_function(wrap_pyfunction!(build_trace_insert_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(build_trace_update, m)?)?;
+    m.add_function(wrap_pyfunction!(load_pii_encryption_key, m)?)?;
+    m.add_function(wrap_pyfunction!(load_pii_pseudonym_key, m)?)?;
     m.add_function(wrap_pyfunction!(get_public_key_count, m)?)?;
+    m.add_function(wrap_pyfunction!(get_key_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(get_signature_metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(get_key_activity, m)?)?;
+    m.add_function(wrap_pyfunction!(set_log_level, m)?)?;
+    m.add_function(wrap_pyfunction!(set_log_format, m)?)?;
+    m.add_function(wrap_pyfunction!(dump_canonical, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_canonical_forms, m)?)?;
+    m.add_function(wrap_pyfunction!(get_recent_rejections, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_recent_rejections, m)?)?;
     m.add_function(wrap_pyfunction!(check_cache_status, m)?)?;
+    m.add_function(wrap_pyfunction!(configure_pipeline, m)?)?;
+    m.add_function(wrap_pyfunction!(get_effective_config, m)?)?;
     m.add_function(wrap_pyfunction!(scrub_trace, m)?)?;
     m.add_function(wrap_pyfunction!(scrub_traces_batch, m)?)?;
     m.add_function(wrap_pyfunction!(ner_is_configured, m)?)?;
@@ -0,0 +1,71 @@
+//! Runtime log level.
+//!
+//! The logger is installed with every level enabled; filtering is done by
+//! `log`'s global max level, an atomic that can be changed from any thread.
+//! That lets operators turn on the pipeline's debug diagnostics from Python
+//! (or via `CIRISLENS_LOG_LEVEL` at startup) without a rebuild.
+
+use log::LevelFilter;
+
+/// Environment variable holding the initial log level.
+pub const LOG_LEVEL_ENV: &str = "CIRISLENS_LOG_LEVEL";
+
+/// Parse a level name (trace, debug, info, warn or error; case-insensitive).
+pub fn parse_log_level(level: &str) -> Result<LevelFilter, String> {
+    match level.trim().to_ascii_lowercase().as_str() {
+        "trace" => Ok(LevelFilter::Trace),
+        "debug" => Ok(LevelFilter::Debug),
+        "info" => Ok(LevelFilter::Info),
+        "warn" | "warning" => Ok(LevelFilter::Warn),
+        "error" => Ok(LevelFilter::Error),
+        _ => Err(format!(
+            "Unknown log level '{}': expected trace, debug, info, warn or error",
+            level
+        )),
+    }
+}
+
+/// Level from `CIRISLENS_LOG_LEVEL`, falling back to info when unset or
+/// unrecognized.
+pub fn initial_log_level() -> LevelFilter {
+    std::env::var(LOG_LEVEL_ENV)
+        .ok()
+        .and_then(|level| parse_log_level(&level).ok())
+        .unwrap_or(LevelFilter::Info)
+}
+
+/// Change the effective log level.
+pub fn set_log_level(level: &str) -> Result<LevelFilter, String> {
+    let filter = parse_log_level(level)?;
+    log::set_max_level(filter);
+    Ok(filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::capture;
+
+    #[test]
+    fn test_parse_log_level() {
+        assert_eq!(parse_log_level("DEBUG"), Ok(LevelFilter::Debug));
+        assert_eq!(parse_log_level("warning"), Ok(LevelFilter::Warn));
+        assert!(parse_log_level("verbose").is_err());
+    }
+
+    #[test]
+    fn test_toggle_to_debug_emits_debug_records() {
+        capture::install();
+        let captured = |probe: &str| capture::messages().iter().any(|m| m == probe);
+
+        set_log_level("info").unwrap();
+        log::debug!("level-probe-hidden");
+        assert!(!captured("level-probe-hidden"));
+
+        set_log_level("debug").unwrap();
+        log::debug!("level-probe-shown");
+        assert!(captured("level-probe-shown"));
+
+        set_log_level("info").unwrap();
+    }
+}
@@ -3,6 +3,44 @@
 //! Provides logging macros and utilities that include batch_id and trace_id
 //! in every log message for easy correlation.
 
+pub mod level;
 pub mod structured;
 
 pub use structured::*;
+
+/// Process-wide logger for tests that check emitted records.
+#[cfg(test)]
+pub(crate) mod capture {
+    use log::{Log, Metadata, Record};
+    use parking_lot::Mutex;
+
+    struct CaptureLogger {
+        messages: Mutex<Vec<String>>,
+    }
+
+    impl Log for CaptureLogger {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            self.messages.lock().push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: CaptureLogger = CaptureLogger {
+        messages: Mutex::new(Vec::new()),
+    };
+
+    /// Install the capturing logger (a no-op once any logger is set).
+    pub fn install() {
+        let _ = log::set_logger(&CAPTURE);
+    }
+
+    /// Messages captured so far, from every test thread.
+    pub fn messages() -> Vec<String> {
+        CAPTURE.messages.lock().clone()
+    }
+}
@@ -2,8 +2,57 @@
 //!
 //! Provides context-aware logging with batch_id and trace_id included
 //! in every log message.
+//!
+//! Records are written as text lines by default. `set_log_format("json")`
+//! switches to one JSON object per line for machine ingestion: records
+//! from the `log_*!` macros carry `batch_id`, `trace_id`, `event`,
+//! `level` and their fields as typed properties, and any other record is
+//! wrapped with its text as `message`.
 
 use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Log target of records whose message is already a JSON object.
+pub const STRUCTURED_TARGET: &str = "cirislens::structured";
+
+/// Field map of a structured record.
+pub type JsonFields = Map<String, Value>;
+
+/// Output format of log records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `[batch=...] [trace=...] EVENT key=value` lines.
+    Text,
+    /// One JSON object per record.
+    Json,
+}
+
+static LOG_FORMAT: AtomicU8 = AtomicU8::new(LogFormat::Text as u8);
+
+/// The current log format.
+pub fn log_format() -> LogFormat {
+    if LOG_FORMAT.load(Ordering::Relaxed) == LogFormat::Json as u8 {
+        LogFormat::Json
+    } else {
+        LogFormat::Text
+    }
+}
+
+/// Switch the log format ("text" or "json").
+pub fn set_log_format(format: &str) -> Result<LogFormat, String> {
+    let parsed = match format.trim().to_ascii_lowercase().as_str() {
+        "text" => LogFormat::Text,
+        "json" => LogFormat::Json,
+        _ => return Err(format!("Unknown log format '{}': expected text or json", format)),
+    };
+    LOG_FORMAT.store(parsed as u8, Ordering::Relaxed);
+    Ok(parsed)
+}
 
 /// Logging context for a batch of traces.
 #[derive(Debug, Clone)]
@@ -26,6 +75,17 @@ impl LogContext {
             trace_id: Some(trace_id.to_string()),
         }
     }
+
+    /// `batch_id` and `trace_id` (null outside a trace) as JSON properties.
+    pub fn to_json(&self) -> JsonFields {
+        let mut map = Map::new();
+        map.insert("batch_id".to_string(), Value::String(self.batch_id.clone()));
+        map.insert(
+            "trace_id".to_string(),
+            self.trace_id.clone().map_or(Value::Null, Value::String),
+        );
+        map
+    }
 }
 
 impl fmt::Display for LogContext {
@@ -37,16 +97,108 @@ impl fmt::Display for LogContext {
     }
 }
 
+fn log_timestamp() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// A field value as JSON (null if it can't be serialized).
+pub fn field_value<T: Serialize + ?Sized>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+/// One structured record as a JSON object string.
+pub fn structured_record(level: log::Level, ctx: &LogContext, event: &str, fields: JsonFields) -> String {
+    let mut record = ctx.to_json();
+    record.insert("timestamp".to_string(), Value::String(log_timestamp()));
+    record.insert("level".to_string(), Value::String(level.as_str().to_string()));
+    record.insert("event".to_string(), Value::String(event.to_string()));
+    record.insert("fields".to_string(), Value::Object(fields));
+    Value::Object(record).to_string()
+}
+
+/// A log record as a JSON object string.
+pub fn json_log_line(record: &log::Record) -> String {
+    if record.target() == STRUCTURED_TARGET {
+        return record.args().to_string();
+    }
+    let mut line = Map::new();
+    line.insert("timestamp".to_string(), Value::String(log_timestamp()));
+    line.insert("level".to_string(), Value::String(record.level().as_str().to_string()));
+    line.insert("target".to_string(), Value::String(record.target().to_string()));
+    line.insert("message".to_string(), Value::String(record.args().to_string()));
+    Value::Object(line).to_string()
+}
+
+/// Write a record in the current log format (the logger's formatter).
+pub fn write_record(out: &mut dyn Write, record: &log::Record) -> io::Result<()> {
+    match log_format() {
+        LogFormat::Json => writeln!(out, "{}", json_log_line(record)),
+        LogFormat::Text => writeln!(
+            out,
+            "[{} {:<5} {}] {}",
+            log_timestamp(),
+            record.level(),
+            record.target(),
+            record.args()
+        ),
+    }
+}
+
+/// Truncate a string to at most `max_chars` characters for log previews.
+///
+/// Always cuts on a char boundary, so multibyte content (agent names,
+/// non-Latin reasoning text) can't panic a log line the way a byte slice
+/// like `&s[..n]` would.
+pub fn safe_truncate(s: &str, max_chars: usize) -> &str {
+    match s.char_indices().nth(max_chars) {
+        Some((byte_idx, _)) => &s[..byte_idx],
+        None => s,
+    }
+}
+
+/// Log a message with context at `$level`.
+///
+/// In text format the fields are appended as `key=value` pairs; in JSON
+/// format they are kept as typed properties under `fields`, so values
+/// containing spaces or `=` survive intact. Values must implement both
+/// `Debug` and `Serialize`.
+#[macro_export]
+macro_rules! log_structured {
+    ($level:expr, $ctx:expr, $event:expr, $($key:ident = $value:expr),* $(,)?) => {
+        if log::log_enabled!($level) {
+            if $crate::logging::structured::log_format() == $crate::logging::structured::LogFormat::Json {
+                #[allow(unused_mut)]
+                let mut fields = $crate::logging::structured::JsonFields::new();
+                $(
+                    fields.insert(
+                        stringify!($key).to_string(),
+                        $crate::logging::structured::field_value(&$value),
+                    );
+                )*
+                log::log!(
+                    target: $crate::logging::structured::STRUCTURED_TARGET,
+                    $level,
+                    "{}",
+                    $crate::logging::structured::structured_record($level, &$ctx, $event, fields)
+                );
+            } else {
+                log::log!(
+                    $level,
+                    "{} {} {}",
+                    $ctx,
+                    $event,
+                    format_args!(concat!($(stringify!($key), "={:?} "),*) $(, $value)*)
+                );
+            }
+        }
+    };
+}
+
 /// Log an info message with context.
 #[macro_export]
 macro_rules! log_info {
     ($ctx:expr, $event:expr, $($key:ident = $value:expr),* $(,)?) => {
-        log::info!(
-            "{} {} {}",
-            $ctx,
-            $event,
-            format_args!($(concat!(stringify!($key), "={:?} "), $value),*)
-        );
+        $crate::log_structured!(log::Level::Info, $ctx, $event, $($key = $value),*)
     };
 }
 
@@ -54,12 +206,7 @@ macro_rules! log_info {
 #[macro_export]
 macro_rules! log_warn {
     ($ctx:expr, $event:expr, $($key:ident = $value:expr),* $(,)?) => {
-        log::warn!(
-            "{} {} {}",
-            $ctx,
-            $event,
-            format_args!($(concat!(stringify!($key), "={:?} "), $value),*)
-        );
+        $crate::log_structured!(log::Level::Warn, $ctx, $event, $($key = $value),*)
     };
 }
 
@@ -67,12 +214,7 @@ macro_rules! log_warn {
 #[macro_export]
 macro_rules! log_error {
     ($ctx:expr, $event:expr, $($key:ident = $value:expr),* $(,)?) => {
-        log::error!(
-            "{} {} {}",
-            $ctx,
-            $event,
-            format_args!($(concat!(stringify!($key), "={:?} "), $value),*)
-        );
+        $crate::log_structured!(log::Level::Error, $ctx, $event, $($key = $value),*)
     };
 }
 
@@ -80,12 +222,7 @@ macro_rules! log_error {
 #[macro_export]
 macro_rules! log_debug {
     ($ctx:expr, $event:expr, $($key:ident = $value:expr),* $(,)?) => {
-        log::debug!(
-            "{} {} {}",
-            $ctx,
-            $event,
-            format_args!($(concat!(stringify!($key), "={:?} "), $value),*)
-        );
+        $crate::log_structured!(log::Level::Debug, $ctx, $event, $($key = $value),*)
     };
 }
 
@@ -104,4 +241,59 @@ mod tests {
             "[batch=batch-123] [trace=trace-456]"
         );
     }
+
+    #[test]
+    fn test_json_format_preserves_fields() {
+        crate::logging::capture::install();
+        if log::max_level() < log::LevelFilter::Info {
+            log::set_max_level(log::LevelFilter::Info);
+        }
+        let ctx = LogContext::new("batch-json").with_trace("trace-json");
+
+        set_log_format("json").unwrap();
+        crate::log_info!(ctx, "JSON_FORMAT_PROBE", reason = "a b=c", count = 3, key_id = None::<String>);
+        set_log_format("text").unwrap();
+        crate::log_info!(ctx, "TEXT_FORMAT_PROBE", count = 3);
+
+        let messages = crate::logging::capture::messages();
+        let line = messages.iter().find(|m| m.contains("JSON_FORMAT_PROBE")).unwrap();
+        let record: Value = serde_json::from_str(line).unwrap();
+        assert_eq!(record["batch_id"], "batch-json");
+        assert_eq!(record["trace_id"], "trace-json");
+        assert_eq!(record["event"], "JSON_FORMAT_PROBE");
+        assert_eq!(record["level"], "INFO");
+        assert_eq!(record["fields"]["reason"], "a b=c");
+        assert_eq!(record["fields"]["count"], 3);
+        assert!(record["fields"]["key_id"].is_null());
+
+        let text = messages.iter().find(|m| m.contains("TEXT_FORMAT_PROBE")).unwrap();
+        assert_eq!(text, "[batch=batch-json] [trace=trace-json] TEXT_FORMAT_PROBE count=3 ");
+    }
+
+    #[test]
+    fn test_json_log_line_wraps_plain_records() {
+        let line = json_log_line(
+            &log::Record::builder()
+                .args(format_args!("[batch=b] EVENT k=v w"))
+                .level(log::Level::Warn)
+                .target("cirislens_core::pipeline")
+                .build(),
+        );
+        let record: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(record["level"], "WARN");
+        assert_eq!(record["message"], "[batch=b] EVENT k=v w");
+        assert!(set_log_format("yaml").is_err());
+    }
+
+    #[test]
+    fn test_safe_truncate() {
+        assert_eq!(safe_truncate("abcdef", 3), "abc");
+        assert_eq!(safe_truncate("abc", 10), "abc");
+        assert_eq!(safe_truncate("", 3), "");
+
+        // 3-byte CJK and a 4-byte emoji: byte slicing at 4 would panic.
+        let multibyte = "日本語🦀テキスト";
+        assert_eq!(safe_truncate(multibyte, 4), "日本語🦀");
+        assert_eq!(safe_truncate(multibyte, 0), "");
+    }
 }
@@ -0,0 +1,166 @@
+//! Throughput benchmarking on operator-supplied batches.
+//!
+//! Runs the real pipeline over a batch several times and reports timing
+//! statistics instead of per-trace results, for capacity planning on
+//! production-shaped data.
+//!
+//! Iterations run under an isolated config (`PipelineConfig::isolate`):
+//! no process-wide tracker is written, so every iteration takes the same
+//! path through the pipeline (no known-malformed or verification-cache
+//! shortcuts, no sequence flags) and live ingestion is left untouched.
+//! Allocation stats are not reported: the crate does not install a
+//! counting allocator.
+
+use std::time::Instant;
+
+use serde::Serialize;
+
+use super::context::BatchContext;
+use super::ingestion::process_batch;
+
+/// Timing statistics across all iterations.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkStats {
+    pub iterations: usize,
+    pub traces_per_iteration: usize,
+    pub total_traces: usize,
+    pub accepted_count: usize,
+    pub rejected_count: usize,
+    pub total_micros: u64,
+    pub traces_per_sec: f64,
+    pub p50_trace_micros: u64,
+    pub p95_trace_micros: u64,
+    pub p99_trace_micros: u64,
+    pub max_trace_micros: u64,
+}
+
+/// Run `process_batch` over `events` `iterations` times.
+///
+/// The context is cloned per iteration so every run sees the same config
+/// snapshot.
+pub fn benchmark_batch(ctx: &BatchContext, events: &[String], iterations: usize) -> BenchmarkStats {
+    let mut ctx = ctx.clone();
+    ctx.config.isolate();
+
+    let mut trace_micros = Vec::with_capacity(events.len() * iterations);
+    let mut accepted_count = 0;
+    let mut rejected_count = 0;
+    let started = Instant::now();
+
+    for _ in 0..iterations {
+        let result = process_batch(&ctx, events.to_vec());
+        accepted_count += result.accepted_count;
+        rejected_count += result.rejected_count;
+        trace_micros.extend(result.trace_micros);
+    }
+
+    let total_micros = started.elapsed().as_micros() as u64;
+    trace_micros.sort_unstable();
+    let total_traces = trace_micros.len();
+    let traces_per_sec = if total_micros > 0 {
+        total_traces as f64 * 1_000_000.0 / total_micros as f64
+    } else {
+        0.0
+    };
+
+    log::info!(
+        "[batch={}] BENCHMARK_COMPLETE iterations={} traces={} traces_per_sec={:.1}",
+        ctx.batch_id,
+        iterations,
+        total_traces,
+        traces_per_sec
+    );
+
+    BenchmarkStats {
+        iterations,
+        traces_per_iteration: events.len(),
+        total_traces,
+        accepted_count,
+        rejected_count,
+        total_micros,
+        traces_per_sec,
+        p50_trace_micros: percentile(&trace_micros, 50.0),
+        p95_trace_micros: percentile(&trace_micros, 95.0),
+        p99_trace_micros: percentile(&trace_micros, 99.0),
+        max_trace_micros: trace_micros.last().copied().unwrap_or(0),
+    }
+}
+
+/// Nearest-rank percentile of sorted samples (0 when empty).
+fn percentile(sorted: &[u64], pct: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let samples: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&samples, 50.0), 50);
+        assert_eq!(percentile(&samples, 95.0), 95);
+        assert_eq!(percentile(&samples, 99.0), 99);
+        assert_eq!(percentile(&[], 50.0), 0);
+        assert_eq!(percentile(&[7], 99.0), 7);
+    }
+
+    #[test]
+    fn test_benchmark_small_batch() {
+        let ctx = BatchContext::new("2026-01-01T00:00:00Z", None, "detailed", None);
+        let events = vec![
+            r#"{"trace_id": "bench-1", "components": []}"#.to_string(),
+            "not json".to_string(),
+        ];
+
+        let stats = benchmark_batch(&ctx, &events, 3);
+
+        assert_eq!(stats.iterations, 3);
+        assert_eq!(stats.traces_per_iteration, 2);
+        assert_eq!(stats.total_traces, 6);
+        assert_eq!(stats.accepted_count + stats.rejected_count, 6);
+        assert!(stats.p50_trace_micros <= stats.p95_trace_micros);
+        assert!(stats.p95_trace_micros <= stats.p99_trace_micros);
+        assert!(stats.p99_trace_micros <= stats.max_trace_micros);
+        assert!(stats.max_trace_micros <= stats.total_micros);
+        assert!(stats.traces_per_sec > 0.0);
+    }
+
+    #[test]
+    fn test_benchmark_writes_no_sequence_state() {
+        use base64::{engine::general_purpose, Engine as _};
+        use ed25519_dalek::Signer;
+
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[70; 32]);
+        crate::validation::signature::get_key_cache_mut()
+            .load_key(
+                "bench-seq-test",
+                &general_purpose::STANDARD.encode(signing_key.verifying_key().to_bytes()),
+            )
+            .unwrap();
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {}}]);
+        let canonical = crate::pipeline::ingestion::build_199_canonical(&components, "detailed");
+        let event = serde_json::json!({
+            "trace_id": "bench-seq",
+            "agent_id_hash": "bench-agent",
+            "seq": 1,
+            "components": components,
+            "signature": general_purpose::STANDARD.encode(signing_key.sign(canonical.as_bytes()).to_bytes()),
+            "signature_key_id": "bench-seq-test"
+        })
+        .to_string();
+        let mut ctx = BatchContext::new("2026-01-01T00:00:00Z", None, "detailed", None);
+        ctx.config.sequence.tracked_agents = 100;
+
+        let stats = benchmark_batch(&ctx, std::slice::from_ref(&event), 3);
+        assert_eq!(stats.accepted_count, 3);
+
+        let result = process_batch(&ctx, vec![event]);
+        assert!(result.traces[0].accepted);
+        assert!(!result.traces[0].extracted_metadata.contains_key("seq_regression"));
+    }
+}
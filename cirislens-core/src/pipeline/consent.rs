@@ -0,0 +1,154 @@
+//! Per-agent consent state.
+//!
+//! Multi-tenant deployments track consent per agent rather than per
+//! batch. A DB-loaded map of `agent_id_hash` -> consent state is consulted
+//! for every trace; agents missing from the map fall back to the
+//! batch-level `consent_timestamp`.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+
+use super::context::BatchContext;
+
+/// Consent state of one agent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentConsent {
+    pub consented: bool,
+    /// When consent was given; traces from batches before it are rejected.
+    pub consent_timestamp: Option<DateTime<Utc>>,
+}
+
+lazy_static! {
+    /// agent_id_hash -> consent state, loaded from the database.
+    static ref AGENT_CONSENT: RwLock<HashMap<String, AgentConsent>> = RwLock::new(HashMap::new());
+}
+
+/// Replace the per-agent consent map.
+pub fn set_agent_consent(consent: HashMap<String, AgentConsent>) {
+    let mut current = AGENT_CONSENT.write().expect("Agent consent lock poisoned");
+    *current = consent;
+}
+
+/// Consent state of an agent, if it is in the map.
+pub fn get_agent_consent(agent_id_hash: &str) -> Option<AgentConsent> {
+    AGENT_CONSENT
+        .read()
+        .expect("Agent consent lock poisoned")
+        .get(agent_id_hash)
+        .cloned()
+}
+
+/// Number of agents in the consent map.
+pub fn agent_consent_count() -> usize {
+    AGENT_CONSENT.read().expect("Agent consent lock poisoned").len()
+}
+
+/// Where a trace's consent decision came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsentSource {
+    Agent,
+    Batch,
+}
+
+impl ConsentSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConsentSource::Agent => "agent",
+            ConsentSource::Batch => "batch",
+        }
+    }
+}
+
+/// Check an agent's consent for a batch against the loaded map.
+///
+/// Agents without an entry (or traces without `agent_id_hash`) use the
+/// batch-level consent.
+///
+/// # Errors
+/// `no_consent` when the agent has not consented for this batch.
+pub fn check_agent_consent(
+    agent_id_hash: Option<&str>,
+    ctx: &BatchContext,
+) -> Result<ConsentSource, &'static str> {
+    let consent = agent_id_hash.and_then(get_agent_consent);
+    check_consent(consent.as_ref(), ctx)
+}
+
+/// Check one agent's consent entry for a batch.
+///
+/// An entry must be consented, with a consent timestamp (if any) not
+/// after the batch timestamp, allowing the configured clock skew. Without
+/// an entry the batch-level consent timestamp (if any) is held to the
+/// same bound.
+fn check_consent(
+    consent: Option<&AgentConsent>,
+    ctx: &BatchContext,
+) -> Result<ConsentSource, &'static str> {
+    let consent = match consent {
+        Some(consent) => consent,
+        None => {
+            let batch_consent_in_time = ctx
+                .consent_timestamp
+                .is_none_or(|ts| ctx.not_after_with_skew(ts, ctx.batch_timestamp));
+            return if batch_consent_in_time {
+                Ok(ConsentSource::Batch)
+            } else {
+                Err("no_consent")
+            };
+        }
+    };
+    let consent_in_time = consent
+        .consent_timestamp
+        .is_none_or(|ts| ctx.not_after_with_skew(ts, ctx.batch_timestamp));
+    if consent.consented && consent_in_time {
+        Ok(ConsentSource::Agent)
+    } else {
+        Err("no_consent")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_consent_window() {
+        let ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        let consent = |consented: bool, ts: &str| AgentConsent {
+            consented,
+            consent_timestamp: Some(DateTime::parse_from_rfc3339(ts).unwrap().with_timezone(&Utc)),
+        };
+
+        let early = consent(true, "2026-01-01T00:00:00Z");
+        let within_skew = consent(true, "2026-01-29T00:01:00Z");
+        let late = consent(true, "2026-02-01T00:00:00Z");
+        let withdrawn = consent(false, "2026-01-01T00:00:00Z");
+
+        assert_eq!(check_consent(Some(&early), &ctx), Ok(ConsentSource::Agent));
+        assert_eq!(check_consent(Some(&within_skew), &ctx), Ok(ConsentSource::Agent));
+        assert_eq!(check_consent(Some(&late), &ctx), Err("no_consent"));
+        assert_eq!(check_consent(Some(&withdrawn), &ctx), Err("no_consent"));
+        assert_eq!(check_consent(None, &ctx), Ok(ConsentSource::Batch));
+    }
+
+    #[test]
+    fn test_batch_consent_window() {
+        let batch = |consent: &str| BatchContext::new("2026-01-29T00:00:00Z", Some(consent), "detailed", None);
+
+        let within_skew = batch("2026-01-29T00:04:00Z");
+        assert_eq!(check_consent(None, &within_skew), Ok(ConsentSource::Batch));
+
+        let late = batch("2026-01-29T00:06:00Z");
+        assert_eq!(check_consent(None, &late), Err("no_consent"));
+
+        // An agent entry takes precedence over the batch-level timestamp
+        let early = AgentConsent {
+            consented: true,
+            consent_timestamp: None,
+        };
+        assert_eq!(check_consent(Some(&early), &late), Ok(ConsentSource::Agent));
+    }
+}
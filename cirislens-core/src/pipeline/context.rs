@@ -2,9 +2,15 @@
 //!
 //! Provides batch and trace context for logging and state tracking.
 
-use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
 use uuid::Uuid;
 
+use crate::config::{get_pipeline_config, PipelineConfig};
+
 /// Context for a batch of traces.
 #[derive(Debug, Clone)]
 pub struct BatchContext {
@@ -13,6 +19,89 @@ pub struct BatchContext {
     pub consent_timestamp: Option<DateTime<Utc>>,
     pub trace_level: String,
     pub correlation_metadata: Option<String>,
+    /// Config snapshot taken when the batch was created.
+    pub config: PipelineConfig,
+    /// Run metadata extraction (false = throughput mode: signature and
+    /// routing only).
+    pub extraction_enabled: bool,
+    /// Stop at the first rejected trace (all-or-nothing ingestion).
+    pub fail_fast: bool,
+    /// Dry run: decide acceptance and routing but skip field extraction
+    /// and return empty `extracted_metadata`.
+    pub validate_only: bool,
+    /// Time spent in each stage across the batch's traces.
+    pub stage_timers: StageTimers,
+    /// `batch_hash` of the previous batch in the chain (`batch.hash_chain`);
+    /// None starts a new chain.
+    pub previous_batch_hash: Option<String>,
+}
+
+/// Wall time of a batch and of its main stages (summed over traces), in
+/// microseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct BatchMetrics {
+    pub total_us: u64,
+    pub signature_us: u64,
+    pub pii_scrub_us: u64,
+    pub extraction_us: u64,
+}
+
+/// Per-stage time accumulated while a batch is processed.
+///
+/// Atomic so stages can record through a shared `&BatchContext`.
+#[derive(Debug, Default)]
+pub struct StageTimers {
+    signature_us: AtomicU64,
+    pii_scrub_us: AtomicU64,
+    extraction_us: AtomicU64,
+}
+
+impl StageTimers {
+    fn add(counter: &AtomicU64, started: Instant) {
+        counter.fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Add the time since `started` to signature verification.
+    pub fn record_signature(&self, started: Instant) {
+        Self::add(&self.signature_us, started);
+    }
+
+    /// Add the time since `started` to PII scrubbing.
+    pub fn record_pii_scrub(&self, started: Instant) {
+        Self::add(&self.pii_scrub_us, started);
+    }
+
+    /// Add the time since `started` to metadata extraction.
+    pub fn record_extraction(&self, started: Instant) {
+        Self::add(&self.extraction_us, started);
+    }
+
+    pub fn reset(&self) {
+        self.signature_us.store(0, Ordering::Relaxed);
+        self.pii_scrub_us.store(0, Ordering::Relaxed);
+        self.extraction_us.store(0, Ordering::Relaxed);
+    }
+
+    /// Stage totals so far, with the batch's wall time.
+    pub fn metrics(&self, total_us: u64) -> BatchMetrics {
+        BatchMetrics {
+            total_us,
+            signature_us: self.signature_us.load(Ordering::Relaxed),
+            pii_scrub_us: self.pii_scrub_us.load(Ordering::Relaxed),
+            extraction_us: self.extraction_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Clone for StageTimers {
+    fn clone(&self) -> Self {
+        let metrics = self.metrics(0);
+        Self {
+            signature_us: AtomicU64::new(metrics.signature_us),
+            pii_scrub_us: AtomicU64::new(metrics.pii_scrub_us),
+            extraction_us: AtomicU64::new(metrics.extraction_us),
+        }
+    }
 }
 
 impl BatchContext {
@@ -40,9 +129,26 @@ impl BatchContext {
             consent_timestamp: consent_ts,
             trace_level: trace_level.to_string(),
             correlation_metadata: correlation_metadata.map(|s| s.to_string()),
+            config: get_pipeline_config().clone(),
+            extraction_enabled: true,
+            fail_fast: false,
+            validate_only: false,
+            stage_timers: StageTimers::default(),
+            previous_batch_hash: None,
         }
     }
 
+    /// Check `earlier <= later`, allowing `timestamps.clock_skew_tolerance_secs`
+    /// of skew.
+    ///
+    /// Timestamp guards (consent, staleness, future-batch) should compare
+    /// through this so agents with minor clock drift aren't rejected and
+    /// the tolerance is applied the same way everywhere.
+    pub fn not_after_with_skew(&self, earlier: DateTime<Utc>, later: DateTime<Utc>) -> bool {
+        let tolerance = Duration::seconds(self.config.timestamps.clock_skew_tolerance_secs as i64);
+        earlier <= later + tolerance
+    }
+
     /// Create a trace context for this batch.
     pub fn trace_context(&self, trace_id: &str) -> TraceContext {
         TraceContext {
@@ -66,3 +172,27 @@ impl TraceContext {
         crate::logging::structured::LogContext::new(&self.batch_id).with_trace(&self.trace_id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_skew_tolerance() {
+        let mut ctx = BatchContext::new("2026-01-01T00:00:00Z", None, "detailed", None);
+        ctx.config.timestamps.clock_skew_tolerance_secs = 300;
+        let batch = ctx.batch_timestamp;
+
+        // Trace timestamp slightly ahead of the batch: within tolerance
+        assert!(ctx.not_after_with_skew(batch + Duration::seconds(299), batch));
+        assert!(!ctx.not_after_with_skew(batch + Duration::seconds(301), batch));
+
+        // Symmetric: the other operand skewed the other way
+        assert!(ctx.not_after_with_skew(batch, batch - Duration::seconds(299)));
+        assert!(!ctx.not_after_with_skew(batch, batch - Duration::seconds(301)));
+
+        ctx.config.timestamps.clock_skew_tolerance_secs = 0;
+        assert!(ctx.not_after_with_skew(batch, batch));
+        assert!(!ctx.not_after_with_skew(batch + Duration::seconds(1), batch));
+    }
+}
@@ -0,0 +1,144 @@
+//! Whole-batch decompression.
+//!
+//! Some collectors send the entire batch array as one compressed blob
+//! rather than compressing events individually. The blob is inflated to
+//! the JSON array and split back into raw event strings for
+//! `process_batch`.
+//!
+//! Inflation is capped at `max_batch_events * MAX_TRACE_SIZE` bytes so a
+//! small compression bomb can't exhaust memory, and the inflated array
+//! may hold at most `max_batch_events` events.
+
+use std::io::Read;
+
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use serde_json::Value;
+
+use crate::security::sanitizer::MAX_TRACE_SIZE;
+
+/// Supported `Content-Encoding` values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchEncoding {
+    Gzip,
+    /// HTTP `deflate`: zlib-wrapped, with a raw-deflate fallback for
+    /// clients that send the bare stream.
+    Deflate,
+}
+
+impl BatchEncoding {
+    /// Parse a `Content-Encoding` value (case-insensitive).
+    pub fn parse(encoding: &str) -> Result<Self, String> {
+        match encoding.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Ok(Self::Gzip),
+            "deflate" => Ok(Self::Deflate),
+            other => Err(format!(
+                "unsupported batch encoding {:?} (expected gzip or deflate)",
+                other
+            )),
+        }
+    }
+}
+
+/// Inflate a compressed batch blob into raw event JSON strings.
+///
+/// The blob must inflate to a JSON array whose items are trace objects
+/// (or trace JSON strings).
+pub fn inflate_batch(blob: &[u8], encoding: &str, max_batch_events: usize) -> Result<Vec<String>, String> {
+    let encoding = BatchEncoding::parse(encoding)?;
+    let max_bytes = max_batch_events.saturating_mul(MAX_TRACE_SIZE);
+
+    let inflated = match encoding {
+        BatchEncoding::Gzip => inflate_limited(GzDecoder::new(blob), max_bytes),
+        BatchEncoding::Deflate => inflate_limited(ZlibDecoder::new(blob), max_bytes)
+            .or_else(|_| inflate_limited(DeflateDecoder::new(blob), max_bytes)),
+    }?;
+
+    let batch: Value = serde_json::from_slice(&inflated)
+        .map_err(|e| format!("inflated batch is not valid JSON: {}", e))?;
+    let items = match batch {
+        Value::Array(items) => items,
+        _ => return Err("inflated batch is not a JSON array".to_string()),
+    };
+    if items.len() > max_batch_events {
+        return Err(format!(
+            "batch has {} events, limit is {}",
+            items.len(),
+            max_batch_events
+        ));
+    }
+
+    Ok(items
+        .into_iter()
+        .map(|item| match item {
+            Value::String(s) => s,
+            other => other.to_string(),
+        })
+        .collect())
+}
+
+/// Read a decoder to the end, failing once more than `max_bytes` come out.
+fn inflate_limited(decoder: impl Read, max_bytes: usize) -> Result<Vec<u8>, String> {
+    let mut inflated = Vec::new();
+    decoder
+        .take(max_bytes as u64 + 1)
+        .read_to_end(&mut inflated)
+        .map_err(|e| format!("failed to inflate batch: {}", e))?;
+    if inflated.len() > max_bytes {
+        return Err(format!("inflated batch exceeds {} bytes", max_bytes));
+    }
+    Ok(inflated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    const BATCH: &str = r#"[{"trace_id": "t-1", "components": []}, "{\"trace_id\": \"t-2\"}"]"#;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_inflate_gzip_batch() {
+        let events = inflate_batch(&gzip(BATCH.as_bytes()), "gzip", 10).unwrap();
+        assert_eq!(events.len(), 2);
+        let first: Value = serde_json::from_str(&events[0]).unwrap();
+        assert_eq!(first["trace_id"], "t-1");
+        assert_eq!(events[1], r#"{"trace_id": "t-2"}"#);
+    }
+
+    #[test]
+    fn test_inflate_deflate_batch() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(BATCH.as_bytes()).unwrap();
+        let blob = encoder.finish().unwrap();
+
+        let events = inflate_batch(&blob, "Deflate", 10).unwrap();
+        assert_eq!(events.len(), 2);
+
+        let mut raw = flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+        raw.write_all(BATCH.as_bytes()).unwrap();
+        assert_eq!(inflate_batch(&raw.finish().unwrap(), "deflate", 10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_unsupported_encoding() {
+        let err = inflate_batch(BATCH.as_bytes(), "br", 10).unwrap_err();
+        assert!(err.contains("unsupported batch encoding"), "{}", err);
+    }
+
+    #[test]
+    fn test_batch_limits() {
+        let err = inflate_batch(&gzip(BATCH.as_bytes()), "gzip", 1).unwrap_err();
+        assert!(err.contains("limit is 1"), "{}", err);
+
+        assert!(inflate_limited(BATCH.as_bytes(), 10).is_err());
+        assert!(inflate_batch(&gzip(b"{}"), "gzip", 10).is_err());
+    }
+}
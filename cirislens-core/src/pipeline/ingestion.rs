@@ -10,29 +10,105 @@
 //! 7. Mock detection & routing
 //! 8. Return routing decisions and extracted metadata
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::time::Instant;
 
+use chrono::{DateTime, Utc};
 use serde_json::Value;
 
-use crate::extraction::metadata::extract_trace_metadata;
-use crate::logging::structured::LogContext;
+use crate::config::{
+    DuplicateSignatureAction, ExtractionConfig, OverScrubAction, PiiConfig, PipelineConfig, RequiredFieldCheck,
+    UnsupportedSchemaAction, WideComponentAction,
+};
+use crate::extraction::metadata::{
+    cap_metadata_entries, component_event_type, extract_models_used, extract_trace_metadata_with_issues,
+    ExtractionIssues,
+};
+use crate::logging::structured::{safe_truncate, LogContext};
+use crate::pipeline::consent::check_agent_consent;
+use crate::pipeline::known_malformed::{
+    is_known_malformed, is_payload_intrinsic, payload_hash, remember_malformed,
+};
+use crate::pipeline::recent_rejections::{record_rejection, RejectionRecord};
+use crate::pipeline::sequence::{observe_agent_sequence, SequenceStatus};
 use crate::routing::decision::{determine_routing, RoutingDecision};
-use crate::security::pii::scrub_pii;
-use crate::security::sanitizer::sanitize_trace;
-use crate::validation::schema::{get_schema_cache, SchemaValidationResult};
-use crate::validation::signature::verify_signature;
+use crate::security::pii::{
+    contains_any_field, get_always_scrub_fields, scrub_pii_with_mode, PiiScrubResult,
+};
+use crate::security::sanitizer::{sanitize_trace_with, SanitizeOptions, XssMode, MAX_JSON_DEPTH};
+use crate::validation::schema::{get_schema_cache, SchemaCache, SchemaValidationResult};
+use crate::validation::replay_detection::{is_replayed_signature, remember_verified_signature};
+use crate::validation::verification_cache::{
+    cache_verification, get_cached_verification, verification_cache_key,
+};
+use crate::validation::signature::{
+    compute_hash, get_key_cache, record_format_attempt, record_key_activity, verify_signature_with_mode,
+    verify_signatures_batch, BatchVerifyItem, SignatureMode,
+};
 
-use super::context::BatchContext;
+use super::context::{BatchContext, BatchMetrics};
 
 /// Result of processing a single trace.
 #[derive(Debug)]
 pub struct TraceResult {
     pub trace_id: String,
-    pub destination: String, // production, mock, connectivity, malformed
+    pub destination: String, // production, mock, connectivity, malformed, review, quarantine, duplicate, sampled_out
     pub schema_version: Option<String>,
     pub accepted: bool,
     pub rejection_reason: Option<String>,
     pub extracted_metadata: HashMap<String, String>,
+    pub extraction_issues: ExtractionIssues,
+    /// Canonical format the signature verified with.
+    pub signature_format: Option<String>,
+    /// Canonical formats attempted during signature verification.
+    pub signature_formats_tried: Vec<String>,
+}
+
+impl TraceResult {
+    /// A rejected trace with no metadata.
+    pub fn rejected(
+        trace_id: impl Into<String>,
+        destination: &str,
+        schema_version: Option<String>,
+        reason: impl Into<String>,
+    ) -> Self {
+        Self {
+            trace_id: trace_id.into(),
+            destination: destination.to_string(),
+            schema_version,
+            accepted: false,
+            rejection_reason: Some(reason.into()),
+            extracted_metadata: HashMap::new(),
+            extraction_issues: ExtractionIssues::default(),
+            signature_format: None,
+            signature_formats_tried: Vec::new(),
+        }
+    }
+
+    /// Replace the extracted metadata.
+    pub fn with_metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.extracted_metadata = metadata;
+        self
+    }
+
+    /// Accepted and routed to a table. Sampled-out traces are accepted
+    /// but dropped.
+    pub fn is_stored(&self) -> bool {
+        self.accepted && self.destination != "sampled_out"
+    }
+
+    /// Extracted metadata ordered by column name.
+    pub fn sorted_metadata(&self) -> BTreeMap<&str, &str> {
+        self.extracted_metadata
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect()
+    }
+
+    /// Extracted metadata as a JSON object with keys in sorted order.
+    pub fn extracted_metadata_json(&self) -> String {
+        serde_json::to_string(&self.sorted_metadata()).unwrap_or_else(|_| "{}".to_string())
+    }
 }
 
 /// Result of processing a batch.
@@ -41,7 +117,31 @@ pub struct BatchResult {
     pub received_count: usize,
     pub accepted_count: usize,
     pub rejected_count: usize,
+    /// Rejected repeats of a trace_id seen earlier in the batch
+    /// (included in `rejected_count`).
+    pub duplicate_count: usize,
     pub traces: Vec<TraceResult>,
+    /// Total length of the raw event strings, in bytes.
+    pub bytes_received: usize,
+    /// Total length of the extracted metadata values of stored traces
+    /// (post-scrub/sanitize; trace bodies themselves are not returned).
+    pub bytes_stored: usize,
+    /// Batch or slowest trace exceeded the backpressure thresholds.
+    pub processing_overloaded: bool,
+    /// Backoff the API layer should ask the agent for (0 when not overloaded).
+    pub suggested_backoff_ms: u64,
+    /// Processing time of each trace, in microseconds, in input order.
+    pub trace_micros: Vec<u64>,
+    /// Processing stopped at the first rejection (`fail_fast`); `traces`
+    /// ends with the rejected trace and later events were not processed.
+    pub aborted: bool,
+    /// Extraction issues summed over all traces, by issue type.
+    pub extraction_issues: ExtractionIssues,
+    /// Wall time and per-stage time for the batch.
+    pub metrics: BatchMetrics,
+    /// Hash over the previous batch's hash and this batch's trace content
+    /// hashes (`batch.hash_chain`); pass it to the next batch.
+    pub batch_hash: Option<String>,
 }
 
 /// Process a batch of traces.
@@ -51,71 +151,780 @@ pub fn process_batch(ctx: &BatchContext, events: Vec<String>) -> BatchResult {
     let mut results = Vec::new();
     let mut accepted = 0;
     let mut rejected = 0;
+    let mut duplicates = 0;
+    let mut seen_trace_ids: HashSet<String> = HashSet::new();
+    let batch_started = Instant::now();
+    let mut max_trace_ms = 0;
+    let bytes_received: usize = events.iter().map(|e| e.len()).sum();
+    let mut trace_micros = Vec::with_capacity(events.len());
+    let mut aborted = false;
+    let mut extraction_issues = ExtractionIssues::default();
+    ctx.stage_timers.reset();
+
+    // Repeats of a trace_id would collapse in storage anyway; skip
+    // their parsing and signature work
+    let duplicate_ids: Vec<Option<String>> = events
+        .iter()
+        .map(|event_json| declared_trace_id(event_json).filter(|id| !seen_trace_ids.insert(id.clone())))
+        .collect();
+
+    let preverified = if ctx.config.signature.batch_verify {
+        let started = Instant::now();
+        // Only events that get past the fast rejections
+        let eligible: Vec<bool> = events
+            .iter()
+            .zip(&duplicate_ids)
+            .map(|(event_json, duplicate_id)| {
+                duplicate_id.is_none() && !rejected_before_parse(ctx, event_json)
+            })
+            .collect();
+        let preverified = batch_preverify(ctx, &events, &eligible, &get_schema_cache());
+        ctx.stage_timers.record_signature(started);
+        preverified
+    } else {
+        vec![false; events.len()]
+    };
 
-    for event_json in &events {
-        let result = process_single_trace(ctx, event_json);
+    for ((event_json, &preverified), duplicate_id) in events.iter().zip(&preverified).zip(duplicate_ids) {
+        let trace_started = Instant::now();
+
+        let result = if let Some(trace_id) = duplicate_id {
+            log::info!(
+                "[batch={}] [trace={}] TRACE_DUPLICATE_IN_BATCH",
+                ctx.batch_id,
+                trace_id
+            );
+            duplicates += 1;
+            TraceResult::rejected(trace_id, "duplicate", None, "duplicate_in_batch")
+        } else {
+            process_single_trace_guarded(ctx, event_json, preverified)
+        };
+        if !result.accepted && ctx.config.diagnostics.recent_rejections > 0 {
+            let record = RejectionRecord::from_event(
+                event_json,
+                &result.trace_id,
+                result.rejection_reason.as_deref().unwrap_or("unknown"),
+                ctx.config.diagnostics.rejection_preview_chars,
+            );
+            record_rejection(record, ctx.config.diagnostics.recent_rejections);
+        }
+        let trace_elapsed = trace_started.elapsed();
+        max_trace_ms = max_trace_ms.max(trace_elapsed.as_millis() as u64);
+        trace_micros.push(trace_elapsed.as_micros() as u64);
 
         if result.accepted {
             accepted += 1;
         } else {
             rejected += 1;
         }
+        extraction_issues.merge(&result.extraction_issues);
 
+        let stop = ctx.fail_fast && !result.accepted;
         results.push(result);
+        if stop {
+            aborted = true;
+            log::warn!(
+                "[batch={}] BATCH_ABORTED processed={} remaining={} reason=fail_fast",
+                ctx.batch_id,
+                results.len(),
+                events.len() - results.len()
+            );
+            break;
+        }
+    }
+
+    let duplicate_action = ctx.config.signature.duplicate_signature_action;
+    if duplicate_action != DuplicateSignatureAction::Ignore {
+        flag_duplicate_signatures(&events, &mut results, duplicate_action, ctx);
+    }
+    if ctx.config.batch.flag_consent_conflicts {
+        flag_consent_conflicts(&events, &mut results, ctx);
+    }
+
+    if ctx.validate_only {
+        // Dry run: report decisions only
+        for result in &mut results {
+            result.extracted_metadata.clear();
+        }
     }
+    let bytes_stored: usize = results
+        .iter()
+        .filter(|result| result.is_stored())
+        .flat_map(|result| result.extracted_metadata.values())
+        .map(String::len)
+        .sum();
 
     log::info!(
-        "[batch={}] BATCH_COMPLETE received={} accepted={} rejected={}",
+        "[batch={}] BATCH_COMPLETE received={} accepted={} rejected={} duplicates={} bytes_received={} bytes_stored={}",
         ctx.batch_id,
         events.len(),
         accepted,
-        rejected
+        rejected,
+        duplicates,
+        bytes_received,
+        bytes_stored
     );
 
+    if !extraction_issues.is_empty() {
+        log::warn!(
+            "[batch={}] BATCH_EXTRACTION_ISSUES issues={}",
+            ctx.batch_id,
+            serde_json::to_string(&extraction_issues).unwrap_or_default()
+        );
+    }
+
+    // Backpressure signal for the API layer
+    let batch_ms = batch_started.elapsed().as_millis() as u64;
+    let backpressure = &ctx.config.backpressure;
+    let processing_overloaded =
+        batch_ms > backpressure.max_batch_ms || max_trace_ms > backpressure.max_trace_ms;
+    if processing_overloaded {
+        log::warn!(
+            "[batch={}] BATCH_OVERLOADED batch_ms={} max_trace_ms={} backoff_ms={}",
+            ctx.batch_id,
+            batch_ms,
+            max_trace_ms,
+            backpressure.backoff_ms
+        );
+    }
+
+    let batch_hash = ctx.config.batch.hash_chain.then(|| {
+        let trace_hashes: Vec<String> = events[..results.len()].iter().map(|e| compute_hash(e)).collect();
+        chain_batch_hash(ctx.previous_batch_hash.as_deref(), &trace_hashes)
+    });
+    if let Some(ref hash) = batch_hash {
+        log::info!(
+            "[batch={}] BATCH_HASH_CHAINED previous={:?} hash={}",
+            ctx.batch_id,
+            ctx.previous_batch_hash,
+            hash
+        );
+    }
+
     BatchResult {
         received_count: events.len(),
         accepted_count: accepted,
         rejected_count: rejected,
+        duplicate_count: duplicates,
         traces: results,
+        bytes_received,
+        bytes_stored,
+        processing_overloaded,
+        suggested_backoff_ms: if processing_overloaded {
+            backpressure.backoff_ms
+        } else {
+            0
+        },
+        trace_micros,
+        aborted,
+        extraction_issues,
+        metrics: ctx.stage_timers.metrics(batch_started.elapsed().as_micros() as u64),
+        batch_hash,
+    }
+}
+
+/// Link a batch into the hash chain: SHA-256 over the previous batch's
+/// hash (empty for the first batch) and the batch's trace content
+/// hashes, in order.
+pub fn chain_batch_hash(previous: Option<&str>, trace_hashes: &[String]) -> String {
+    let mut content = previous.unwrap_or_default().to_string();
+    for hash in trace_hashes {
+        content.push('\n');
+        content.push_str(hash);
+    }
+    compute_hash(&content)
+}
+
+/// The `trace_id` a raw event declares, if it has one.
+///
+/// Streams the top-level object and stops at the `trace_id` key, so the
+/// rest of the event is left to `process_single_trace` to parse.
+fn declared_trace_id(event_json: &str) -> Option<String> {
+    use serde::de::{DeserializeSeed, Deserializer, Error, IgnoredAny, MapAccess, Visitor};
+
+    struct TraceIdSeed<'a>(&'a mut Option<String>);
+
+    impl<'de> DeserializeSeed<'de> for TraceIdSeed<'_> {
+        type Value = ();
+
+        fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+            deserializer.deserialize_map(self)
+        }
+    }
+
+    impl<'de> Visitor<'de> for TraceIdSeed<'_> {
+        type Value = ();
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a trace object")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+            while let Some(key) = map.next_key::<String>()? {
+                if key == "trace_id" {
+                    *self.0 = map.next_value()?;
+                    // Abandon the parse; the caller only wants the id
+                    return Err(A::Error::custom("trace_id read"));
+                }
+                map.next_value::<IgnoredAny>()?;
+            }
+            Ok(())
+        }
+    }
+
+    let mut trace_id = None;
+    let mut deserializer = serde_json::Deserializer::from_str(event_json);
+    let _ = TraceIdSeed(&mut trace_id).deserialize(&mut deserializer);
+    trace_id
+}
+
+/// Signature strings carried by a raw event (top-level and `signatures`).
+fn event_signatures(event_json: &str) -> HashSet<String> {
+    match serde_json::from_str::<Value>(event_json) {
+        Ok(trace) => trace_signatures(&trace).into_iter().map(|s| s.to_string()).collect(),
+        Err(_) => HashSet::new(),
+    }
+}
+
+/// Signature strings carried by a trace (top-level and `signatures`).
+fn trace_signatures(trace: &Value) -> Vec<&str> {
+    let entries = trace
+        .get("signatures")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.get("signature"));
+    trace
+        .get("signature")
+        .into_iter()
+        .chain(entries)
+        .filter_map(|v| v.as_str())
+        .collect()
+}
+
+/// Mark accepted traces whose signature also appears on another trace in
+/// the batch with `duplicate_signature_in_batch`, routing them to review
+/// under `DuplicateSignatureAction::Review`.
+fn flag_duplicate_signatures(
+    events: &[String],
+    results: &mut [TraceResult],
+    action: DuplicateSignatureAction,
+    ctx: &BatchContext,
+) {
+    let signatures: Vec<HashSet<String>> = events
+        .iter()
+        .zip(results.iter())
+        .map(|(event_json, result)| {
+            if result.accepted {
+                event_signatures(event_json)
+            } else {
+                HashSet::new()
+            }
+        })
+        .collect();
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for sig in signatures.iter().flatten() {
+        *counts.entry(sig.as_str()).or_default() += 1;
+    }
+
+    for (result, sigs) in results.iter_mut().zip(&signatures) {
+        if !sigs.iter().any(|sig| counts[sig.as_str()] > 1) {
+            continue;
+        }
+        log::warn!(
+            "[batch={}] [trace={}] SIGNATURE_DUPLICATE_IN_BATCH action={:?}",
+            ctx.batch_id,
+            result.trace_id,
+            action
+        );
+        result
+            .extracted_metadata
+            .insert("duplicate_signature_in_batch".to_string(), "true".to_string());
+        if action == DuplicateSignatureAction::Review {
+            result.destination = "review".to_string();
+            result
+                .extracted_metadata
+                .insert("is_production".to_string(), "false".to_string());
+        }
+    }
+}
+
+/// The `consent_timestamp` a raw event embeds, normalized to UTC where it
+/// parses as RFC 3339 (so `Z` and `+00:00` forms agree).
+fn embedded_consent_timestamp(event_json: &str) -> Option<String> {
+    #[derive(serde::Deserialize)]
+    struct ConsentOnly {
+        consent_timestamp: Option<String>,
+    }
+    let raw = serde_json::from_str::<ConsentOnly>(event_json).ok()?.consent_timestamp?;
+    Some(
+        DateTime::parse_from_rfc3339(&raw)
+            .map(|ts| ts.with_timezone(&Utc).to_rfc3339())
+            .unwrap_or(raw),
+    )
+}
+
+/// Mark accepted traces with `consent_conflict` when the traces in a batch
+/// embed differing consent timestamps.
+///
+/// Consent is decided per batch (or per agent), so traces disagreeing
+/// about it point at a client bug rather than anything to reject.
+fn flag_consent_conflicts(events: &[String], results: &mut [TraceResult], ctx: &BatchContext) {
+    let embedded: Vec<Option<String>> = events
+        .iter()
+        .zip(results.iter())
+        .map(|(event_json, result)| {
+            result
+                .accepted
+                .then(|| embedded_consent_timestamp(event_json))
+                .flatten()
+        })
+        .collect();
+
+    let distinct: BTreeSet<&str> = embedded.iter().flatten().map(|ts| ts.as_str()).collect();
+    if distinct.len() < 2 {
+        return;
+    }
+
+    let mut flagged = 0;
+    for (result, consent) in results.iter_mut().zip(&embedded) {
+        if consent.is_some() {
+            result
+                .extracted_metadata
+                .insert("consent_conflict".to_string(), "true".to_string());
+            flagged += 1;
+        }
+    }
+    log::warn!(
+        "[batch={}] BATCH_CONSENT_CONFLICT distinct_consent_timestamps={} traces={} batch_consent={:?}",
+        ctx.batch_id,
+        distinct.len(),
+        flagged,
+        ctx.consent_timestamp.map(|ts| ts.to_rfc3339())
+    );
+}
+
+/// Trace id that makes `process_single_trace` panic, for exercising the
+/// panic guard in tests.
+#[cfg(test)]
+const TEST_PANIC_TRACE_ID: &str = "test-hook-panic";
+
+/// Trace id that makes `process_single_trace` sleep, for exercising the
+/// backpressure thresholds in tests.
+#[cfg(test)]
+const TEST_SLOW_TRACE_ID: &str = "test-hook-slow";
+
+/// Process a single trace, converting a panic into a malformed result.
+///
+/// A bug in any stage (regex, slicing, ...) must cost one trace, not the
+/// whole batch — an unwinding panic would otherwise surface as a PyO3
+/// panic and drop every trace in the request.
+///
+/// Payloads identical to a recently rejected malformed trace are rejected
+/// up front with reason `known_malformed`, skipping the full pipeline.
+/// Only payload-intrinsic rejections are remembered; consent, timestamp
+/// and key validity rejections depend on the batch.
+fn process_single_trace_guarded(
+    batch_ctx: &BatchContext,
+    event_json: &str,
+    preverified: bool,
+) -> TraceResult {
+    let capacity = batch_ctx.config.fast_reject.known_malformed_capacity;
+    let content_hash = if capacity > 0 {
+        let hash = payload_hash(&batch_ctx.trace_level, event_json);
+        if is_known_malformed(&hash) {
+            log::info!(
+                "[batch={}] TRACE_KNOWN_MALFORMED hash={}",
+                batch_ctx.batch_id,
+                safe_truncate(&hash, 16)
+            );
+            return TraceResult::rejected("unknown", "malformed", None, "known_malformed");
+        }
+        Some(hash)
+    } else {
+        None
+    };
+
+    let result = process_single_trace_catching(batch_ctx, event_json, preverified);
+    // Panics are bugs, not payload faults, so they are never remembered
+    let remember = result
+        .rejection_reason
+        .as_deref()
+        .is_some_and(|reason| reason != "internal_panic" && is_payload_intrinsic(reason));
+    if let Some(hash) = content_hash {
+        if result.destination == "malformed" && remember {
+            remember_malformed(hash, capacity);
+        }
+    }
+    result
+}
+
+/// Whether `process_single_trace_guarded` rejects an event without
+/// parsing it: a known-malformed payload or excessive nesting.
+fn rejected_before_parse(batch_ctx: &BatchContext, event_json: &str) -> bool {
+    let fast_reject = &batch_ctx.config.fast_reject;
+    (fast_reject.known_malformed_capacity > 0
+        && is_known_malformed(&payload_hash(&batch_ctx.trace_level, event_json)))
+        || (fast_reject.max_nesting_depth > 0
+            && exceeds_nesting_depth(event_json, fast_reject.max_nesting_depth))
+}
+
+/// Replay a stored trace through the current pipeline.
+///
+/// Identical to single-trace processing, except that the known-malformed
+/// fast path is bypassed (the trace being replayed is usually in it) and
+/// the result is tagged `replayed=true`. Intended for re-running traces
+/// from the malformed table after a key, schema or config fix.
+pub fn reprocess_event(batch_ctx: &BatchContext, event_json: &str) -> TraceResult {
+    let mut result = process_single_trace_catching(batch_ctx, event_json, false);
+    log::info!(
+        "[batch={}] [trace={}] TRACE_REPLAYED destination={} accepted={}",
+        batch_ctx.batch_id,
+        result.trace_id,
+        result.destination,
+        result.accepted
+    );
+    result
+        .extracted_metadata
+        .insert("replayed".to_string(), "true".to_string());
+    result
+}
+
+/// Run `process_single_trace`, converting a panic into a malformed result.
+fn process_single_trace_catching(
+    batch_ctx: &BatchContext,
+    event_json: &str,
+    preverified: bool,
+) -> TraceResult {
+    let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        process_single_trace(batch_ctx, event_json, preverified)
+    }));
+
+    match outcome {
+        Ok(result) => result,
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "non-string panic payload".to_string());
+            let trace_id = serde_json::from_str::<Value>(event_json)
+                .ok()
+                .and_then(|v| v.get("trace_id").and_then(|t| t.as_str()).map(|t| t.to_string()))
+                .unwrap_or_else(|| "unknown".to_string());
+
+            log::error!(
+                "[batch={}] [trace={}] TRACE_PANIC message={}",
+                batch_ctx.batch_id,
+                trace_id,
+                message
+            );
+
+            TraceResult::rejected(trace_id, "malformed", None, "internal_panic")
+        }
+    }
+}
+
+/// Stable trace id for a trace without one: a UUIDv5 of its canonical
+/// (sorted, compact) content hash.
+///
+/// A literal `"unknown"` would collide across all id-less traces and
+/// break `ON CONFLICT (trace_id)` dedup; this keeps distinct traces
+/// distinct while identical re-sends still dedup.
+fn derive_trace_id(trace: &Value) -> String {
+    let content_hash = crate::validation::signature::compute_hash(&sort_and_serialize_compact(trace));
+    let name = format!("cirislens:trace:{}", content_hash);
+    uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, name.as_bytes()).to_string()
+}
+
+/// Class of a JSON parse failure, reported as the `error_class` metadata
+/// of the malformed result. The rejection reason is derived from it too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JsonErrorClass {
+    /// The body ends mid-value.
+    Truncated,
+    /// A complete value followed by more data.
+    TrailingData,
+    /// A `\u` escape that doesn't encode valid UTF-8 (lone surrogate,
+    /// invalid code point).
+    InvalidUtf8,
+    /// Any other syntax error.
+    UnexpectedToken,
+}
+
+impl JsonErrorClass {
+    /// Classify from serde's error category and position, never its
+    /// message text.
+    fn classify(input: &str, e: &serde_json::Error) -> Self {
+        if e.is_eof() {
+            return JsonErrorClass::Truncated;
+        }
+        let mut values = serde_json::Deserializer::from_str(input).into_iter::<Value>();
+        if matches!(values.next(), Some(Ok(_))) {
+            return JsonErrorClass::TrailingData;
+        }
+        let prefix = input.get(..json_error_offset(input, e)).unwrap_or(input);
+        if ends_in_unicode_escape(prefix) {
+            JsonErrorClass::InvalidUtf8
+        } else {
+            JsonErrorClass::UnexpectedToken
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            JsonErrorClass::Truncated => "truncated",
+            JsonErrorClass::TrailingData => "trailing_data",
+            JsonErrorClass::InvalidUtf8 => "invalid_utf8",
+            JsonErrorClass::UnexpectedToken => "unexpected_token",
+        }
+    }
+
+    /// Rejection reason prefix.
+    ///
+    /// A body that ends mid-value (`truncated_json`) usually means the client
+    /// or a proxy cut the request short and is worth retrying; anything else
+    /// (`invalid_json`) is a malformed payload that will fail again.
+    fn reason(self) -> &'static str {
+        match self {
+            JsonErrorClass::Truncated => "truncated_json",
+            _ => "invalid_json",
+        }
+    }
+}
+
+/// Whether `prefix` ends in a `\uXXXX` escape, optionally plus one more
+/// byte. serde reports a bad escape there: at its last hex digit, or just
+/// past it when a leading surrogate isn't followed by a trailing one.
+fn ends_in_unicode_escape(prefix: &str) -> bool {
+    let Some(pos) = prefix.rfind("\\u") else {
+        return false;
+    };
+    let bytes = prefix.as_bytes();
+    // An odd run of backslashes means the last one starts an escape
+    let backslashes = bytes[..=pos].iter().rev().take_while(|&&b| b == b'\\').count();
+    let hex = &bytes[pos + 2..];
+    backslashes % 2 == 1 && (4..=5).contains(&hex.len()) && hex[..4].iter().all(u8::is_ascii_hexdigit)
+}
+
+/// Byte offset of a parse error's (1-based) line and column in `input`.
+fn json_error_offset(input: &str, e: &serde_json::Error) -> usize {
+    let line_start: usize = input
+        .split_inclusive('\n')
+        .take(e.line().saturating_sub(1))
+        .map(str::len)
+        .sum();
+    (line_start + e.column()).min(input.len())
+}
+
+/// Check whether a raw JSON body nests objects/arrays deeper than
+/// `max_depth`, without parsing it.
+///
+/// Brackets inside string literals are skipped. Malformed input is left
+/// to the parser.
+fn exceeds_nesting_depth(event_json: &str, max_depth: usize) -> bool {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for byte in event_json.bytes() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                if depth > max_depth {
+                    return true;
+                }
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Check a trace's timestamps against the batch timestamp and the server
+/// clock, allowing the configured clock skew.
+///
+/// # Errors
+/// `future_batch` when the batch is timestamped after the server clock,
+/// `future_timestamp` when a component is timestamped after the batch, and
+/// `stale_trace` when one is older than `timestamps.max_trace_age_secs`.
+fn check_trace_timestamps(trace: &Value, ctx: &BatchContext) -> Result<(), &'static str> {
+    let timestamps = &ctx.config.timestamps;
+    if timestamps.reject_future_timestamps && !ctx.not_after_with_skew(ctx.batch_timestamp, Utc::now()) {
+        return Err("future_batch");
+    }
+    let component_times = trace
+        .get("components")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|component| component.get("timestamp").and_then(|t| t.as_str()))
+        .filter_map(|ts| DateTime::parse_from_rfc3339(ts).ok())
+        .map(|ts| ts.with_timezone(&Utc));
+    let oldest_allowed = ctx.batch_timestamp - chrono::Duration::seconds(timestamps.max_trace_age_secs as i64);
+    for ts in component_times {
+        if timestamps.reject_future_timestamps && !ctx.not_after_with_skew(ts, ctx.batch_timestamp) {
+            return Err("future_timestamp");
+        }
+        if timestamps.max_trace_age_secs > 0 && !ctx.not_after_with_skew(oldest_allowed, ts) {
+            return Err("stale_trace");
+        }
+    }
+    Ok(())
+}
+
+/// Whether any component carries more than `max_fields` object keys,
+/// counted at every depth. Counting stops once the limit is passed.
+fn component_too_wide(trace: &Value, max_fields: usize) -> bool {
+    fn count_keys(value: &Value, count: &mut usize, max_fields: usize) -> bool {
+        match value {
+            Value::Object(map) => {
+                *count += map.len();
+                *count > max_fields || map.values().any(|v| count_keys(v, count, max_fields))
+            }
+            Value::Array(arr) => arr.iter().any(|v| count_keys(v, count, max_fields)),
+            _ => false,
+        }
     }
+
+    trace
+        .get("components")
+        .and_then(|c| c.as_array())
+        .is_some_and(|components| {
+            components.iter().any(|component| count_keys(component, &mut 0, max_fields))
+        })
 }
 
 /// Process a single trace.
-fn process_single_trace(batch_ctx: &BatchContext, event_json: &str) -> TraceResult {
+///
+/// `preverified` marks a trace whose 1.9.9 signature already verified in
+/// `batch_preverify`.
+fn process_single_trace(batch_ctx: &BatchContext, event_json: &str, preverified: bool) -> TraceResult {
+    // Reject pathological nesting before the parser recurses into it
+    let max_depth = batch_ctx.config.fast_reject.max_nesting_depth;
+    if max_depth > 0 && exceeds_nesting_depth(event_json, max_depth) {
+        log::warn!(
+            "[batch={}] TRACE_PARSE_FAILED reason=excessive_nesting max_depth={}",
+            batch_ctx.batch_id,
+            max_depth
+        );
+        return TraceResult::rejected("unknown", "malformed", None, "excessive_nesting");
+    }
+
     // Parse JSON
-    let trace: Value = match serde_json::from_str(event_json) {
+    let mut trace: Value = match serde_json::from_str(event_json) {
         Ok(v) => v,
         Err(e) => {
+            let error_class = JsonErrorClass::classify(event_json, &e);
+            let reason = error_class.reason();
+            let offset = json_error_offset(event_json, &e);
             log::warn!(
-                "[batch={}] TRACE_PARSE_FAILED error={}",
+                "[batch={}] TRACE_PARSE_FAILED reason={} class={} offset={} error={}",
                 batch_ctx.batch_id,
+                reason,
+                error_class.as_str(),
+                offset,
                 e
             );
-            return TraceResult {
-                trace_id: "unknown".to_string(),
-                destination: "malformed".to_string(),
-                schema_version: None,
-                accepted: false,
-                rejection_reason: Some(format!("JSON parse error: {}", e)),
-                extracted_metadata: HashMap::new(),
-            };
+            return TraceResult::rejected("unknown", "malformed", None, format!("{}: {}", reason, e))
+                .with_metadata(HashMap::from([
+                    ("error_class".to_string(), error_class.as_str().to_string()),
+                    ("error_offset".to_string(), offset.to_string()),
+                ]));
         }
     };
 
-    // Extract trace_id
-    let trace_id = trace
+    // Extract trace_id (derived from the content when absent)
+    let trace_id = match trace
         .get("trace_id")
         .and_then(|v| v.as_str())
-        .unwrap_or("unknown")
-        .to_string();
+        .filter(|id| !id.is_empty())
+    {
+        Some(id) => id.to_string(),
+        None => {
+            let derived = derive_trace_id(&trace);
+            log::info!(
+                "[batch={}] TRACE_ID_DERIVED trace_id={}",
+                batch_ctx.batch_id,
+                derived
+            );
+            derived
+        }
+    };
+
+    #[cfg(test)]
+    if trace_id == TEST_PANIC_TRACE_ID {
+        panic!("test hook panic");
+    }
+    #[cfg(test)]
+    if trace_id == TEST_SLOW_TRACE_ID {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
 
     let trace_ctx = batch_ctx.trace_context(&trace_id);
     let log_ctx = trace_ctx.log_context();
 
     log::debug!("{} TRACE_PROCESS_START", log_ctx);
 
+    // [0] CONSENT (per-agent map, batch-level consent as the fallback)
+    let agent_id_hash = trace.get("agent_id_hash").and_then(|v| v.as_str());
+    let consent_source = match check_agent_consent(agent_id_hash, batch_ctx) {
+        Ok(source) => source,
+        Err(reason) => {
+            log::warn!(
+                "{} CONSENT_REJECTED agent_id_hash={:?} reason={}",
+                log_ctx,
+                agent_id_hash,
+                reason
+            );
+            return TraceResult::rejected(trace_id, "malformed", None, reason);
+        }
+    };
+
+    // Base64-wrapped components (only when a loaded schema allows them)
+    let components_decoded = match decode_base64_components(&mut trace, &get_schema_cache()) {
+        Ok(decoded) => decoded,
+        Err(reason) => {
+            log::warn!("{} COMPONENTS_DECODE_FAILED reason={}", log_ctx, reason);
+            return TraceResult::rejected(trace_id, "malformed", None, reason);
+        }
+    };
+
+    // Very wide components slow extraction and sanitization
+    let fast_reject = &batch_ctx.config.fast_reject;
+    let too_wide = fast_reject.max_fields_per_component > 0
+        && component_too_wide(&trace, fast_reject.max_fields_per_component);
+    if too_wide {
+        log::warn!(
+            "{} COMPONENT_TOO_WIDE max_fields={} action={:?}",
+            log_ctx,
+            fast_reject.max_fields_per_component,
+            fast_reject.wide_component_action
+        );
+        if fast_reject.wide_component_action == WideComponentAction::Reject {
+            return TraceResult::rejected(trace_id, "malformed", None, "component_too_wide");
+        }
+    }
+
+    // Staleness and future timestamps, within the clock skew tolerance
+    if let Err(reason) = check_trace_timestamps(&trace, batch_ctx) {
+        log::warn!("{} TIMESTAMP_REJECTED reason={}", log_ctx, reason);
+        return TraceResult::rejected(trace_id, "malformed", None, reason);
+    }
+
     // [1] SCHEMA VALIDATION
-    let schema_result = validate_schema(&trace, &log_ctx);
+    let schema_result = validate_schema(&trace, &batch_ctx.config, &log_ctx);
 
     if !schema_result.valid {
         log::warn!(
@@ -123,17 +932,43 @@ fn process_single_trace(batch_ctx: &BatchContext, event_json: &str) -> TraceResu
             log_ctx,
             schema_result.reason
         );
-        return TraceResult {
+        return TraceResult::rejected(
             trace_id,
-            destination: "malformed".to_string(),
-            schema_version: None,
-            accepted: false,
-            rejection_reason: schema_result.reason,
-            extracted_metadata: HashMap::new(),
-        };
+            "malformed",
+            None,
+            schema_result.reason.unwrap_or_default(),
+        );
     }
 
     let schema_version = schema_result.version.unwrap_or_default();
+    let declared_unsupported = unsupported_declared_version(&trace, &get_schema_cache());
+    if let Some(ref declared) = declared_unsupported {
+        log::warn!(
+            "{} SCHEMA_VERSION_UNSUPPORTED declared={} detected={} action={:?}",
+            log_ctx,
+            declared,
+            schema_version,
+            batch_ctx.config.routing.unsupported_schema_action
+        );
+    }
+
+    if components_decoded
+        && !get_schema_cache()
+            .get_schema(&schema_version)
+            .is_some_and(|schema| schema.components_base64)
+    {
+        log::warn!(
+            "{} COMPONENTS_DECODE_REJECTED schema_version={} reason=base64_components_not_allowed",
+            log_ctx,
+            schema_version
+        );
+        return TraceResult::rejected(
+            trace_id,
+            "malformed",
+            Some(schema_version),
+            "base64_components_not_allowed",
+        );
+    }
 
     // [2] CONNECTIVITY EVENT HANDLING
     if schema_version == "connectivity" {
@@ -148,13 +983,51 @@ fn process_single_trace(batch_ctx: &BatchContext, event_json: &str) -> TraceResu
             schema_version: Some(schema_version),
             accepted: true,
             rejection_reason: None,
-            extracted_metadata: extract_connectivity_metadata(&trace),
+            extracted_metadata: connectivity_metadata(
+                &trace,
+                &trace_ctx.trace_level,
+                &batch_ctx.config.pii,
+                &log_ctx,
+            ),
+            extraction_issues: ExtractionIssues::default(),
+            signature_format: None,
+            signature_formats_tried: Vec::new(),
         };
     }
 
     // [3] SIGNATURE VERIFICATION
     // Signatures are REQUIRED for trace integrity - no bypass
-    let signature_result = verify_trace_signature(&trace, &trace_ctx.trace_level, &log_ctx);
+    let quorum_threshold = get_schema_cache()
+        .get_schema(&schema_version)
+        .and_then(|schema| schema.signature_quorum)
+        .unwrap_or(batch_ctx.config.signature.quorum_threshold);
+    let signature_started = Instant::now();
+    let quorum = if preverified && quorum_threshold <= 1 {
+        preverified_quorum(&trace, &log_ctx)
+    } else {
+        verify_trace_signatures(
+            &trace,
+            &trace_ctx.trace_level,
+            batch_ctx.batch_timestamp,
+            quorum_threshold,
+            batch_ctx.config.signature.verification_cache_capacity,
+            &log_ctx,
+        )
+    };
+    batch_ctx.stage_timers.record_signature(signature_started);
+    if batch_ctx.config.diagnostics.key_activity {
+        for check in &quorum.key_checks {
+            note_key_activity(check);
+        }
+    }
+    let signature_result = quorum.result;
+
+    let replay_window = batch_ctx.config.signature.replay_window;
+    let components_hash = (replay_window > 0).then(|| {
+        crate::validation::signature::compute_hash(&sort_and_serialize_compact(
+            trace.get("components").unwrap_or(&Value::Null),
+        ))
+    });
 
     if !signature_result.verified {
         log::warn!(
@@ -163,41 +1036,202 @@ fn process_single_trace(batch_ctx: &BatchContext, event_json: &str) -> TraceResu
             signature_result.key_id,
             signature_result.error
         );
+        let mut extracted_metadata = HashMap::new();
+        if let Some(components_hash) = &components_hash {
+            if trace_signatures(&trace)
+                .into_iter()
+                .any(|sig| is_replayed_signature(sig, components_hash))
+            {
+                log::warn!(
+                    "{} SIGNATURE_REPLAY_SUSPECTED key_id={:?}",
+                    log_ctx,
+                    signature_result.key_id
+                );
+                extracted_metadata.insert("signature_replay_suspected".to_string(), "true".to_string());
+            }
+        }
         return TraceResult {
-            trace_id,
-            destination: "malformed".to_string(),
-            schema_version: Some(schema_version),
-            accepted: false,
-            rejection_reason: signature_result.error,
-            extracted_metadata: HashMap::new(),
+            signature_formats_tried: signature_result.formats_tried,
+            ..TraceResult::rejected(
+                trace_id,
+                "malformed",
+                Some(schema_version),
+                signature_result.error.unwrap_or_default(),
+            )
+            .with_metadata(extracted_metadata)
         };
     }
 
-    // [4] PII SCRUBBING (full_traces level only)
-    let trace_to_process = if trace_ctx.trace_level == "full_traces" {
-        log::info!("{} PII_SCRUB_START level=full_traces", log_ctx);
-        let (scrubbed, pii_result) = scrub_pii(&trace, &log_ctx);
-        if pii_result.total_entities() > 0 {
-            log::info!(
-                "{} PII_SCRUBBED total_entities={} fields_modified={}",
-                log_ctx,
-                pii_result.total_entities(),
-                pii_result.fields_modified
-            );
+    if let Some(components_hash) = &components_hash {
+        for sig in trace_signatures(&trace) {
+            remember_verified_signature(sig, components_hash, replay_window);
         }
-        scrubbed
-    } else {
-        log::debug!("{} PII_SKIPPED level={}", log_ctx, trace_ctx.trace_level);
-        trace.clone()
-    };
+    }
 
-    // [5] SECURITY SANITIZATION
-    let sanitized_trace = sanitize_trace(&trace_to_process, &log_ctx);
+    // [4] PII SCRUBBING (full_traces, plus always-scrub fields at any level)
+    let pii_targets = get_schema_cache()
+        .get_schema(&schema_version)
+        .and_then(|schema| schema.effective_pii_target_fields());
+    let pii_started = Instant::now();
+    let (trace_to_process, pii_result) = scrub_pii_for_level(
+        &trace,
+        &trace_ctx.trace_level,
+        pii_targets,
+        &get_always_scrub_fields(),
+        &batch_ctx.config.pii,
+        &log_ctx,
+    );
+    batch_ctx.stage_timers.record_pii_scrub(pii_started);
 
-    // [6] METADATA EXTRACTION
-    let mut extracted_metadata = extract_trace_metadata(&sanitized_trace, &schema_version, &log_ctx);
+    // [5] SECURITY SANITIZATION
+    let sanitizer_config = &batch_ctx.config.sanitizer;
+    let sanitize_options =
+        SanitizeOptions::from_globals(sanitizer_config.oversize_mode, sanitizer_config.xss_mode);
+    // Hash the content before neutralization can rewrite it, so stored
+    // traces keep a reference to what the agent actually sent
+    let pre_sanitize_hash = (sanitizer_config.xss_mode == XssMode::Neutralize).then(|| {
+        crate::validation::signature::compute_hash(&sort_and_serialize_compact(&trace_to_process))
+    });
+    let (sanitized_trace, sanitize_result) =
+        sanitize_trace_with(&trace_to_process, &sanitize_options, &log_ctx);
+    let neutralized = pre_sanitize_hash.filter(|_| sanitized_trace != trace_to_process);
 
-    // Add signature verification result to metadata
+    // Scrubbing drops subtrees beyond MAX_JSON_DEPTH before the sanitizer
+    // sees them, so check both
+    let depth_exceeded = sanitize_result.depth_exceeded
+        + pii_result.as_ref().map_or(0, |pii| pii.depth_exceeded);
+    if depth_exceeded > 0 {
+        log::warn!(
+            "{} TRACE_REJECTED reason=max_depth_exceeded subtrees={} limit={}",
+            log_ctx,
+            depth_exceeded,
+            MAX_JSON_DEPTH
+        );
+        return TraceResult::rejected(
+            trace_id,
+            "malformed",
+            Some(schema_version),
+            "max_depth_exceeded",
+        );
+    }
+
+    // [6] METADATA EXTRACTION (skipped in throughput mode, and in
+    // validate-only mode unless the required-field check needs it)
+    let mut extraction_issues = ExtractionIssues::default();
+    let extract = batch_ctx.extraction_enabled
+        && (!batch_ctx.validate_only || batch_ctx.config.extraction.required_fields == RequiredFieldCheck::Reject);
+    let mut extracted_metadata = if extract {
+        let started = Instant::now();
+        let mut metadata = extract_trace_metadata_with_issues(
+            &sanitized_trace,
+            &schema_version,
+            &batch_ctx.config.extraction,
+            &mut extraction_issues,
+            &log_ctx,
+        );
+        // Cap before the pipeline adds its own flags, so they always survive
+        cap_metadata_entries(
+            &mut metadata,
+            batch_ctx.config.extraction.max_metadata_entries,
+            &log_ctx,
+        );
+        batch_ctx.stage_timers.record_extraction(started);
+        metadata
+    } else if batch_ctx.validate_only {
+        log::debug!("{} EXTRACT_SKIP reason=validate_only", log_ctx);
+        // Mock detection still needs the models used
+        let mut metadata = HashMap::from([("trace_id".to_string(), trace_id.clone())]);
+        let models_used = extract_models_used(&sanitized_trace);
+        if !models_used.is_empty() {
+            metadata.insert("models_used".to_string(), serde_json::to_string(&models_used).unwrap_or_default());
+        }
+        metadata
+    } else {
+        log::debug!("{} EXTRACT_SKIP reason=throughput_mode", log_ctx);
+        HashMap::from([("trace_id".to_string(), trace_id.clone())])
+    };
+
+    let missing_required = missing_required_fields(&extraction_issues, batch_ctx.config.extraction.required_fields);
+    if let Some((ref columns, true)) = missing_required {
+        log::warn!(
+            "{} TRACE_REJECTED reason=missing_required_field columns={}",
+            log_ctx,
+            columns
+        );
+        return TraceResult {
+            extraction_issues,
+            ..TraceResult::rejected(
+                trace_id,
+                "malformed",
+                Some(schema_version),
+                "missing_required_field",
+            )
+        };
+    }
+
+    for (column, count) in sanitize_result.category_columns() {
+        extracted_metadata.insert(column.to_string(), count.to_string());
+    }
+
+    if let Some(hash) = neutralized {
+        extracted_metadata.insert("sanitizer_modified".to_string(), "true".to_string());
+        extracted_metadata.insert("pre_sanitize_content_hash".to_string(), hash);
+    }
+
+    if components_decoded {
+        extracted_metadata.insert("components_base64".to_string(), "true".to_string());
+    }
+    if let Some((columns, _)) = missing_required {
+        extracted_metadata.insert("missing_required_fields".to_string(), columns);
+    }
+    if too_wide {
+        extracted_metadata.insert("component_too_wide".to_string(), "true".to_string());
+    }
+    let mut quarantine_reason = None;
+    if let Some(ref declared) = declared_unsupported {
+        extracted_metadata.insert("schema_version_unsupported".to_string(), "true".to_string());
+        extracted_metadata.insert("declared_schema_version".to_string(), declared.clone());
+        if batch_ctx.config.routing.unsupported_schema_action == UnsupportedSchemaAction::Quarantine {
+            quarantine_reason = Some("schema_version_unsupported");
+        }
+    }
+
+    extracted_metadata.insert(
+        "consent_source".to_string(),
+        consent_source.as_str().to_string(),
+    );
+
+    let tracked_agents = batch_ctx.config.sequence.tracked_agents;
+    if tracked_agents > 0 && !batch_ctx.validate_only {
+        let agent_id_hash = trace.get("agent_id_hash").and_then(|v| v.as_str());
+        let seq = trace.get("seq").and_then(|v| v.as_u64());
+        if let (Some(agent_id_hash), Some(seq)) = (agent_id_hash, seq) {
+            let flag = match observe_agent_sequence(agent_id_hash, seq, tracked_agents) {
+                SequenceStatus::First | SequenceStatus::InOrder => None,
+                SequenceStatus::Gap { previous } => Some(("seq_gap", previous)),
+                SequenceStatus::Regression { previous } => Some(("seq_regression", previous)),
+            };
+            if let Some((column, previous)) = flag {
+                log::warn!(
+                    "{} {} agent_id_hash={} seq={} previous={}",
+                    log_ctx,
+                    column.to_ascii_uppercase(),
+                    agent_id_hash,
+                    seq,
+                    previous
+                );
+                extracted_metadata.insert(column.to_string(), "true".to_string());
+                extracted_metadata.insert("seq_previous".to_string(), previous.to_string());
+            }
+        }
+    }
+
+    // Extraction only copies a trace_id present in the body
+    extracted_metadata
+        .entry("trace_id".to_string())
+        .or_insert_with(|| trace_id.clone());
+
+    // Add signature verification result to metadata
     extracted_metadata.insert(
         "signature_verified".to_string(),
         signature_result.verified.to_string(),
@@ -208,15 +1242,73 @@ fn process_single_trace(batch_ctx: &BatchContext, event_json: &str) -> TraceResu
             key_id.clone(),
         );
     }
+    if let Some(ref format) = signature_result.format {
+        extracted_metadata.insert("signature_format".to_string(), format.clone());
+    }
+    extracted_metadata.insert(
+        "signatures_verified".to_string(),
+        format!("{}/{}", quorum.verified_keys, quorum.total),
+    );
+
+    for (column, value) in matched_schema_columns(
+        &get_schema_cache(),
+        &schema_version,
+        &schema_result.event_types,
+        &batch_ctx.config.extraction,
+    ) {
+        extracted_metadata.insert(column.to_string(), value);
+    }
+
+    // Per-category PII counts (only when scrubbing ran)
+    let mut over_scrubbed = false;
+    if let Some(ref pii_result) = pii_result {
+        for (column, count) in pii_result.category_columns() {
+            extracted_metadata.insert(column.to_string(), count.to_string());
+        }
+
+        // Sanity check: a scrub that replaced most of a field is more likely
+        // a pattern misfire than real PII
+        let pii_config = &batch_ctx.config.pii;
+        let replaced_pct = pii_result.max_replaced_ratio * 100.0;
+        if replaced_pct > pii_config.max_replaced_pct {
+            log::warn!(
+                "{} PII_OVER_SCRUB replaced_pct={:.1} threshold={} action={:?}",
+                log_ctx,
+                replaced_pct,
+                pii_config.max_replaced_pct,
+                pii_config.over_scrub_action
+            );
+            extracted_metadata.insert("pii_over_scrubbed".to_string(), "true".to_string());
+            extracted_metadata.insert(
+                "pii_max_replaced_pct".to_string(),
+                format!("{:.1}", replaced_pct),
+            );
+            over_scrubbed = true;
+        }
+    }
 
     // [7] MOCK DETECTION & ROUTING
-    let routing = determine_routing(&extracted_metadata, &trace_ctx.trace_level, &log_ctx);
+    let mut routing = determine_routing(
+        &extracted_metadata,
+        &trace_ctx.trace_level,
+        quarantine_reason,
+        &batch_ctx.config.routing,
+        &log_ctx,
+    );
+    if over_scrubbed && batch_ctx.config.pii.over_scrub_action == OverScrubAction::Review {
+        routing = RoutingDecision::Review("pii_over_scrub".to_string());
+    }
+
+    extracted_metadata.insert("is_production".to_string(), routing.is_production().to_string());
 
     let destination = match routing {
         RoutingDecision::Production => "production",
         RoutingDecision::Mock => "mock",
         RoutingDecision::Connectivity => "connectivity",
         RoutingDecision::Malformed(_) => "malformed",
+        RoutingDecision::Review(_) => "review",
+        RoutingDecision::Quarantine(_) => "quarantine",
+        RoutingDecision::SampledOut => "sampled_out",
     };
 
     log::info!(
@@ -233,18 +1325,91 @@ fn process_single_trace(batch_ctx: &BatchContext, event_json: &str) -> TraceResu
         accepted: true,
         rejection_reason: None,
         extracted_metadata,
+        extraction_issues,
+        signature_format: signature_result.format,
+        signature_formats_tried: signature_result.formats_tried,
+    }
+}
+
+/// Optional columns describing the matched schema: its match confidence
+/// and its status, each when enabled in the extraction config.
+fn matched_schema_columns(
+    cache: &SchemaCache,
+    schema_version: &str,
+    event_types: &HashSet<String>,
+    config: &ExtractionConfig,
+) -> Vec<(&'static str, String)> {
+    let Some(schema) = cache.get_schema(schema_version) else {
+        return Vec::new();
+    };
+    let mut columns = Vec::new();
+    if config.schema_match_confidence {
+        columns.push((
+            "schema_match_confidence",
+            format!("{:.2}", schema.match_confidence(event_types)),
+        ));
+    }
+    if config.schema_status {
+        columns.push(("schema_status", schema.status.clone()));
+    }
+    columns
+}
+
+/// The trace's declared `schema_version`, when it names a version that
+/// isn't loaded. `None` when nothing is declared or no schemas are loaded.
+fn unsupported_declared_version(trace: &Value, cache: &SchemaCache) -> Option<String> {
+    let declared = trace.get("schema_version")?.as_str()?;
+    (cache.is_loaded() && cache.get_schema(declared).is_none()).then(|| declared.to_string())
+}
+
+/// Decode a base64 string `components` into its JSON array, in place.
+///
+/// Only done when a loaded schema sets `components_base64`; otherwise the
+/// trace is left alone and fails validation as before. Signatures are
+/// verified over the decoded array. Returns whether decoding happened.
+///
+/// # Errors
+/// `invalid_base64_components: ...` when the string is not base64 of a
+/// JSON array.
+fn decode_base64_components(trace: &mut Value, cache: &SchemaCache) -> Result<bool, String> {
+    use base64::{engine::general_purpose, Engine as _};
+
+    let encoded = match trace.get("components") {
+        Some(Value::String(encoded)) if cache.accepts_base64_components() => encoded,
+        _ => return Ok(false),
+    };
+    let bytes = general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| format!("invalid_base64_components: {}", e))?;
+    let components: Value = serde_json::from_slice(&bytes)
+        .map_err(|e| format!("invalid_base64_components: {}", e))?;
+    if !components.is_array() {
+        return Err("invalid_base64_components: not a JSON array".to_string());
     }
+    trace["components"] = components;
+    Ok(true)
+}
+
+/// Required columns the extraction found missing (comma-joined, sorted),
+/// and whether `check` rejects the trace for them.
+fn missing_required_fields(issues: &ExtractionIssues, check: RequiredFieldCheck) -> Option<(String, bool)> {
+    let missing = issues.get("missing_required")?;
+    let columns: Vec<&str> = missing.columns.iter().map(|c| c.as_str()).collect();
+    Some((columns.join(","), check == RequiredFieldCheck::Reject))
 }
 
 /// Validate trace schema.
-fn validate_schema(trace: &Value, ctx: &LogContext) -> SchemaValidationResult {
+///
+/// Uses the configured event type keys and mixed-connectivity policy.
+fn validate_schema(trace: &Value, config: &PipelineConfig, ctx: &LogContext) -> SchemaValidationResult {
+    let event_type_keys = &config.extraction.event_type_keys;
     // Extract event_types from components
     let event_types: HashSet<String> = trace
         .get("components")
         .and_then(|c| c.as_array())
         .map(|arr| {
             arr.iter()
-                .filter_map(|c| c.get("event_type").and_then(|e| e.as_str()))
+                .filter_map(|c| component_event_type(c, event_type_keys))
                 .map(|s| s.to_string())
                 .collect()
         })
@@ -276,8 +1441,31 @@ fn validate_schema(trace: &Value, ctx: &LogContext) -> SchemaValidationResult {
         return SchemaValidationResult::valid("unknown", all_events);
     }
 
-    match cache.detect_schema_version(&all_events, ctx) {
-        Some(schema) => SchemaValidationResult::valid(&schema.version, all_events),
+    let detected = match cache.resolve_mixed_connectivity(&all_events, config.routing.mixed_connectivity, ctx) {
+        Ok(Some(schema)) => Some(schema),
+        Ok(None) => cache.detect_schema_version(&all_events, ctx),
+        Err(reason) => return SchemaValidationResult::invalid(&reason, all_events),
+    };
+    match detected {
+        Some(schema) => {
+            let missing = schema.missing_required_events(&all_events);
+            if !missing.is_empty() {
+                log::warn!(
+                    "{} SCHEMA_REQUIRED_EVENTS_MISSING version={} missing={:?}",
+                    ctx,
+                    schema.version,
+                    missing
+                );
+                return SchemaValidationResult::invalid(
+                    &format!(
+                        "Missing required event types for schema {}: {:?}",
+                        schema.version, missing
+                    ),
+                    all_events,
+                );
+            }
+            SchemaValidationResult::valid(&schema.version, all_events)
+        }
         None => SchemaValidationResult::invalid(
             &format!("No matching schema for events: {:?}", all_events),
             all_events,
@@ -289,13 +1477,26 @@ fn validate_schema(trace: &Value, ctx: &LogContext) -> SchemaValidationResult {
 ///
 /// Extracts signature and key_id from trace and verifies against loaded public keys.
 ///
-/// Supports three formats:
+/// Supports these formats:
 /// - 1.9.9+: Wrapper object {"components": [...], "trace_level": "..."}, compact JSON, sorted keys
+/// - 1.9.8: Wrapper object {"components": [...]} without trace_level, compact JSON, sorted keys
 /// - 1.9.7+: Components array only, compact JSON with strip_empty
 /// - Pre-1.9.7: Components array only, JSON with spaces, no stripping
+/// - envelope: Whole trace object minus the signature fields
+/// - legacy-2field: 2.7.6-era agents' projected components + trace_level,
+///   Python `json.dumps` bytes (tried first for `2.7.legacy` traces)
+///
+/// The format that verified is returned in the result's `format`.
+/// A trace-level `signature_mode: "ed25519ph"` selects prehashed
+/// verification; the default is PureEdDSA.
+///
+/// With a nonzero `cache_capacity`, results are cached by trace level,
+/// key, signature and body, so a retried trace skips the cascade.
 fn verify_trace_signature(
     trace: &Value,
     batch_trace_level: &str,
+    batch_timestamp: DateTime<Utc>,
+    cache_capacity: usize,
     ctx: &LogContext,
 ) -> crate::validation::signature::SignatureVerificationResult {
     // Extract signature fields
@@ -304,86 +1505,38 @@ fn verify_trace_signature(
 
     match (signature, key_id) {
         (Some(sig), Some(kid)) => {
-            // Get components array
-            let components = match trace.get("components") {
-                Some(c) => c,
-                None => {
-                    log::warn!("{} SIGNATURE_NO_COMPONENTS", ctx);
-                    return crate::validation::signature::SignatureVerificationResult {
-                        verified: false,
-                        key_id: Some(kid.to_string()),
-                        error: Some("No components array for signature verification".to_string()),
-                    };
-                }
-            };
-
-            // Use batch-level trace_level for 1.9.9 format (from API request, not trace object)
-            let trace_level = batch_trace_level;
-
-            // Try 1.9.9 format first: {"components": [...], "trace_level": "..."}
-            // Compact JSON with sorted keys, no stripping
-            let canonical_199 = build_199_canonical(components, trace_level);
-            let hash_199 = crate::validation::signature::compute_hash(&canonical_199);
-            let hash_199_short: String = hash_199.chars().take(16).collect();
-            let preview_start: String = canonical_199.chars().take(300).collect();
-            log::info!(
-                "{} SIGNATURE_199_DEBUG key_id={} level={} len={} hash={} preview={}",
-                ctx, kid, trace_level, canonical_199.len(), hash_199_short, preview_start
-            );
-
-            let result_199 = verify_signature(&canonical_199, sig, kid, ctx);
-            if result_199.verified {
-                log::info!(
-                    "{} SIGNATURE_VERIFIED format=1.9.9 key_id={} len={} hash={}",
-                    ctx, kid, canonical_199.len(), hash_199_short
-                );
-                return result_199;
-            }
-
-            // Try 1.9.7 format (compact + strip_empty, components only)
-            let canonical_197 = sort_and_serialize(components);
-            let hash_197 = crate::validation::signature::compute_hash(&canonical_197);
-            log::debug!(
-                "{} SIGNATURE_TRY_FORMAT format=1.9.7 key_id={} len={} hash={}",
-                ctx, kid, canonical_197.len(), hash_197
-            );
-
-            let result_197 = verify_signature(&canonical_197, sig, kid, ctx);
-            if result_197.verified {
+            // Key validity depends on the batch timestamp, so it is checked
+            // on every trace and window failures are never cached
+            let key_cache = get_key_cache();
+            let normalized_kid = key_cache.normalize_key_id(kid).into_owned();
+            let cacheable =
+                cache_capacity > 0 && key_cache.check_key_validity(kid, batch_timestamp).is_ok();
+            drop(key_cache);
+            let cache_key = cacheable.then(|| {
+                verification_cache_key(
+                    batch_trace_level,
+                    &normalized_kid,
+                    sig,
+                    &sort_and_serialize_compact(trace),
+                )
+            });
+            if let Some(cached) = cache_key.as_deref().and_then(get_cached_verification) {
                 log::info!(
-                    "{} SIGNATURE_VERIFIED format=1.9.7 key_id={} len={} hash={}",
-                    ctx, kid, canonical_197.len(), hash_197
+                    "{} SIGNATURE_CACHE_HIT key_id={} verified={} format={:?}",
+                    ctx,
+                    kid,
+                    cached.verified,
+                    cached.format
                 );
-                return result_197;
+                return cached;
             }
 
-            // Try pre-1.9.7 format (with spaces, no stripping, components only)
-            let canonical_pre197 = sort_and_serialize_legacy(components);
-            let hash_pre197 = crate::validation::signature::compute_hash(&canonical_pre197);
-            log::debug!(
-                "{} SIGNATURE_TRY_FORMAT format=pre-1.9.7 key_id={} len={} hash={}",
-                ctx, kid, canonical_pre197.len(), hash_pre197
-            );
-
-            let result_pre197 = verify_signature(&canonical_pre197, sig, kid, ctx);
-            if result_pre197.verified {
-                log::info!(
-                    "{} SIGNATURE_VERIFIED format=pre-1.9.7 key_id={} len={} hash={}",
-                    ctx, kid, canonical_pre197.len(), hash_pre197
-                );
-                return result_pre197;
+            let result =
+                verify_components_signature(trace, batch_trace_level, batch_timestamp, sig, kid, ctx);
+            if let Some(cache_key) = cache_key {
+                cache_verification(cache_key, result.clone(), cache_capacity);
             }
-
-            // All formats failed - log details for troubleshooting
-            let preview_199: String = canonical_199.chars().take(200).collect();
-            log::warn!(
-                "{} SIGNATURE_VERIFICATION_FAILED key_id={} tried_formats=[1.9.9,1.9.7,pre-1.9.7] \
-                 hash_199={} hash_197={} hash_pre197={} preview_199={}...",
-                ctx, kid, hash_199_short, hash_197, hash_pre197, preview_199
-            );
-
-            // Return the 1.9.9 result (most recent format)
-            result_199
+            result
         }
         (None, _) => {
             log::debug!("{} SIGNATURE_MISSING", ctx);
@@ -395,227 +1548,2855 @@ fn verify_trace_signature(
                 verified: false,
                 key_id: None,
                 error: Some("Signature present but key_id missing".to_string()),
+                format: None,
+                formats_tried: Vec::new(),
             }
         }
     }
 }
 
-/// Check if a value is "empty" (null, empty string, empty array, empty object).
-fn is_empty_value(value: &Value) -> bool {
-    match value {
-        Value::Null => true,
-        Value::String(s) => s.is_empty(),
-        Value::Array(arr) => arr.is_empty(),
-        Value::Object(map) => map.is_empty(),
-        _ => false,
-    }
-}
+/// 1.9.9 signatures of a batch verified together, for
+/// `SignatureConfig::batch_verify`; true for each event that verified.
+///
+/// Only `eligible` events with a single top-level PureEdDSA signature
+/// take part; the canonical uses the trace's embedded `trace_level` when
+/// present, and base64 components are decoded as in the per-trace path.
+/// Events that are false here still go through the full per-trace
+/// cascade.
+fn batch_preverify(
+    ctx: &BatchContext,
+    events: &[String],
+    eligible: &[bool],
+    schemas: &SchemaCache,
+) -> Vec<bool> {
+    let started = Instant::now();
+    let log_ctx = LogContext::new(&ctx.batch_id);
 
-/// Recursively strip empty values from a JSON value.
-fn strip_empty(value: &Value) -> Option<Value> {
-    match value {
-        Value::Object(map) => {
-            let filtered: serde_json::Map<String, Value> = map
-                .iter()
-                .filter_map(|(k, v)| {
-                    if is_empty_value(v) {
-                        None
-                    } else {
-                        strip_empty(v).map(|stripped| (k.clone(), stripped))
-                    }
-                })
-                .collect();
-            if filtered.is_empty() {
-                None
-            } else {
-                Some(Value::Object(filtered))
-            }
+    // (event index, canonical, signature, key_id)
+    let mut candidates: Vec<(usize, String, String, String)> = Vec::new();
+    for (i, event_json) in events.iter().enumerate() {
+        if !eligible[i] {
+            continue;
         }
-        Value::Array(arr) => {
-            let filtered: Vec<Value> = arr
-                .iter()
-                .filter_map(|v| {
-                    if is_empty_value(v) {
-                        None
-                    } else {
-                        strip_empty(v)
-                    }
-                })
-                .collect();
-            if filtered.is_empty() {
-                None
-            } else {
-                Some(Value::Array(filtered))
-            }
+        let mut trace: Value = match serde_json::from_str(event_json) {
+            Ok(trace) => trace,
+            Err(_) => continue,
+        };
+        if decode_base64_components(&mut trace, schemas).is_err() {
+            continue;
         }
-        _ => Some(value.clone()),
+        if trace.get("signatures").is_some() {
+            continue;
+        }
+        let mode = SignatureMode::from_field(trace.get("signature_mode").and_then(|v| v.as_str()));
+        if mode != Ok(SignatureMode::Pure) {
+            continue;
+        }
+        let sig = trace.get("signature").and_then(|v| v.as_str());
+        let kid = trace.get("signature_key_id").and_then(|v| v.as_str());
+        let (sig, kid, components) = match (sig, kid, trace.get("components")) {
+            (Some(sig), Some(kid), Some(components)) => (sig, kid, components),
+            _ => continue,
+        };
+        if get_key_cache().check_key_validity(kid, ctx.batch_timestamp).is_err() {
+            continue;
+        }
+        let level = trace
+            .get("trace_level")
+            .and_then(|v| v.as_str())
+            .unwrap_or(&ctx.trace_level);
+        let canonical = build_199_canonical(components, level);
+        candidates.push((i, canonical, sig.to_string(), kid.to_string()));
     }
-}
 
-/// Serialize JSON value with sorted keys (recursive).
-/// Uses compact JSON (no spaces) and strips empty values to match agent's _strip_empty().
-fn sort_and_serialize(value: &Value) -> String {
-    // First strip empty values
-    let stripped = strip_empty(value).unwrap_or(Value::Null);
-    sort_and_serialize_inner(&stripped)
-}
+    let items: Vec<BatchVerifyItem> = candidates
+        .iter()
+        .map(|(_, message, signature, key_id)| BatchVerifyItem { message, signature, key_id })
+        .collect();
+    let results = verify_signatures_batch(&items, &log_ctx);
 
-/// Inner serialization function (after stripping).
-fn sort_and_serialize_inner(value: &Value) -> String {
-    match value {
-        Value::Object(map) => {
-            // Sort keys and recursively process values
-            let mut sorted: Vec<_> = map.iter().collect();
-            sorted.sort_by(|a, b| a.0.cmp(b.0));
+    let mut preverified = vec![false; events.len()];
+    let share = started.elapsed() / (candidates.len().max(1) as u32);
+    for ((i, ..), verified) in candidates.iter().zip(results) {
+        if verified {
+            preverified[*i] = true;
+            record_format_attempt("1.9.9", true, share);
+        }
+    }
+    log::info!(
+        "{} SIGNATURE_BATCH_PREVERIFY candidates={} verified={} elapsed_us={}",
+        log_ctx,
+        candidates.len(),
+        preverified.iter().filter(|v| **v).count(),
+        started.elapsed().as_micros()
+    );
+    preverified
+}
 
-            let pairs: Vec<String> = sorted
-                .iter()
-                .map(|(k, v)| format!("\"{}\":{}", k, sort_and_serialize_inner(v)))
-                .collect();
+/// Signature outcome for a trace already verified by `batch_preverify`.
+fn preverified_quorum(trace: &Value, ctx: &LogContext) -> QuorumVerification {
+    use crate::validation::signature::SignatureVerificationResult;
 
-            format!("{{{}}}", pairs.join(","))
-        }
-        Value::Array(arr) => {
-            let items: Vec<String> = arr.iter().map(sort_and_serialize_inner).collect();
-            format!("[{}]", items.join(","))
-        }
-        Value::String(s) => {
-            // Properly escape the string for JSON
-            serde_json::to_string(s).unwrap_or_else(|_| format!("\"{}\"", s))
-        }
-        Value::Number(n) => n.to_string(),
-        Value::Bool(b) => b.to_string(),
-        Value::Null => "null".to_string(),
+    let kid = trace
+        .get("signature_key_id")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    log::info!("{} SIGNATURE_VERIFIED format=1.9.9 key_id={} path=batch", ctx, kid);
+    QuorumVerification {
+        result: SignatureVerificationResult::verified(kid)
+            .with_format("1.9.9")
+            .with_formats_tried(vec!["1.9.9".to_string()]),
+        verified_keys: 1,
+        total: 1,
+        key_checks: vec![KeyCheck {
+            key_id: kid.to_string(),
+            verified: true,
+            format: Some("1.9.9".to_string()),
+        }],
     }
 }
 
-/// Serialize JSON value with sorted keys for pre-1.9.7 format.
-/// Uses spaces after `:` and `,` and does NOT strip empty values.
-/// This matches Python's default: json.dumps(obj, sort_keys=True)
-fn sort_and_serialize_legacy(value: &Value) -> String {
-    match value {
-        Value::Object(map) => {
-            // Sort keys and recursively process values
-            let mut sorted: Vec<_> = map.iter().collect();
-            sorted.sort_by(|a, b| a.0.cmp(b.0));
+/// Add a signature check to the key activity index. Only registered keys
+/// are tracked, so arbitrary key ids in rejected traces can't fill it.
+fn note_key_activity(check: &KeyCheck) {
+    let key_cache = get_key_cache();
+    if !key_cache.has_key(&check.key_id) {
+        return;
+    }
+    let key_id = key_cache.normalize_key_id(&check.key_id).into_owned();
+    drop(key_cache);
+    record_key_activity(&key_id, check.verified, check.format.as_deref());
+}
 
-            let pairs: Vec<String> = sorted
-                .iter()
-                .map(|(k, v)| format!("\"{}\": {}", k, sort_and_serialize_legacy(v)))
-                .collect();
+/// One key's signature check on a trace, for the key activity index.
+struct KeyCheck {
+    key_id: String,
+    verified: bool,
+    format: Option<String>,
+}
 
-            format!("{{{}}}", pairs.join(", "))
-        }
-        Value::Array(arr) => {
-            let items: Vec<String> = arr.iter().map(sort_and_serialize_legacy).collect();
-            format!("[{}]", items.join(", "))
-        }
-        Value::String(s) => {
-            // Properly escape the string for JSON
-            serde_json::to_string(s).unwrap_or_else(|_| format!("\"{}\"", s))
+impl KeyCheck {
+    fn new(key_id: &str, result: &crate::validation::signature::SignatureVerificationResult) -> Self {
+        Self {
+            key_id: key_id.to_string(),
+            verified: result.verified,
+            format: result.format.clone(),
         }
-        Value::Number(n) => n.to_string(),
-        Value::Bool(b) => b.to_string(),
-        Value::Null => "null".to_string(),
     }
 }
 
-/// Build 1.9.9 canonical message format.
-/// Wrapper object: {"components": [...], "trace_level": "..."}
-/// Compact JSON with sorted keys, NO stripping of empty values.
-/// Matches Python: json.dumps(payload, sort_keys=True, separators=(",", ":"))
-fn build_199_canonical(components: &Value, trace_level: &str) -> String {
-    // Serialize components with sorted keys, compact format, no stripping
-    let components_str = sort_and_serialize_compact(components);
-    // Build wrapper object with sorted keys: "components" comes before "trace_level"
-    format!("{{\"components\":{},\"trace_level\":\"{}\"}}", components_str, trace_level)
+/// Outcome of checking a trace's signatures against the quorum threshold.
+struct QuorumVerification {
+    /// Overall result; `key_id` is the first key that verified.
+    result: crate::validation::signature::SignatureVerificationResult,
+    /// Distinct registered keys that verified.
+    verified_keys: usize,
+    /// Signatures provided on the trace.
+    total: usize,
+    /// Every key checked, recorded by the caller when key activity
+    /// tracking is on.
+    key_checks: Vec<KeyCheck>,
 }
 
-/// Serialize JSON value with sorted keys, compact format (no spaces).
-/// Does NOT strip empty values - keeps nulls, empty strings, etc.
-fn sort_and_serialize_compact(value: &Value) -> String {
-    match value {
-        Value::Object(map) => {
-            let mut sorted: Vec<_> = map.iter().collect();
-            sorted.sort_by(|a, b| a.0.cmp(b.0));
+/// Verify a trace's signatures against an M-of-N quorum.
+///
+/// Traces may carry a `signatures` array of `{"signature", "signature_key_id"}`
+/// objects (`key_id` is accepted for the key), e.g. old and new keys during
+/// a rotation window; otherwise the top-level `signature`/`signature_key_id`
+/// pair is treated as a single signature. The trace is verified when at least
+/// `threshold` distinct keys verify — repeated signatures from one key
+/// count once.
+fn verify_trace_signatures(
+    trace: &Value,
+    batch_trace_level: &str,
+    batch_timestamp: DateTime<Utc>,
+    threshold: usize,
+    cache_capacity: usize,
+    ctx: &LogContext,
+) -> QuorumVerification {
+    use crate::validation::signature::SignatureVerificationResult;
 
-            let pairs: Vec<String> = sorted
-                .iter()
-                .map(|(k, v)| format!("\"{}\":{}", k, sort_and_serialize_compact(v)))
+    let entries = match trace.get("signatures").and_then(|v| v.as_array()) {
+        Some(entries) => entries,
+        None => {
+            let result =
+                verify_trace_signature(trace, batch_trace_level, batch_timestamp, cache_capacity, ctx);
+            let verified_keys = usize::from(result.verified);
+            let total = usize::from(trace.get("signature").is_some());
+            let key_checks: Vec<KeyCheck> = trace
+                .get("signature_key_id")
+                .and_then(|v| v.as_str())
+                .filter(|_| total > 0)
+                .map(|kid| KeyCheck::new(kid, &result))
+                .into_iter()
                 .collect();
-
-            format!("{{{}}}", pairs.join(","))
+            if result.verified && verified_keys < threshold {
+                log::warn!(
+                    "{} SIGNATURE_QUORUM_NOT_MET verified={}/{} threshold={}",
+                    ctx, verified_keys, total, threshold
+                );
+                return QuorumVerification {
+                    result: SignatureVerificationResult {
+                        verified: false,
+                        key_id: result.key_id,
+                        error: Some(format!(
+                            "Signature quorum not met: {} of {} required keys verified",
+                            verified_keys, threshold
+                        )),
+                        format: None,
+                        formats_tried: result.formats_tried,
+                    },
+                    verified_keys,
+                    total,
+                    key_checks,
+                };
+            }
+            return QuorumVerification { result, verified_keys, total, key_checks };
         }
-        Value::Array(arr) => {
-            let items: Vec<String> = arr.iter().map(sort_and_serialize_compact).collect();
-            format!("[{}]", items.join(","))
+    };
+
+    let mut key_checks = Vec::new();
+    let mut verified_key_ids: Vec<String> = Vec::new();
+    let mut first_format = None;
+    let mut first_tried = Vec::new();
+    let mut last_error = None;
+    let mut last_tried = Vec::new();
+    for entry in entries {
+        let sig = entry.get("signature").and_then(|v| v.as_str());
+        let kid = entry
+            .get("signature_key_id")
+            .or_else(|| entry.get("key_id"))
+            .and_then(|v| v.as_str());
+        let (sig, kid) = match (sig, kid) {
+            (Some(sig), Some(kid)) => (sig, kid),
+            _ => {
+                log::warn!("{} SIGNATURE_ENTRY_INCOMPLETE", ctx);
+                last_error = Some("Signature entry missing signature or key_id".to_string());
+                continue;
+            }
+        };
+        // Distinct keys are counted by normalized id, so whitespace or case
+        // variants of one key can't satisfy the quorum on their own
+        let normalized_kid = get_key_cache().normalize_key_id(kid).into_owned();
+        if verified_key_ids.contains(&normalized_kid) {
+            log::debug!("{} SIGNATURE_DUPLICATE_KEY key_id={}", ctx, kid);
+            continue;
+        }
+        let result = verify_components_signature(trace, batch_trace_level, batch_timestamp, sig, kid, ctx);
+        key_checks.push(KeyCheck::new(kid, &result));
+        if result.verified {
+            if verified_key_ids.is_empty() {
+                first_format = result.format;
+                first_tried = result.formats_tried;
+            }
+            verified_key_ids.push(normalized_kid);
+        } else {
+            last_error = result.error;
+            last_tried = result.formats_tried;
+        }
+    }
+
+    let verified_keys = verified_key_ids.len();
+    let total = entries.len();
+    log::info!(
+        "{} SIGNATURE_QUORUM verified={}/{} threshold={}",
+        ctx, verified_keys, total, threshold
+    );
+
+    let result = if total == 0 {
+        SignatureVerificationResult::no_signature()
+    } else if verified_keys >= threshold.max(1) {
+        SignatureVerificationResult {
+            format: first_format,
+            formats_tried: first_tried,
+            ..SignatureVerificationResult::verified(&verified_key_ids[0])
+        }
+    } else {
+        SignatureVerificationResult {
+            verified: false,
+            key_id: verified_key_ids.first().cloned(),
+            error: Some(format!(
+                "Signature quorum not met: {} of {} required keys verified, \
+                 {} signatures attempted (last error: {})",
+                verified_keys,
+                threshold,
+                total,
+                last_error.unwrap_or_else(|| "none".to_string())
+            )),
+            format: None,
+            formats_tried: last_tried,
+        }
+    };
+
+    QuorumVerification { result, verified_keys, total, key_checks }
+}
+
+/// Canonical signing inputs for a trace, one per supported format, in the
+/// order the verify cascade tries them. No verification is attempted.
+///
+/// Returns `{"trace_id", "trace_level", "candidates": [{"format",
+/// "bytes_b64", "length", "sha256"}]}` so an agent developer can diff the
+/// exact bytes against their own signing input.
+///
+/// # Errors
+/// Invalid JSON or a trace without a `components` field.
+pub fn canonical_candidates(event_json: &str, trace_level: &str) -> Result<Value, String> {
+    use base64::{engine::general_purpose, Engine as _};
+
+    let trace: Value =
+        serde_json::from_str(event_json).map_err(|e| format!("invalid trace JSON: {}", e))?;
+    let components = trace
+        .get("components")
+        .ok_or_else(|| "trace has no components field".to_string())?;
+
+    let candidates: Vec<Value> = [
+        ("1.9.9", build_199_canonical(components, trace_level)),
+        ("1.9.8", build_198_canonical(components)),
+        ("1.9.7", sort_and_serialize(components)),
+        ("pre-1.9.7", sort_and_serialize_legacy(components)),
+        ("envelope", build_envelope_canonical(&trace)),
+    ]
+    .into_iter()
+    .chain(build_legacy_canonical(&trace, trace_level).map(|c| ("legacy-2field", c)))
+    .map(|(format, canonical)| {
+        serde_json::json!({
+            "format": format,
+            "bytes_b64": general_purpose::STANDARD.encode(canonical.as_bytes()),
+            "length": canonical.len(),
+            "sha256": crate::validation::signature::compute_hash(&canonical),
+        })
+    })
+    .collect();
+
+    Ok(serde_json::json!({
+        "trace_id": trace.get("trace_id").cloned().unwrap_or(Value::Null),
+        "trace_level": trace_level,
+        "candidates": candidates,
+    }))
+}
+
+/// Canonical strings of a bare components array in each components-only
+/// format, keyed by format name, with their SHA256 hex hashes.
+///
+/// For pasting an agent's components and diffing against the agent's
+/// own canonicalization; formats that need the whole trace (`envelope`,
+/// `legacy-2field`) are in `canonical_candidates` instead.
+pub fn canonical_forms(components_json: &str, trace_level: &str) -> Result<Value, String> {
+    let components: Value = serde_json::from_str(components_json)
+        .map_err(|e| format!("invalid components JSON: {}", e))?;
+
+    let forms: serde_json::Map<String, Value> = [
+        ("1.9.9", build_199_canonical(&components, trace_level)),
+        ("1.9.8", build_198_canonical(&components)),
+        ("1.9.7", sort_and_serialize(&components)),
+        ("pre-1.9.7", sort_and_serialize_legacy(&components)),
+    ]
+    .into_iter()
+    .map(|(format, canonical)| {
+        let sha256 = crate::validation::signature::compute_hash(&canonical);
+        (
+            format.to_string(),
+            serde_json::json!({"canonical": canonical, "sha256": sha256}),
+        )
+    })
+    .collect();
+
+    Ok(Value::Object(forms))
+}
+
+/// Canonical form for a format named in a trace's `sig_format`; None for
+/// names agents can't declare.
+fn declared_canonical(format: &str, trace: &Value, components: &Value, trace_level: &str) -> Option<String> {
+    Some(match format {
+        "1.9.9" => build_199_canonical(components, trace_level),
+        "1.9.9-pynum" => build_199_canonical_python_numbers(components, trace_level),
+        "1.9.8" => build_198_canonical(components),
+        "1.9.7" => sort_and_serialize(components),
+        "pre-1.9.7" => sort_and_serialize_legacy(components),
+        "envelope" => build_envelope_canonical(trace),
+        _ => return None,
+    })
+}
+
+/// Verify one signature over the trace's components, trying each
+/// canonical format in turn.
+///
+/// A key outside its validity window at `batch_timestamp` is rejected
+/// with `key_expired` / `key_not_yet_valid` before any format is tried.
+fn verify_components_signature(
+    trace: &Value,
+    batch_trace_level: &str,
+    batch_timestamp: DateTime<Utc>,
+    sig: &str,
+    kid: &str,
+    ctx: &LogContext,
+) -> crate::validation::signature::SignatureVerificationResult {
+    let validity = get_key_cache().check_key_validity(kid, batch_timestamp);
+    if let Err(reason) = validity {
+        log::warn!(
+            "{} SIGNATURE_KEY_OUTSIDE_WINDOW key_id={} reason={} batch_timestamp={}",
+            ctx,
+            kid,
+            reason,
+            batch_timestamp.to_rfc3339()
+        );
+        return crate::validation::signature::SignatureVerificationResult::invalid(kid, reason);
+    }
+
+    // Get components array
+    let components = match trace.get("components") {
+        Some(c) => c,
+        None => {
+            log::warn!("{} SIGNATURE_NO_COMPONENTS", ctx);
+            return crate::validation::signature::SignatureVerificationResult {
+                verified: false,
+                key_id: Some(kid.to_string()),
+                error: Some("No components array for signature verification".to_string()),
+                format: None,
+                formats_tried: Vec::new(),
+            };
+        }
+    };
+
+    // Pure EdDSA unless the agent declares Ed25519ph
+    let mode = match SignatureMode::from_field(trace.get("signature_mode").and_then(|v| v.as_str())) {
+        Ok(mode) => mode,
+        Err(e) => {
+            log::warn!("{} SIGNATURE_MODE_UNSUPPORTED error={}", ctx, e);
+            return crate::validation::signature::SignatureVerificationResult::invalid(kid, &e);
+        }
+    };
+
+    // Agents stamped 2.7.legacy sign the 2-field legacy form: try it first
+    let legacy_first = trace
+        .get("trace_schema_version")
+        .and_then(|v| v.as_str())
+        .is_some_and(|v| v.starts_with(LEGACY_SCHEMA_PREFIX));
+    let mut tried: Vec<String> = Vec::new();
+    if legacy_first {
+        if let Some(result) =
+            try_legacy_canonical(trace, batch_trace_level, sig, kid, mode, &mut tried, ctx)
+        {
+            return result.with_formats_tried(tried);
+        }
+    }
+
+    // Agents may name their canonical format in `sig_format`: try it
+    // first, then fall back to the full cascade
+    let declared = trace.get("sig_format").and_then(|v| v.as_str());
+    if let Some(format) = declared {
+        let level = trace
+            .get("trace_level")
+            .and_then(|v| v.as_str())
+            .unwrap_or(batch_trace_level);
+        match declared_canonical(format, trace, components, level) {
+            Some(canonical) => {
+                let started = Instant::now();
+                tried.push(format!("{}@declared", format));
+                let result = verify_signature_with_mode(&canonical, sig, kid, mode, ctx);
+                record_format_attempt(format, result.verified, started.elapsed());
+                if result.verified {
+                    log::info!(
+                        "{} SIGNATURE_VERIFIED format={} key_id={} reason=declared",
+                        ctx, format, kid
+                    );
+                    return result.with_format(format).with_formats_tried(tried);
+                }
+                log::debug!("{} SIGNATURE_DECLARED_FORMAT_FAILED format={}", ctx, format);
+            }
+            None => log::debug!("{} SIGNATURE_DECLARED_FORMAT_UNKNOWN format={:?}", ctx, format),
+        }
+    }
+
+    // The 1.9.9 wrapper signs the agent's trace_level. When the trace
+    // carries it, try that first: the batch level from the API request
+    // can disagree with what the agent signed.
+    let embedded_level = trace
+        .get("trace_level")
+        .and_then(|v| v.as_str())
+        .filter(|level| *level != batch_trace_level);
+    if let Some(level) = embedded_level {
+        let started = Instant::now();
+        let canonical = build_199_canonical(components, level);
+        tried.push(format!("1.9.9@{}", level));
+        let result = verify_signature_with_mode(&canonical, sig, kid, mode, ctx);
+        record_format_attempt("1.9.9", result.verified, started.elapsed());
+        if result.verified {
+            log::warn!(
+                "{} SIGNATURE_VERIFIED format=1.9.9 key_id={} trace_level={} batch_level={} \
+                 reason=embedded_level_mismatch",
+                ctx, kid, level, batch_trace_level
+            );
+            return result.with_format("1.9.9").with_formats_tried(tried);
+        }
+    }
+
+    // Batch-level trace_level (from the API request)
+    let trace_level = batch_trace_level;
+
+    // Try 1.9.9 format first: {"components": [...], "trace_level": "..."}
+    // Compact JSON with sorted keys, no stripping
+    let started_199 = Instant::now();
+    let canonical_199 = build_199_canonical(components, trace_level);
+    let hash_199 = crate::validation::signature::compute_hash(&canonical_199);
+    let hash_199_short = safe_truncate(&hash_199, 16);
+    let preview_start = safe_truncate(&canonical_199, 300);
+    log::info!(
+        "{} SIGNATURE_199_DEBUG key_id={} level={} len={} hash={} preview={}",
+        ctx, kid, trace_level, canonical_199.len(), hash_199_short, preview_start
+    );
+
+    tried.push("1.9.9".to_string());
+    let result_199 = verify_signature_with_mode(&canonical_199, sig, kid, mode, ctx);
+    record_format_attempt("1.9.9", result_199.verified, started_199.elapsed());
+    if result_199.verified {
+        log::info!(
+            "{} SIGNATURE_VERIFIED format=1.9.9 key_id={} len={} hash={}",
+            ctx, kid, canonical_199.len(), hash_199_short
+        );
+        return result_199.with_format("1.9.9").with_formats_tried(tried);
+    }
+
+    // Same wrapper with floats formatted like Python's repr(): serde
+    // writes e.g. 1e20 and 1e-7 where Python writes 1e+20 and 1e-07.
+    // Only tried when the two forms actually differ.
+    let canonical_199_py = build_199_canonical_python_numbers(components, trace_level);
+    if canonical_199_py != canonical_199 {
+        let started = Instant::now();
+        tried.push("1.9.9-pynum".to_string());
+        let result = verify_signature_with_mode(&canonical_199_py, sig, kid, mode, ctx);
+        record_format_attempt("1.9.9-pynum", result.verified, started.elapsed());
+        if result.verified {
+            log::info!(
+                "{} SIGNATURE_VERIFIED format=1.9.9-pynum key_id={} len={}",
+                ctx, kid, canonical_199_py.len()
+            );
+            return result.with_format("1.9.9-pynum").with_formats_tried(tried);
+        }
+    }
+
+    // Try 1.9.8 format: {"components": [...]} wrapper without trace_level
+    let started_198 = Instant::now();
+    let canonical_198 = build_198_canonical(components);
+    let hash_198 = crate::validation::signature::compute_hash(&canonical_198);
+    log::debug!(
+        "{} SIGNATURE_TRY_FORMAT format=1.9.8 key_id={} len={} hash={}",
+        ctx, kid, canonical_198.len(), hash_198
+    );
+
+    tried.push("1.9.8".to_string());
+    let result_198 = verify_signature_with_mode(&canonical_198, sig, kid, mode, ctx);
+    record_format_attempt("1.9.8", result_198.verified, started_198.elapsed());
+    if result_198.verified {
+        log::info!(
+            "{} SIGNATURE_VERIFIED format=1.9.8 key_id={} len={} hash={}",
+            ctx, kid, canonical_198.len(), hash_198
+        );
+        return result_198.with_format("1.9.8").with_formats_tried(tried);
+    }
+
+    // Try 1.9.7 format (compact + strip_empty, components only)
+    let started_197 = Instant::now();
+    let canonical_197 = sort_and_serialize(components);
+    let hash_197 = crate::validation::signature::compute_hash(&canonical_197);
+    log::debug!(
+        "{} SIGNATURE_TRY_FORMAT format=1.9.7 key_id={} len={} hash={}",
+        ctx, kid, canonical_197.len(), hash_197
+    );
+
+    tried.push("1.9.7".to_string());
+    let result_197 = verify_signature_with_mode(&canonical_197, sig, kid, mode, ctx);
+    record_format_attempt("1.9.7", result_197.verified, started_197.elapsed());
+    if result_197.verified {
+        log::info!(
+            "{} SIGNATURE_VERIFIED format=1.9.7 key_id={} len={} hash={}",
+            ctx, kid, canonical_197.len(), hash_197
+        );
+        return result_197.with_format("1.9.7").with_formats_tried(tried);
+    }
+
+    // Try pre-1.9.7 format (with spaces, no stripping, components only)
+    let started_pre197 = Instant::now();
+    let canonical_pre197 = sort_and_serialize_legacy(components);
+    let hash_pre197 = crate::validation::signature::compute_hash(&canonical_pre197);
+    log::debug!(
+        "{} SIGNATURE_TRY_FORMAT format=pre-1.9.7 key_id={} len={} hash={}",
+        ctx, kid, canonical_pre197.len(), hash_pre197
+    );
+
+    tried.push("pre-1.9.7".to_string());
+    let result_pre197 = verify_signature_with_mode(&canonical_pre197, sig, kid, mode, ctx);
+    record_format_attempt("pre-1.9.7", result_pre197.verified, started_pre197.elapsed());
+    if result_pre197.verified {
+        log::info!(
+            "{} SIGNATURE_VERIFIED format=pre-1.9.7 key_id={} len={} hash={}",
+            ctx, kid, canonical_pre197.len(), hash_pre197
+        );
+        return result_pre197.with_format("pre-1.9.7").with_formats_tried(tried);
+    }
+
+    // Last resort: the whole trace object minus the signature fields
+    let started_envelope = Instant::now();
+    let canonical_envelope = build_envelope_canonical(trace);
+    let hash_envelope = crate::validation::signature::compute_hash(&canonical_envelope);
+    log::debug!(
+        "{} SIGNATURE_TRY_FORMAT format=envelope key_id={} len={} hash={}",
+        ctx, kid, canonical_envelope.len(), hash_envelope
+    );
+
+    tried.push("envelope".to_string());
+    let result_envelope = verify_signature_with_mode(&canonical_envelope, sig, kid, mode, ctx);
+    record_format_attempt("envelope", result_envelope.verified, started_envelope.elapsed());
+    if result_envelope.verified {
+        log::info!(
+            "{} SIGNATURE_VERIFIED format=envelope key_id={} len={} hash={}",
+            ctx, kid, canonical_envelope.len(), hash_envelope
+        );
+        return result_envelope.with_format("envelope").with_formats_tried(tried);
+    }
+
+    if !legacy_first {
+        if let Some(result) =
+            try_legacy_canonical(trace, batch_trace_level, sig, kid, mode, &mut tried, ctx)
+        {
+            return result.with_formats_tried(tried);
+        }
+    }
+
+    // All formats failed - log details for troubleshooting
+    let preview_199 = safe_truncate(&canonical_199, 200);
+    log::warn!(
+        "{} SIGNATURE_VERIFICATION_FAILED key_id={} tried_formats=[{}] \
+         hash_199={} hash_198={} hash_197={} hash_pre197={} hash_envelope={} preview_199={}...",
+        ctx, kid, tried.join(","), hash_199_short, hash_198, hash_197, hash_pre197, hash_envelope, preview_199
+    );
+
+    // Return the 1.9.9 result (most recent format), naming every attempt
+    let mut result = result_199;
+    result.error = result
+        .error
+        .map(|e| format!("{} (formats tried: {})", e, tried.join(", ")));
+    result.with_formats_tried(tried)
+}
+
+/// `trace_schema_version` prefix of agents that sign the 2-field legacy form.
+const LEGACY_SCHEMA_PREFIX: &str = "2.7.legacy";
+
+/// Try the 2-field legacy canonical; `Some` only when it verifies.
+/// The attempt is appended to `tried` when the canonical can be built.
+fn try_legacy_canonical(
+    trace: &Value,
+    batch_trace_level: &str,
+    sig: &str,
+    kid: &str,
+    mode: SignatureMode,
+    tried: &mut Vec<String>,
+    ctx: &LogContext,
+) -> Option<crate::validation::signature::SignatureVerificationResult> {
+    let started = Instant::now();
+    let canonical = match build_legacy_canonical(trace, batch_trace_level) {
+        Some(canonical) => canonical,
+        None => {
+            log::debug!("{} SIGNATURE_LEGACY_SKIPPED reason=component_missing_fields", ctx);
+            return None;
+        }
+    };
+    let hash = crate::validation::signature::compute_hash(&canonical);
+    log::debug!(
+        "{} SIGNATURE_TRY_FORMAT format=legacy-2field key_id={} len={} hash={}",
+        ctx, kid, canonical.len(), hash
+    );
+
+    tried.push("legacy-2field".to_string());
+    let result = verify_signature_with_mode(&canonical, sig, kid, mode, ctx);
+    record_format_attempt("legacy-2field", result.verified, started.elapsed());
+    if !result.verified {
+        return None;
+    }
+    log::info!(
+        "{} SIGNATURE_VERIFIED format=legacy-2field key_id={} len={} hash={}",
+        ctx, kid, canonical.len(), hash
+    );
+    Some(result.with_format("legacy-2field"))
+}
+
+/// Build the 2-field legacy canonical signed by pre-2.7.8.9 agents
+/// (e.g. 2.7.6-stable).
+///
+/// Reproduces the agent's `sign_trace`: each component projected to
+/// `component_type`, `data`, `event_type` and `timestamp`, empty values
+/// stripped, wrapped as `{"components": [...], "trace_level": ...}` and
+/// dumped with Python's `json.dumps(sort_keys=True, separators=(",", ":"))`
+/// — so non-ASCII is `\uXXXX`-escaped. The trace's own `trace_level` is
+/// used, falling back to the batch level.
+///
+/// Returns None when a component lacks a field the agent requires.
+fn build_legacy_canonical(trace: &Value, batch_trace_level: &str) -> Option<String> {
+    let components = trace.get("components")?.as_array()?;
+    let projected = components
+        .iter()
+        .map(|component| {
+            let mut fields = serde_json::Map::new();
+            for key in ["component_type", "event_type", "timestamp"] {
+                fields.insert(key.to_string(), component.get(key)?.clone());
+            }
+            let data = component.get("data").cloned().unwrap_or_else(|| serde_json::json!({}));
+            fields.insert("data".to_string(), data);
+            Some(strip_empty_shallow(&Value::Object(fields)))
+        })
+        .collect::<Option<Vec<Value>>>()?;
+
+    let trace_level = trace
+        .get("trace_level")
+        .and_then(|v| v.as_str())
+        .unwrap_or(batch_trace_level);
+    let wrapper = serde_json::json!({
+        "components": projected,
+        "trace_level": trace_level,
+    });
+    Some(python_json_dumps(&wrapper))
+}
+
+/// Python agents' `strip_empty`: drops object entries whose value is
+/// empty *before* recursing, so an object emptied by stripping stays as
+/// `{}`. Array items are never dropped.
+fn strip_empty_shallow(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(_, v)| !is_empty_value(v))
+                .map(|(k, v)| (k.clone(), strip_empty_shallow(v)))
+                .collect(),
+        ),
+        Value::Array(arr) => Value::Array(arr.iter().map(strip_empty_shallow).collect()),
+        _ => value.clone(),
+    }
+}
+
+/// Serialize like Python's `json.dumps(v, sort_keys=True, separators=(",", ":"))`
+/// with the default `ensure_ascii=True`.
+fn python_json_dumps(value: &Value) -> String {
+    let mut out = String::new();
+    write_python_json(value, &mut out);
+    out
+}
+
+fn write_python_json(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut sorted: Vec<_> = map.iter().collect();
+            sorted.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (k, v)) in sorted.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_python_string(k, out);
+                out.push(':');
+                write_python_json(v, out);
+            }
+            out.push('}');
+        }
+        Value::Array(arr) => {
+            out.push('[');
+            for (i, v) in arr.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_python_json(v, out);
+            }
+            out.push(']');
+        }
+        Value::String(s) => write_python_string(s, out),
+        Value::Number(n) => out.push_str(&python_number(n)),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Null => out.push_str("null"),
+    }
+}
+
+/// Format a number the way Python's `json.dumps` does.
+///
+/// Integers print as-is. Floats use Python's `repr()`: shortest
+/// round-trip digits, positional for exponents in -4..16 (always with a
+/// fractional part, so `1e15` is `1000000000000000.0`), otherwise
+/// scientific with a signed, at least two-digit exponent (`1e+20`,
+/// `1e-07`). `-0.0` keeps its sign.
+///
+/// Integers beyond the u64/i64 range arrive here as floats (serde_json
+/// parses them that way), so they print as Python would print the float,
+/// not the original digits. NaN and infinities never reach this point:
+/// serde_json rejects them at parse time.
+fn python_number(n: &serde_json::Number) -> String {
+    match n.as_f64() {
+        Some(f) if n.is_f64() => python_float_repr(f),
+        _ => n.to_string(),
+    }
+}
+
+fn python_float_repr(f: f64) -> String {
+    // `{:e}` gives the shortest round-trip digits, e.g. "-1.5e-7", "0e0"
+    let scientific = format!("{:e}", f);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", mantissa),
+    };
+    let digits = mantissa.replace('.', "");
+
+    if (-4..16).contains(&exponent) {
+        let point = exponent + 1;
+        if point <= 0 {
+            format!("{}0.{}{}", sign, "0".repeat(point.unsigned_abs() as usize), digits)
+        } else if point as usize >= digits.len() {
+            format!("{}{}{}.0", sign, digits, "0".repeat(point as usize - digits.len()))
+        } else {
+            let (whole, fraction) = digits.split_at(point as usize);
+            format!("{}{}.{}", sign, whole, fraction)
+        }
+    } else {
+        let (first, rest) = digits.split_at(1);
+        let fraction = if rest.is_empty() { String::new() } else { format!(".{}", rest) };
+        let exp_sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}{}{}e{}{:02}", sign, first, fraction, exp_sign, exponent.unsigned_abs())
+    }
+}
+
+/// ASCII-only string escaping, matching Python's `ensure_ascii=True`.
+fn write_python_string(s: &str, out: &mut String) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            ' '..='~' => out.push(ch),
+            _ => {
+                let mut units = [0u16; 2];
+                for unit in ch.encode_utf16(&mut units) {
+                    out.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+    }
+    out.push('"');
+}
+
+/// Check if a value is "empty" (null, empty string, empty array, empty object).
+fn is_empty_value(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        Value::Array(arr) => arr.is_empty(),
+        Value::Object(map) => map.is_empty(),
+        _ => false,
+    }
+}
+
+/// Recursively strip empty values from a JSON value.
+fn strip_empty(value: &Value) -> Option<Value> {
+    match value {
+        Value::Object(map) => {
+            let filtered: serde_json::Map<String, Value> = map
+                .iter()
+                .filter_map(|(k, v)| {
+                    if is_empty_value(v) {
+                        None
+                    } else {
+                        strip_empty(v).map(|stripped| (k.clone(), stripped))
+                    }
+                })
+                .collect();
+            if filtered.is_empty() {
+                None
+            } else {
+                Some(Value::Object(filtered))
+            }
+        }
+        Value::Array(arr) => {
+            let filtered: Vec<Value> = arr
+                .iter()
+                .filter_map(|v| {
+                    if is_empty_value(v) {
+                        None
+                    } else {
+                        strip_empty(v)
+                    }
+                })
+                .collect();
+            if filtered.is_empty() {
+                None
+            } else {
+                Some(Value::Array(filtered))
+            }
+        }
+        _ => Some(value.clone()),
+    }
+}
+
+/// Serialize JSON value with sorted keys (recursive).
+/// Uses compact JSON (no spaces) and strips empty values to match agent's _strip_empty().
+fn sort_and_serialize(value: &Value) -> String {
+    // First strip empty values
+    let stripped = strip_empty(value).unwrap_or(Value::Null);
+    sort_and_serialize_inner(&stripped)
+}
+
+/// Inner serialization function (after stripping).
+fn sort_and_serialize_inner(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            // Sort keys and recursively process values
+            let mut sorted: Vec<_> = map.iter().collect();
+            sorted.sort_by(|a, b| a.0.cmp(b.0));
+
+            let pairs: Vec<String> = sorted
+                .iter()
+                .map(|(k, v)| format!("\"{}\":{}", k, sort_and_serialize_inner(v)))
+                .collect();
+
+            format!("{{{}}}", pairs.join(","))
+        }
+        Value::Array(arr) => {
+            let items: Vec<String> = arr.iter().map(sort_and_serialize_inner).collect();
+            format!("[{}]", items.join(","))
+        }
+        Value::String(s) => {
+            // Properly escape the string for JSON
+            serde_json::to_string(s).unwrap_or_else(|_| format!("\"{}\"", s))
+        }
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Null => "null".to_string(),
+    }
+}
+
+/// Serialize JSON value with sorted keys for pre-1.9.7 format.
+/// Uses spaces after `:` and `,` and does NOT strip empty values.
+/// This matches Python's default: json.dumps(obj, sort_keys=True)
+fn sort_and_serialize_legacy(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            // Sort keys and recursively process values
+            let mut sorted: Vec<_> = map.iter().collect();
+            sorted.sort_by(|a, b| a.0.cmp(b.0));
+
+            let pairs: Vec<String> = sorted
+                .iter()
+                .map(|(k, v)| format!("\"{}\": {}", k, sort_and_serialize_legacy(v)))
+                .collect();
+
+            format!("{{{}}}", pairs.join(", "))
+        }
+        Value::Array(arr) => {
+            let items: Vec<String> = arr.iter().map(sort_and_serialize_legacy).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::String(s) => {
+            // Properly escape the string for JSON
+            serde_json::to_string(s).unwrap_or_else(|_| format!("\"{}\"", s))
+        }
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Null => "null".to_string(),
+    }
+}
+
+/// Build 1.9.9 canonical message format.
+/// Wrapper object: {"components": [...], "trace_level": "..."}
+/// Compact JSON with sorted keys, NO stripping of empty values.
+/// Matches Python: json.dumps(payload, sort_keys=True, separators=(",", ":"))
+pub(crate) fn build_199_canonical(components: &Value, trace_level: &str) -> String {
+    // Serialize components with sorted keys, compact format, no stripping
+    let components_str = sort_and_serialize_compact(components);
+    // Build wrapper object with sorted keys: "components" comes before "trace_level"
+    format!("{{\"components\":{},\"trace_level\":\"{}\"}}", components_str, trace_level)
+}
+
+/// The 1.9.9 canonical with numbers formatted by `python_number`.
+fn build_199_canonical_python_numbers(components: &Value, trace_level: &str) -> String {
+    let components_str = sort_and_serialize_compact_with(components, python_number);
+    format!("{{\"components\":{},\"trace_level\":\"{}\"}}", components_str, trace_level)
+}
+
+/// Build canonical JSON for transitional 1.9.8 agents.
+///
+/// Same as 1.9.9 but the wrapper has no `trace_level` key:
+/// `{"components":[...]}`, compact with sorted keys, no stripping.
+fn build_198_canonical(components: &Value) -> String {
+    format!("{{\"components\":{}}}", sort_and_serialize_compact(components))
+}
+
+/// Build the "signed envelope" canonical form.
+///
+/// Some agents sign the whole trace object, extra top-level fields
+/// (`agent_version`, `timestamp`, ...) included. The canonical form is
+/// that object without the signature fields (`signature`,
+/// `signature_key_id`, and the `signatures` quorum array), compact with
+/// sorted keys, no stripping.
+fn build_envelope_canonical(trace: &Value) -> String {
+    let mut envelope = trace.clone();
+    if let Some(map) = envelope.as_object_mut() {
+        for field in ["signature", "signature_key_id", "signatures"] {
+            map.remove(field);
+        }
+    }
+    sort_and_serialize_compact(&envelope)
+}
+
+/// Serialize JSON value with sorted keys, compact format (no spaces).
+/// Does NOT strip empty values - keeps nulls, empty strings, etc.
+fn sort_and_serialize_compact(value: &Value) -> String {
+    sort_and_serialize_compact_with(value, serde_json::Number::to_string)
+}
+
+/// `sort_and_serialize_compact` with a custom number formatter.
+fn sort_and_serialize_compact_with(value: &Value, number: fn(&serde_json::Number) -> String) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut sorted: Vec<_> = map.iter().collect();
+            sorted.sort_by(|a, b| a.0.cmp(b.0));
+
+            let pairs: Vec<String> = sorted
+                .iter()
+                .map(|(k, v)| format!("\"{}\":{}", k, sort_and_serialize_compact_with(v, number)))
+                .collect();
+
+            format!("{{{}}}", pairs.join(","))
+        }
+        Value::Array(arr) => {
+            let items: Vec<String> = arr.iter().map(|v| sort_and_serialize_compact_with(v, number)).collect();
+            format!("[{}]", items.join(","))
         }
         Value::String(s) => {
             serde_json::to_string(s).unwrap_or_else(|_| format!("\"{}\"", s))
         }
-        Value::Number(n) => n.to_string(),
-        Value::Bool(b) => b.to_string(),
-        Value::Null => "null".to_string(),
+        Value::Number(n) => number(n),
+        Value::Bool(b) => b.to_string(),
+        Value::Null => "null".to_string(),
+    }
+}
+
+/// PII scrubbing step.
+///
+/// full_traces scrubs the schema's target fields (or the whole trace).
+/// Below full_traces only always-scrub fields are scrubbed, and only
+/// when the trace contains one — defense in depth for fields that must
+/// never be stored in the clear. Returns None for the result when no
+/// scrubbing ran.
+fn scrub_pii_for_level(
+    trace: &Value,
+    trace_level: &str,
+    schema_targets: Option<HashSet<String>>,
+    always_scrub: &HashSet<String>,
+    pii: &PiiConfig,
+    ctx: &LogContext,
+) -> (Value, Option<PiiScrubResult>) {
+    let targets = if trace_level == "full_traces" {
+        log::info!("{} PII_SCRUB_START level=full_traces", ctx);
+        schema_targets.map(|mut targets| {
+            targets.extend(always_scrub.iter().cloned());
+            targets
+        })
+    } else if contains_any_field(trace, always_scrub) {
+        log::info!("{} PII_SCRUB_START level={} reason=always_scrub_field", ctx, trace_level);
+        Some(always_scrub.clone())
+    } else {
+        log::debug!("{} PII_SKIPPED level={}", ctx, trace_level);
+        return (trace.clone(), None);
+    };
+
+    let (scrubbed, pii_result) = scrub_pii_with_mode(trace, pii.mode, pii.redaction_format(), targets.as_ref(), ctx);
+    if pii_result.total_entities() > 0 {
+        log::info!(
+            "{} PII_SCRUBBED total_entities={} fields_modified={}",
+            ctx,
+            pii_result.total_entities(),
+            pii_result.fields_modified
+        );
+    }
+    (scrubbed, Some(pii_result))
+}
+
+/// Extract metadata from connectivity events.
+/// Connectivity metadata, PII-scrubbed at full_traces level.
+///
+/// `event_data` holds the whole event, so at full_traces it gets the same
+/// scrubbing (and per-category counts) as regular traces.
+fn connectivity_metadata(
+    trace: &Value,
+    trace_level: &str,
+    pii: &PiiConfig,
+    ctx: &LogContext,
+) -> HashMap<String, String> {
+    if trace_level != "full_traces" {
+        return extract_connectivity_metadata(trace);
+    }
+
+    let (scrubbed, pii_result) = scrub_pii_with_mode(trace, pii.mode, pii.redaction_format(), None, ctx);
+    let mut metadata = extract_connectivity_metadata(&scrubbed);
+    for (column, count) in pii_result.category_columns() {
+        metadata.insert(column.to_string(), count.to_string());
+    }
+    metadata
+}
+
+fn extract_connectivity_metadata(trace: &Value) -> HashMap<String, String> {
+    let mut metadata = HashMap::new();
+
+    if let Some(event_type) = trace.get("event_type").and_then(|v| v.as_str()) {
+        metadata.insert("event_type".to_string(), event_type.to_string());
+    }
+
+    if let Some(agent_name) = trace.get("agent_name").and_then(|v| v.as_str()) {
+        metadata.insert("agent_name".to_string(), agent_name.to_string());
+    }
+
+    if let Some(agent_id) = trace.get("agent_id").and_then(|v| v.as_str()) {
+        metadata.insert("agent_id".to_string(), agent_id.to_string());
+    }
+
+    if let Some(agent_id_hash) = trace.get("agent_id_hash").and_then(|v| v.as_str()) {
+        metadata.insert("agent_id_hash".to_string(), agent_id_hash.to_string());
+    }
+
+    // Store full event data as JSON string
+    metadata.insert("event_data".to_string(), trace.to_string());
+
+    metadata
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_invalid_json() {
+        let ctx = BatchContext::new(
+            "2026-01-29T00:00:00Z",
+            None,
+            "detailed",
+            None,
+        );
+
+        let result = process_single_trace(&ctx, "invalid json{", false);
+        assert!(!result.accepted);
+        assert_eq!(result.destination, "malformed");
+        assert!(result.rejection_reason.is_some());
+    }
+
+    #[test]
+    fn test_truncated_json_reason() {
+        let ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+
+        let result = process_single_trace(&ctx, r#"{"trace_id": "test-123", "events": [{"#, false);
+        assert_eq!(result.destination, "malformed");
+        let reason = result.rejection_reason.unwrap();
+        assert!(reason.starts_with("truncated_json:"), "{}", reason);
+    }
+
+    #[test]
+    fn test_missing_required_fields_lenient_and_strict() {
+        let mut issues = ExtractionIssues::default();
+        issues.record("type_mismatch", "idma_k_eff");
+        assert_eq!(missing_required_fields(&issues, RequiredFieldCheck::Reject), None);
+
+        issues.record("missing_required", "thought_id");
+        issues.record("missing_required", "agent_name");
+        assert_eq!(
+            missing_required_fields(&issues, RequiredFieldCheck::NonEmpty),
+            Some(("agent_name,thought_id".to_string(), false))
+        );
+        assert_eq!(
+            missing_required_fields(&issues, RequiredFieldCheck::Reject),
+            Some(("agent_name,thought_id".to_string(), true))
+        );
+    }
+
+    #[test]
+    fn test_event_type_key_fallback() {
+        let trace = serde_json::json!({
+            "components": [
+                {"type": "THOUGHT_START"},
+                {"data": {"event_type": "ACTION_RESULT"}},
+                {"event_type": "DMA_RESULTS", "type": "ignored"}
+            ]
+        });
+        let ctx = LogContext::new("test-batch");
+
+        let mut config = PipelineConfig::default();
+        let result = validate_schema(&trace, &config, &ctx);
+        assert_eq!(result.event_types, HashSet::from(["DMA_RESULTS".to_string()]));
+
+        config.extraction.event_type_keys = ["event_type", "type", "data.event_type"].map(String::from).to_vec();
+        let result = validate_schema(&trace, &config, &ctx);
+        assert_eq!(
+            result.event_types,
+            HashSet::from([
+                "THOUGHT_START".to_string(),
+                "ACTION_RESULT".to_string(),
+                "DMA_RESULTS".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_declared_signature_format_tried_first() {
+        use base64::{engine::general_purpose, Engine as _};
+        use ed25519_dalek::Signer;
+
+        let key = register_test_key("declared-format-test", 65);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"x": 1}}]);
+        let canonical = build_198_canonical(&components);
+        let signature = general_purpose::STANDARD.encode(key.sign(canonical.as_bytes()).to_bytes());
+        let log_ctx = LogContext::new("test-batch");
+        let verify = |sig_format: &str| {
+            let trace = serde_json::json!({
+                "components": components,
+                "signature": signature,
+                "signature_key_id": "declared-format-test",
+                "sig_format": sig_format
+            });
+            verify_trace_signature(&trace, "detailed", Utc::now(), 0, &log_ctx)
+        };
+
+        let declared = verify("1.9.8");
+        assert!(declared.verified);
+        assert_eq!(declared.format.as_deref(), Some("1.9.8"));
+        assert_eq!(declared.formats_tried, ["1.9.8@declared"]);
+
+        // A wrong declaration falls back to the cascade
+        let wrong = verify("1.9.7");
+        assert!(wrong.verified);
+        assert_eq!(wrong.format.as_deref(), Some("1.9.8"));
+        assert_eq!(wrong.formats_tried[0], "1.9.7@declared");
+
+        let unknown = verify("2.0-experimental");
+        assert!(unknown.verified);
+        assert!(unknown.formats_tried.iter().all(|f| !f.ends_with("@declared")));
+    }
+
+    #[test]
+    fn test_json_error_classes() {
+        let ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        let cases = [
+            (r#"{"trace_id": "t", "components": ["#, "truncated"),
+            (r#"{"trace_id": "t"} {"trace_id": "u"}"#, "trailing_data"),
+            (r#"{"trace_id": "\ud800"}"#, "invalid_utf8"),
+            (r#"{"trace_id": "\udc00"}"#, "invalid_utf8"),
+            (r#"{"trace_id": 't'}"#, "unexpected_token"),
+            (r#"{"trace_id": "t",, "x": 1}"#, "unexpected_token"),
+            // An escaped backslash before "u" doesn't start a \u escape
+            ("{\"trace_id\": \"\\\\ud800\u{1}\"}", "unexpected_token"),
+        ];
+        for (input, class) in cases {
+            let result = process_single_trace(&ctx, input, false);
+            assert_eq!(result.destination, "malformed", "{}", input);
+            assert_eq!(result.extracted_metadata["error_class"], class, "{}", input);
+            let reason = if class == "truncated" { "truncated_json:" } else { "invalid_json:" };
+            assert!(result.rejection_reason.unwrap().starts_with(reason), "{}", input);
+        }
+
+        let trailing = r#"{"a": 1}
+  xyz"#;
+        let e = serde_json::from_str::<Value>(trailing).unwrap_err();
+        assert_eq!(JsonErrorClass::classify(trailing, &e), JsonErrorClass::TrailingData);
+        assert_eq!(&trailing[json_error_offset(trailing, &e) - 1..], "xyz");
+    }
+
+    #[test]
+    fn test_excessive_nesting_rejected_before_parse() {
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+
+        // Deeper than serde_json's own recursion limit
+        let nested = format!(r#"{{"trace_id": "t", "x": {}{}}}"#, "[".repeat(500), "]".repeat(500));
+        let result = process_single_trace(&ctx, &nested, false);
+        assert_eq!(result.rejection_reason.as_deref(), Some("excessive_nesting"));
+        assert_eq!(result.destination, "malformed");
+
+        // Brackets inside strings do not count
+        let quoted = format!(r#"{{"trace_id": "test-nesting", "x": "{}"}}"#, "[".repeat(500));
+        assert!(!exceeds_nesting_depth(&quoted, 64));
+        assert!(!exceeds_nesting_depth(r#"{"a": "\"[[["}"#, 1));
+
+        assert!(exceeds_nesting_depth(r#"{"a": {"b": {}}}"#, 2));
+        assert!(!exceeds_nesting_depth(r#"{"a": {"b": {}}}"#, 3));
+    }
+
+    #[test]
+    fn test_component_too_wide() {
+        let key = register_test_key("too-wide-test", 61);
+        let data: serde_json::Map<String, Value> =
+            (0..3_000).map(|i| (format!("k{}", i), Value::from(i))).collect();
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": data}]);
+        let event = serde_json::json!({
+            "trace_id": "test-too-wide",
+            "components": components,
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "too-wide-test"
+        })
+        .to_string();
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+        ctx.config.fast_reject.max_fields_per_component = 1_000;
+        ctx.config.fast_reject.wide_component_action = WideComponentAction::Reject;
+
+        let rejected = process_single_trace(&ctx, &event, false);
+        assert_eq!(rejected.destination, "malformed");
+        assert_eq!(rejected.rejection_reason.as_deref(), Some("component_too_wide"));
+
+        // Keys are counted across nesting levels
+        let nested = serde_json::json!({"components": [{"data": {"a": data.clone(), "b": data}}]});
+        assert!(component_too_wide(&nested, 5_000));
+        assert!(!component_too_wide(&nested, 6_003));
+
+        // Flag keeps the trace and marks it
+        ctx.config.fast_reject.wide_component_action = WideComponentAction::Flag;
+        let flagged = process_single_trace(&ctx, &event, false);
+        assert!(flagged.accepted, "{:?}", flagged.rejection_reason);
+        assert_eq!(flagged.extracted_metadata["component_too_wide"], "true");
+    }
+
+    #[test]
+    fn test_metadata_cap_keeps_pipeline_flags() {
+        let key = register_test_key("metadata-cap-test", 70);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"a": 1, "b": 2, "c": 3}}]);
+        let event = serde_json::json!({
+            "trace_id": "test-metadata-cap",
+            "components": components,
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "metadata-cap-test"
+        })
+        .to_string();
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+        ctx.config.fast_reject.max_fields_per_component = 2;
+        ctx.config.fast_reject.wide_component_action = WideComponentAction::Flag;
+        ctx.config.extraction.max_metadata_entries = 1;
+
+        let result = process_single_trace(&ctx, &event, false);
+        assert!(result.accepted, "{:?}", result.rejection_reason);
+        for key in ["component_too_wide", "consent_source", "signature_verified", "is_production"] {
+            assert!(result.extracted_metadata.contains_key(key), "{} dropped", key);
+        }
+    }
+
+    #[test]
+    fn test_complete_but_invalid_json_reason() {
+        let ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+
+        let result = process_single_trace(&ctx, r#"{"trace_id": "test-123",, "events": []}"#, false);
+        assert_eq!(result.destination, "malformed");
+        let reason = result.rejection_reason.unwrap();
+        assert!(reason.starts_with("invalid_json:"), "{}", reason);
+    }
+
+    #[test]
+    fn test_repeated_malformed_payload_fast_path() {
+        let ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        let payload = r#"{"trace_id": "test-known-malformed", "components": "#;
+
+        assert!(!rejected_before_parse(&ctx, payload));
+        let first = process_single_trace_guarded(&ctx, payload, false);
+        assert!(first.rejection_reason.unwrap().starts_with("truncated_json:"));
+
+        // Batch verification skips it from now on
+        assert!(rejected_before_parse(&ctx, payload));
+        let second = process_single_trace_guarded(&ctx, payload, false);
+        assert_eq!(second.destination, "malformed");
+        assert_eq!(second.rejection_reason.as_deref(), Some("known_malformed"));
+    }
+
+    #[test]
+    fn test_batch_dependent_rejection_not_remembered() {
+        let key = register_test_key("known-malformed-batch", 69);
+        let components = serde_json::json!([
+            {"event_type": "THOUGHT_START", "timestamp": "2026-01-28T22:54:00Z", "data": {}}
+        ]);
+        let event = serde_json::json!({
+            "trace_id": "test-known-malformed-stale",
+            "components": components,
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "known-malformed-batch"
+        })
+        .to_string();
+
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.timestamps.max_trace_age_secs = 3_600;
+        let stale = process_single_trace_guarded(&ctx, &event, false);
+        assert_eq!(stale.rejection_reason.as_deref(), Some("stale_trace"));
+
+        // A later batch with an earlier timestamp accepts the same payload
+        let mut ctx = BatchContext::new("2026-01-28T23:00:00Z", None, "detailed", None);
+        ctx.config.timestamps.max_trace_age_secs = 3_600;
+        let retried = process_single_trace_guarded(&ctx, &event, false);
+        assert!(retried.accepted, "{:?}", retried.rejection_reason);
+    }
+
+    #[test]
+    fn test_batch_metrics() {
+        let key = register_test_key("metrics-test", 63);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {}}]);
+        let event = serde_json::json!({
+            "trace_id": "test-metrics",
+            "components": components,
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "metrics-test"
+        })
+        .to_string();
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+
+        let result = process_batch(&ctx, vec![event]);
+        assert_eq!(result.accepted_count, 1, "{:?}", result.traces[0].rejection_reason);
+        let stored: usize = result.traces[0].extracted_metadata.values().map(String::len).sum();
+        assert!(stored > 0);
+        assert_eq!(result.bytes_stored, stored);
+
+        let metrics = serde_json::to_value(result.metrics).unwrap();
+        for key in ["total_us", "signature_us", "pii_scrub_us", "extraction_us"] {
+            assert!(metrics[key].is_u64(), "{}", key);
+        }
+        let stages = result.metrics.signature_us + result.metrics.pii_scrub_us + result.metrics.extraction_us;
+        assert!(stages <= result.metrics.total_us);
+    }
+
+    #[test]
+    fn test_batch_hash_chain() {
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+        let batch = |ctx: &BatchContext, events: &[&str]| {
+            process_batch(ctx, events.iter().map(|e| e.to_string()).collect()).batch_hash
+        };
+        let events = [r#"{"trace_id": "chain-1"}"#, r#"{"trace_id": "chain-2"}"#];
+        assert!(batch(&ctx, &events).is_none());
+
+        ctx.config.batch.hash_chain = true;
+        let first = batch(&ctx, &events).unwrap();
+        let tampered = batch(&ctx, &[events[0], r#"{"trace_id": "chain-2", "x": 1}"#]).unwrap();
+        assert_ne!(first, tampered);
+
+        ctx.previous_batch_hash = Some(first.clone());
+        let second = batch(&ctx, &[r#"{"trace_id": "chain-3"}"#]).unwrap();
+        let expected = chain_batch_hash(Some(&first), &[compute_hash(r#"{"trace_id": "chain-3"}"#)]);
+        assert_eq!(second, expected);
+        assert_ne!(second, chain_batch_hash(None, &[compute_hash(r#"{"trace_id": "chain-3"}"#)]));
+    }
+
+    #[test]
+    fn test_sequence_gaps_and_regressions_flagged() {
+        let key = register_test_key("sequence-test", 68);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START"}]);
+        let signature = sign_components(&key, &components);
+        let event = |seq: u64| {
+            serde_json::json!({
+                "trace_id": format!("sequence-{}", seq),
+                "agent_id_hash": "sequence-test-agent",
+                "seq": seq,
+                "components": components,
+                "signature": signature,
+                "signature_key_id": "sequence-test"
+            })
+            .to_string()
+        };
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+        ctx.config.sequence.tracked_agents = 100;
+
+        let result = process_batch(&ctx, [1, 2, 5, 3].map(event).to_vec());
+        let flags: Vec<(Option<&str>, Option<&str>, Option<&str>)> = result
+            .traces
+            .iter()
+            .map(|t| {
+                assert!(t.accepted, "{:?}", t.rejection_reason);
+                let get = |k: &str| t.extracted_metadata.get(k).map(|v| v.as_str());
+                (get("seq_gap"), get("seq_regression"), get("seq_previous"))
+            })
+            .collect();
+        assert_eq!(
+            flags,
+            [
+                (None, None, None),
+                (None, None, None),
+                (Some("true"), None, Some("2")),
+                (None, Some("true"), Some("5")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_only_matches_full_run() {
+        let key = register_test_key("dry-run-test", 64);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"models_used": ["gpt-4"]}}]);
+        let event = |trace_id: &str, signature: &str| {
+            serde_json::json!({
+                "trace_id": trace_id,
+                "components": components,
+                "signature": signature,
+                "signature_key_id": "dry-run-test"
+            })
+            .to_string()
+        };
+        let signature = sign_components(&key, &components);
+        let events = vec![
+            event("dry-run-good", &signature),
+            event("dry-run-bad-signature", "AAAA"),
+            r#"{"trace_id": "dry-run-truncated", "components": "#.to_string(),
+            event("dry-run-good", &signature),
+        ];
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+
+        let full = process_batch(&ctx, events.clone());
+        ctx.validate_only = true;
+        let dry = process_batch(&ctx, events);
+
+        assert_eq!(full.accepted_count, 1);
+        assert_eq!(dry.accepted_count, full.accepted_count);
+        for (dry, full) in dry.traces.iter().zip(&full.traces) {
+            assert_eq!(dry.accepted, full.accepted, "{}", full.trace_id);
+            assert_eq!(dry.destination, full.destination, "{}", full.trace_id);
+            assert_eq!(dry.schema_version, full.schema_version);
+            assert_eq!(dry.rejection_reason, full.rejection_reason);
+            assert!(dry.extracted_metadata.is_empty());
+        }
+        assert!(!full.traces[0].extracted_metadata.is_empty());
+    }
+
+    #[test]
+    fn test_key_activity_per_key() {
+        let key_a = register_test_key("activity-test-a", 66);
+        let _key_b = register_test_key("activity-test-b", 67);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {}}]);
+        let event = |trace_id: &str, key_id: &str, signature: &str| {
+            serde_json::json!({
+                "trace_id": trace_id,
+                "components": components,
+                "signature": signature,
+                "signature_key_id": key_id
+            })
+            .to_string()
+        };
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+        ctx.config.signature.verification_cache_capacity = 0;
+        let signature_a = sign_components(&key_a, &components);
+        process_batch(
+            &ctx,
+            vec![
+                event("activity-1", "activity-test-a", &signature_a),
+                event("activity-2", "activity-test-b", &signature_a),
+                event("activity-3", "activity-test-unregistered", &signature_a),
+            ],
+        );
+
+        let activity: Vec<_> = crate::validation::signature::get_key_activity()
+            .into_iter()
+            .filter(|entry| entry.key_id.starts_with("activity-test-"))
+            .collect();
+        assert_eq!(activity.len(), 2);
+        assert_eq!(activity[0].key_id, "activity-test-a");
+        assert_eq!((activity[0].verified, activity[0].failed), (1, 0));
+        assert!(activity[0].formats.contains("1.9.9"));
+        assert_eq!(activity[1].key_id, "activity-test-b");
+        assert_eq!((activity[1].verified, activity[1].failed), (0, 1));
+        assert!(activity[1].formats.is_empty());
+    }
+
+    #[test]
+    fn test_process_empty_events() {
+        let ctx = BatchContext::new(
+            "2026-01-29T00:00:00Z",
+            None,
+            "detailed",
+            None,
+        );
+
+        let result = process_single_trace(&ctx, r#"{"trace_id": "test-123"}"#, false);
+        // Without schema cache loaded, this should fail validation
+        assert!(!result.accepted);
+        assert_eq!(result.destination, "malformed");
+    }
+
+    #[test]
+    fn test_panicking_trace_does_not_abort_batch() {
+        let ctx = BatchContext::new(
+            "2026-01-29T00:00:00Z",
+            None,
+            "detailed",
+            None,
+        );
+
+        let events = vec![
+            r#"{"trace_id": "before"}"#.to_string(),
+            format!(r#"{{"trace_id": "{}"}}"#, TEST_PANIC_TRACE_ID),
+            r#"{"trace_id": "after"}"#.to_string(),
+        ];
+        let result = process_batch(&ctx, events);
+
+        assert_eq!(result.received_count, 3);
+        assert_eq!(result.traces.len(), 3);
+        let panicked = &result.traces[1];
+        assert_eq!(panicked.trace_id, TEST_PANIC_TRACE_ID);
+        assert_eq!(panicked.destination, "malformed");
+        assert_eq!(panicked.rejection_reason.as_deref(), Some("internal_panic"));
+        assert_eq!(result.traces[2].trace_id, "after");
+    }
+
+    #[test]
+    fn test_duplicate_trace_ids_in_batch() {
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+        let event = r#"{"trace_id": "test-dup-in-batch", "components": []}"#.to_string();
+        let events = vec![
+            event.clone(),
+            r#"{"trace_id": "test-dup-in-batch", "components": [{}]}"#.to_string(),
+            event,
+            r#"{"components": []}"#.to_string(),
+        ];
+
+        let result = process_batch(&ctx, events);
+        assert_eq!(result.received_count, 4);
+        assert_eq!(result.duplicate_count, 2);
+        assert_eq!(result.accepted_count + result.rejected_count, 4);
+
+        let first = &result.traces[0];
+        assert_ne!(first.destination, "duplicate");
+        for repeat in &result.traces[1..3] {
+            assert_eq!(repeat.trace_id, "test-dup-in-batch");
+            assert_eq!(repeat.destination, "duplicate");
+            assert!(!repeat.accepted);
+            assert_eq!(repeat.rejection_reason.as_deref(), Some("duplicate_in_batch"));
+            assert!(repeat.signature_formats_tried.is_empty());
+        }
+        // Events without a trace_id aren't deduplicated
+        assert_ne!(result.traces[3].destination, "duplicate");
+    }
+
+    #[test]
+    fn test_declared_trace_id_stops_at_key() {
+        assert_eq!(declared_trace_id(r#"{"components": [], "trace_id": "b"}"#).as_deref(), Some("b"));
+        // Nothing after the id is read
+        assert_eq!(declared_trace_id(r#"{"trace_id": "a", "components": ["#).as_deref(), Some("a"));
+        assert_eq!(declared_trace_id(r#"{"components": []}"#), None);
+        assert_eq!(declared_trace_id(r#"{"trace_id": null}"#), None);
+        assert_eq!(declared_trace_id("not json"), None);
+    }
+
+    #[test]
+    fn test_timestamp_guards_allow_clock_skew() {
+        let key = register_test_key("timestamp-test", 65);
+        let event = |ts: &str| {
+            let components = serde_json::json!([{"event_type": "THOUGHT_START", "timestamp": ts, "data": {}}]);
+            serde_json::json!({
+                "trace_id": format!("test-timestamp-{}", ts),
+                "components": components,
+                "signature": sign_components(&key, &components),
+                "signature_key_id": "timestamp-test"
+            })
+            .to_string()
+        };
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+
+        // Guards are off by default
+        assert!(process_single_trace(&ctx, &event("2026-01-29T01:00:00Z"), false).accepted);
+
+        ctx.config.timestamps.reject_future_timestamps = true;
+        ctx.config.timestamps.max_trace_age_secs = 3_600;
+        for (ts, reason) in [
+            ("2026-01-29T00:04:00Z", None),
+            ("2026-01-29T00:06:00Z", Some("future_timestamp")),
+            ("2026-01-28T22:56:00Z", None),
+            ("2026-01-28T22:54:00Z", Some("stale_trace")),
+        ] {
+            let result = process_single_trace(&ctx, &event(ts), false);
+            assert_eq!(result.accepted, reason.is_none(), "{} {:?}", ts, result.rejection_reason);
+            assert_eq!(result.rejection_reason.as_deref(), reason, "{}", ts);
+        }
+
+        ctx.batch_timestamp = Utc::now() + chrono::Duration::seconds(240);
+        assert!(process_single_trace(&ctx, &event(&ctx.batch_timestamp.to_rfc3339()), false).accepted);
+        ctx.batch_timestamp = Utc::now() + chrono::Duration::seconds(360);
+        let future_batch = process_single_trace(&ctx, &event(&ctx.batch_timestamp.to_rfc3339()), false);
+        assert_eq!(future_batch.rejection_reason.as_deref(), Some("future_batch"));
+    }
+
+    #[test]
+    fn test_fail_fast_stops_after_first_rejection() {
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        let events = vec![
+            "not json".to_string(),
+            r#"{"trace_id": "test-fail-fast-2"}"#.to_string(),
+            r#"{"trace_id": "test-fail-fast-3"}"#.to_string(),
+        ];
+
+        let best_effort = process_batch(&ctx, events.clone());
+        assert!(!best_effort.aborted);
+        assert_eq!(best_effort.traces.len(), 3);
+
+        ctx.fail_fast = true;
+        let result = process_batch(&ctx, events);
+        assert!(result.aborted);
+        assert_eq!(result.received_count, 3);
+        assert_eq!(result.traces.len(), 1);
+        assert_eq!(result.rejected_count, 1);
+        assert!(!result.traces[0].accepted);
+    }
+
+    #[test]
+    fn test_fail_fast_stops_at_duplicate_trace_id() {
+        let key = register_test_key("fail-fast-dup-test", 64);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {}}]);
+        let event = serde_json::json!({
+            "trace_id": "test-fail-fast-dup",
+            "components": components,
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "fail-fast-dup-test"
+        })
+        .to_string();
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.fail_fast = true;
+
+        let result = process_batch(
+            &ctx,
+            vec![event.clone(), event, r#"{"trace_id": "test-fail-fast-dup-3"}"#.to_string()],
+        );
+        assert!(result.aborted);
+        assert_eq!(result.traces.len(), 2);
+        assert!(result.traces[0].accepted, "{:?}", result.traces[0].rejection_reason);
+        assert_eq!(result.traces[1].destination, "duplicate");
+        assert_eq!((result.accepted_count, result.rejected_count), (1, 1));
+    }
+
+    #[test]
+    fn test_derived_trace_ids_are_stable_and_distinct() {
+        let a: Value = serde_json::from_str(r#"{"components": [{"event_type": "A"}]}"#).unwrap();
+        let a_again: Value =
+            serde_json::from_str(r#"{ "components" : [ {"event_type": "A"} ] }"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"components": [{"event_type": "B"}]}"#).unwrap();
+
+        let id_a = derive_trace_id(&a);
+        assert_eq!(id_a, derive_trace_id(&a_again));
+        assert_ne!(id_a, derive_trace_id(&b));
+        assert_eq!(uuid::Uuid::parse_str(&id_a).unwrap().get_version_num(), 5);
+
+        // These traces are rejected; keep the repeat off the known-malformed path
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+        let result = process_batch(
+            &ctx,
+            vec![a.to_string(), b.to_string(), a_again.to_string()],
+        );
+        assert_eq!(result.traces[0].trace_id, id_a);
+        assert_ne!(result.traces[1].trace_id, id_a);
+        assert_eq!(result.traces[2].trace_id, id_a);
+    }
+
+    #[test]
+    fn test_always_scrub_field_scrubbed_below_full_traces() {
+        let log_ctx = LogContext::new("test-batch");
+        let always = HashSet::from(["operator_contact".to_string()]);
+        let trace = serde_json::json!({
+            "components": [{"data": {
+                "operator_contact": "ops@example.com",
+                "reasoning": "user said bob@example.com"
+            }}]
+        });
+
+        let (scrubbed, result) =
+            scrub_pii_for_level(&trace, "generic", None, &always, &PiiConfig::default(), &log_ctx);
+        let data = &scrubbed["components"][0]["data"];
+        assert_eq!(data["operator_contact"], "[EMAIL]");
+        assert_eq!(data["reasoning"], "user said bob@example.com");
+        assert_eq!(result.unwrap().emails_found, 1);
+
+        // No always-scrub field present: nothing runs
+        let plain = serde_json::json!({"components": [{"data": {"reasoning": "a@b.example"}}]});
+        let (unchanged, result) =
+            scrub_pii_for_level(&plain, "detailed", None, &always, &PiiConfig::default(), &log_ctx);
+        assert_eq!(unchanged, plain);
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_bytes_received_sums_input_lengths() {
+        let ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        let events = vec![
+            r#"{"trace_id": "test-bytes-1"}"#.to_string(),
+            "not json".to_string(),
+            r#"{"trace_id": "test-bytes-ü"}"#.to_string(),
+        ];
+        let expected: usize = events.iter().map(|e| e.len()).sum();
+
+        let result = process_batch(&ctx, events);
+        assert_eq!(result.bytes_received, expected);
+        assert_eq!(result.bytes_stored, 0);
+    }
+
+    #[test]
+    fn test_rejections_recorded_in_recent_buffer() {
+        use crate::pipeline::recent_rejections::get_recent_rejections;
+
+        let ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        process_batch(
+            &ctx,
+            vec![r#"{"trace_id": "test-recent-rejection", "signature_key_id": "k-recent"}"#.to_string()],
+        );
+
+        let recent = get_recent_rejections();
+        let record = recent
+            .iter()
+            .find(|r| r.trace_id == "test-recent-rejection")
+            .expect("rejection not recorded");
+        assert_eq!(record.key_id.as_deref(), Some("k-recent"));
+        assert!(!record.reason.is_empty());
+        assert!(recent.len() <= ctx.config.diagnostics.recent_rejections);
+    }
+
+    #[test]
+    fn test_slow_batch_sets_backpressure() {
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.backpressure.max_trace_ms = 25;
+        ctx.config.backpressure.backoff_ms = 2_500;
+
+        let fast = process_batch(&ctx, vec![r#"{"trace_id": "test-fast"}"#.to_string()]);
+        assert!(!fast.processing_overloaded);
+        assert_eq!(fast.suggested_backoff_ms, 0);
+
+        let slow = process_batch(
+            &ctx,
+            vec![format!(r#"{{"trace_id": "{}"}}"#, TEST_SLOW_TRACE_ID)],
+        );
+        assert!(slow.processing_overloaded);
+        assert_eq!(slow.suggested_backoff_ms, 2_500);
+    }
+
+    #[test]
+    fn test_reprocess_event_after_key_load() {
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[58; 32]);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"thought_id": "th-1"}}]);
+        let event = serde_json::json!({
+            "trace_id": "test-replay",
+            "components": components,
+            "signature": sign_components(&signing_key, &components),
+            "signature_key_id": "replay-test"
+        })
+        .to_string();
+        let ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+
+        let first = process_single_trace_guarded(&ctx, &event, false);
+        assert!(!first.accepted);
+
+        // As load_public_keys_from_db does, forget the cached failure
+        register_test_key("replay-test", 58);
+        crate::validation::verification_cache::clear_verification_cache();
+        let replayed = reprocess_event(&ctx, &event);
+        assert!(replayed.accepted, "{:?}", replayed.rejection_reason);
+        assert_eq!(replayed.destination, "production");
+        assert_eq!(replayed.extracted_metadata["replayed"], "true");
+    }
+
+    #[test]
+    fn test_throughput_mode_matches_full_mode_destination() {
+        let key = register_test_key("throughput-test", 31);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"thought_id": "th-1"}}]);
+        let event = serde_json::json!({
+            "trace_id": "test-throughput",
+            "components": components,
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "throughput-test"
+        })
+        .to_string();
+
+        let full_ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        let mut throughput_ctx = full_ctx.clone();
+        throughput_ctx.extraction_enabled = false;
+
+        let full = process_single_trace(&full_ctx, &event, false);
+        let throughput = process_single_trace(&throughput_ctx, &event, false);
+
+        assert!(full.accepted, "{:?}", full.rejection_reason);
+        assert!(throughput.accepted, "{:?}", throughput.rejection_reason);
+        assert_eq!(throughput.destination, full.destination);
+        assert_eq!(throughput.extracted_metadata["signature_verified"], "true");
+        assert_eq!(throughput.extracted_metadata["signature_key_id"], "throughput-test");
+    }
+
+    #[test]
+    fn test_connectivity_event_data_scrubbed_at_full_traces() {
+        let log_ctx = LogContext::new("test-batch");
+        let trace = serde_json::json!({
+            "event_type": "startup",
+            "agent_name": "datum",
+            "data": {"operator_contact": "ops@example.com"}
+        });
+
+        let full = connectivity_metadata(&trace, "full_traces", &PiiConfig::default(), &log_ctx);
+        assert!(!full["event_data"].contains("ops@example.com"));
+        assert!(full["event_data"].contains("[EMAIL]"));
+        assert_eq!(full["pii_email_count"], "1");
+        assert_eq!(full["agent_name"], "datum");
+
+        let detailed = connectivity_metadata(&trace, "detailed", &PiiConfig::default(), &log_ctx);
+        assert!(detailed["event_data"].contains("ops@example.com"));
+        assert!(!detailed.contains_key("pii_email_count"));
+    }
+
+    #[test]
+    fn test_verify_records_attempt_per_format() {
+        use crate::validation::signature::get_signature_metrics;
+
+        let log_ctx = LogContext::new("test-batch");
+        let trace = serde_json::json!({
+            "trace_id": "test-metrics",
+            "signature": "not-a-real-signature",
+            "signature_key_id": "test-key",
+            "components": [{"event_type": "THOUGHT_START", "data": {}}]
+        });
+
+        let attempts = |format: &str| {
+            get_signature_metrics()
+                .get(format)
+                .map(|m| m.attempts)
+                .unwrap_or(0)
+        };
+        let formats = ["1.9.9", "1.9.8", "1.9.7", "pre-1.9.7"];
+        let before: Vec<u64> = formats.iter().map(|f| attempts(f)).collect();
+
+        let result = verify_trace_signature(&trace, "detailed", Utc::now(), 0, &log_ctx);
+        assert!(!result.verified);
+
+        // Other tests may verify concurrently, so only a lower bound holds.
+        for (format, before) in formats.iter().zip(before) {
+            assert!(attempts(format) > before, "no attempt recorded for {}", format);
+        }
     }
-}
 
-/// Extract metadata from connectivity events.
-fn extract_connectivity_metadata(trace: &Value) -> HashMap<String, String> {
-    let mut metadata = HashMap::new();
+    /// Register a deterministic test key in the global key cache and
+    /// return a signer for it. Key ids are unique per test.
+    fn register_test_key(key_id: &str, seed: u8) -> ed25519_dalek::SigningKey {
+        use base64::{engine::general_purpose, Engine as _};
 
-    if let Some(event_type) = trace.get("event_type").and_then(|v| v.as_str()) {
-        metadata.insert("event_type".to_string(), event_type.to_string());
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[seed; 32]);
+        let public_b64 = general_purpose::STANDARD.encode(signing_key.verifying_key().to_bytes());
+        crate::validation::signature::get_key_cache_mut()
+            .load_key(key_id, &public_b64)
+            .unwrap();
+        signing_key
     }
 
-    if let Some(agent_name) = trace.get("agent_name").and_then(|v| v.as_str()) {
-        metadata.insert("agent_name".to_string(), agent_name.to_string());
+    fn sign_components(signing_key: &ed25519_dalek::SigningKey, components: &Value) -> String {
+        use base64::{engine::general_purpose, Engine as _};
+        use ed25519_dalek::Signer;
+
+        let canonical = build_199_canonical(components, "detailed");
+        general_purpose::STANDARD.encode(signing_key.sign(canonical.as_bytes()).to_bytes())
     }
 
-    if let Some(agent_id) = trace.get("agent_id").and_then(|v| v.as_str()) {
-        metadata.insert("agent_id".to_string(), agent_id.to_string());
+    fn quorum_trace() -> Value {
+        let key_a = register_test_key("quorum-test-a", 11);
+        let key_b = register_test_key("quorum-test-b", 12);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"x": 1}}]);
+
+        serde_json::json!({
+            "trace_id": "test-quorum",
+            "components": components,
+            "signatures": [
+                {"signature": sign_components(&key_a, &components), "signature_key_id": "quorum-test-a"},
+                {"signature": sign_components(&key_b, &components), "signature_key_id": "quorum-test-b"},
+                // Same key again: must not count twice
+                {"signature": sign_components(&key_a, &components), "signature_key_id": "quorum-test-a"},
+                {"signature": "bm90LWEtc2lnbmF0dXJl", "signature_key_id": "quorum-test-unknown"}
+            ]
+        })
     }
 
-    if let Some(agent_id_hash) = trace.get("agent_id_hash").and_then(|v| v.as_str()) {
-        metadata.insert("agent_id_hash".to_string(), agent_id_hash.to_string());
+    #[test]
+    fn test_signature_quorum_met() {
+        let log_ctx = LogContext::new("test-batch");
+        let quorum = verify_trace_signatures(&quorum_trace(), "detailed", Utc::now(), 2, 0, &log_ctx);
+
+        assert!(quorum.result.verified, "{:?}", quorum.result.error);
+        assert_eq!(quorum.verified_keys, 2);
+        assert_eq!(quorum.total, 4);
     }
 
-    // Store full event data as JSON string
-    metadata.insert("event_data".to_string(), trace.to_string());
+    #[test]
+    fn test_signature_quorum_not_met() {
+        let log_ctx = LogContext::new("test-batch");
+        let quorum = verify_trace_signatures(&quorum_trace(), "detailed", Utc::now(), 3, 0, &log_ctx);
 
-    metadata
-}
+        assert!(!quorum.result.verified);
+        assert_eq!(quorum.verified_keys, 2);
+        assert!(quorum
+            .result
+            .error
+            .unwrap()
+            .starts_with("Signature quorum not met: 2 of 3"));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_key_id_variants_count_once_toward_quorum() {
+        let key = register_test_key("quorum-test-variant", 15);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"v": 1}}]);
+        let signature = sign_components(&key, &components);
+        let trace = serde_json::json!({
+            "trace_id": "test-quorum-variant",
+            "components": components,
+            "signatures": [
+                {"signature": signature, "key_id": "quorum-test-variant"},
+                {"signature": signature, "key_id": "quorum-test-variant "},
+                {"signature": signature, "key_id": " QUORUM-TEST-VARIANT"}
+            ]
+        });
+        let log_ctx = LogContext::new("test-batch");
+
+        let quorum = verify_trace_signatures(&trace, "detailed", Utc::now(), 2, 0, &log_ctx);
+        assert!(!quorum.result.verified);
+        assert_eq!(quorum.verified_keys, 1);
+
+        crate::validation::signature::get_key_cache_mut().set_case_insensitive_ids(true);
+        let quorum = verify_trace_signatures(&trace, "detailed", Utc::now(), 2, 0, &log_ctx);
+        crate::validation::signature::get_key_cache_mut().set_case_insensitive_ids(false);
+        assert!(!quorum.result.verified);
+        assert_eq!(quorum.verified_keys, 1);
+    }
 
     #[test]
-    fn test_process_invalid_json() {
-        let ctx = BatchContext::new(
-            "2026-01-29T00:00:00Z",
-            None,
-            "detailed",
-            None,
+    fn test_rotation_signatures_accept_either_key() {
+        let old_key = register_test_key("rotation-test-old", 14);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"r": 1}}]);
+        let trace = serde_json::json!({
+            "components": components,
+            "signatures": [
+                {"signature": "bm90LWEtc2lnbmF0dXJl", "key_id": "rotation-test-new"},
+                {"signature": sign_components(&old_key, &components), "key_id": "rotation-test-old"}
+            ]
+        });
+        let log_ctx = LogContext::new("test-batch");
+
+        let quorum = verify_trace_signatures(&trace, "detailed", Utc::now(), 1, 0, &log_ctx);
+        assert!(quorum.result.verified, "{:?}", quorum.result.error);
+        assert_eq!(quorum.result.key_id.as_deref(), Some("rotation-test-old"));
+
+        let mut unsigned = trace.clone();
+        unsigned["signatures"][1]["signature"] = Value::String("bm90LWEtc2lnbmF0dXJl".to_string());
+        let quorum = verify_trace_signatures(&unsigned, "detailed", Utc::now(), 1, 0, &log_ctx);
+        assert!(!quorum.result.verified);
+        assert!(quorum.result.error.unwrap().contains("2 signatures attempted"));
+    }
+
+    #[test]
+    fn test_single_signature_below_quorum() {
+        let key = register_test_key("quorum-test-single", 13);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {}}]);
+        let trace = serde_json::json!({
+            "trace_id": "test-quorum-single",
+            "components": components,
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "quorum-test-single"
+        });
+        let log_ctx = LogContext::new("test-batch");
+
+        let single = verify_trace_signatures(&trace, "detailed", Utc::now(), 1, 0, &log_ctx);
+        assert!(single.result.verified);
+        assert_eq!((single.verified_keys, single.total), (1, 1));
+
+        let quorum = verify_trace_signatures(&trace, "detailed", Utc::now(), 2, 0, &log_ctx);
+        assert!(!quorum.result.verified);
+    }
+
+    #[test]
+    fn test_over_scrubbed_trace_routes_to_review() {
+        let key = register_test_key("overscrub-test", 21);
+        let components = serde_json::json!([{
+            "event_type": "THOUGHT_START",
+            "data": {"task_description": "alice@example.com bob@example.org carol@example.net"}
+        }]);
+        let canonical = build_199_canonical(&components, "full_traces");
+        let signature = {
+            use base64::{engine::general_purpose, Engine as _};
+            use ed25519_dalek::Signer;
+            general_purpose::STANDARD.encode(key.sign(canonical.as_bytes()).to_bytes())
+        };
+        let event = serde_json::json!({
+            "trace_id": "test-overscrub",
+            "components": components,
+            "signature": signature,
+            "signature_key_id": "overscrub-test"
+        })
+        .to_string();
+
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "full_traces", None);
+        let flagged = process_single_trace(&ctx, &event, false);
+        assert!(flagged.accepted, "{:?}", flagged.rejection_reason);
+        assert_eq!(flagged.extracted_metadata["pii_over_scrubbed"], "true");
+        assert_ne!(flagged.destination, "review");
+
+        ctx.config.pii.over_scrub_action = OverScrubAction::Review;
+        let reviewed = process_single_trace(&ctx, &event, false);
+        assert!(reviewed.accepted);
+        assert_eq!(reviewed.destination, "review");
+    }
+
+    #[test]
+    fn test_batch_verify_with_one_bad_signature() {
+        let key = register_test_key("batch-preverify-test", 48);
+        let events: Vec<String> = (0..4)
+            .map(|i| {
+                let components =
+                    serde_json::json!([{"event_type": "THOUGHT_START", "data": {"n": i}}]);
+                let signed_over = if i == 2 {
+                    serde_json::json!([{"event_type": "THOUGHT_START", "data": {"n": 99}}])
+                } else {
+                    components.clone()
+                };
+                serde_json::json!({
+                    "trace_id": format!("test-batch-preverify-{}", i),
+                    "components": components,
+                    "signature": sign_components(&key, &signed_over),
+                    "signature_key_id": "batch-preverify-test"
+                })
+                .to_string()
+            })
+            .collect();
+
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+        let schemas = SchemaCache::new();
+        assert_eq!(batch_preverify(&ctx, &events, &[true; 4], &schemas), [true, true, false, true]);
+        assert_eq!(
+            batch_preverify(&ctx, &events, &[true, false, true, true], &schemas),
+            [true, false, false, true]
+        );
+
+        ctx.config.signature.batch_verify = true;
+        let result = process_batch(&ctx, events);
+        assert_eq!(result.accepted_count, 3);
+        assert!(!result.traces[2].accepted);
+        assert_eq!(result.traces[2].signature_formats_tried.len(), 5);
+        assert_eq!(result.traces[0].signature_format.as_deref(), Some("1.9.9"));
+        assert_eq!(result.traces[0].extracted_metadata["signature_key_id"], "batch-preverify-test");
+    }
+
+    #[test]
+    fn test_batch_preverify_decodes_base64_components() {
+        use base64::{engine::general_purpose, Engine as _};
+        use crate::validation::schema::SchemaOptions;
+
+        let key = register_test_key("batch-preverify-base64-test", 71);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"n": 1}}]);
+        let events = vec![serde_json::json!({
+            "trace_id": "test-batch-preverify-base64",
+            "components": general_purpose::STANDARD.encode(components.to_string()),
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "batch-preverify-base64-test"
+        })
+        .to_string()];
+
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows_with_options(
+            vec![(
+                "1.9.3".to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["THOUGHT_START".to_string()],
+            )],
+            vec![],
+            HashMap::from([(
+                "1.9.3".to_string(),
+                SchemaOptions {
+                    components_base64: true,
+                    ..Default::default()
+                },
+            )]),
         );
+        let ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        assert_eq!(batch_preverify(&ctx, &events, &[true], &cache), [true]);
+        // Without a schema allowing base64, the string is left alone
+        assert_eq!(batch_preverify(&ctx, &events, &[true], &SchemaCache::new()), [false]);
+    }
+
+    #[test]
+    fn test_neutralized_trace_keeps_pre_sanitize_hash() {
+        let key = register_test_key("neutralize-test", 50);
+        let components = serde_json::json!([{
+            "event_type": "THOUGHT_START",
+            "data": {"thought": "<script>alert(1)</script>"}
+        }]);
+        let event = serde_json::json!({
+            "trace_id": "test-neutralize",
+            "components": components,
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "neutralize-test"
+        });
+        let expected_hash =
+            crate::validation::signature::compute_hash(&sort_and_serialize_compact(&event));
+
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        let logged = process_single_trace(&ctx, &event.to_string(), false);
+        assert!(logged.accepted, "{:?}", logged.rejection_reason);
+        assert!(!logged.extracted_metadata.contains_key("pre_sanitize_content_hash"));
+        assert_eq!(logged.extracted_metadata["security_xss_count"], "1");
+        assert_eq!(logged.extracted_metadata["security_sql_count"], "0");
+
+        ctx.config.sanitizer.xss_mode = XssMode::Neutralize;
+        let neutralized = process_single_trace(&ctx, &event.to_string(), false);
+        assert!(neutralized.accepted, "{:?}", neutralized.rejection_reason);
+        assert_eq!(neutralized.extracted_metadata["sanitizer_modified"], "true");
+        assert_eq!(neutralized.extracted_metadata["pre_sanitize_content_hash"], expected_hash);
+    }
 
-        let result = process_single_trace(&ctx, "invalid json{");
+    #[test]
+    fn test_deep_nesting_past_pre_parse_check_rejected() {
+        let key = register_test_key("depth-test", 54);
+        // Deep enough for the sanitizer limit, shallow enough for the parser
+        let mut data = serde_json::json!("leaf");
+        for _ in 0..100 {
+            data = serde_json::json!([data]);
+        }
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"n": data}}]);
+        let event = serde_json::json!({
+            "trace_id": "test-max-depth",
+            "components": components,
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "depth-test"
+        });
+
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.max_nesting_depth = 0;
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+        let result = process_single_trace(&ctx, &event.to_string(), false);
         assert!(!result.accepted);
         assert_eq!(result.destination, "malformed");
-        assert!(result.rejection_reason.is_some());
+        assert_eq!(result.rejection_reason.as_deref(), Some("max_depth_exceeded"));
     }
 
     #[test]
-    fn test_process_empty_events() {
-        let ctx = BatchContext::new(
+    fn test_sorted_metadata_output() {
+        let result = TraceResult {
+            trace_id: "test-sorted".to_string(),
+            destination: "production".to_string(),
+            schema_version: None,
+            accepted: true,
+            rejection_reason: None,
+            extracted_metadata: HashMap::from([
+                ("trace_id".to_string(), "test-sorted".to_string()),
+                ("agent_name".to_string(), "a".to_string()),
+                ("signature_verified".to_string(), "true".to_string()),
+                ("csdma_plausibility".to_string(), "0.9".to_string()),
+            ]),
+            extraction_issues: ExtractionIssues::default(),
+            signature_format: None,
+            signature_formats_tried: Vec::new(),
+        };
+
+        let keys: Vec<&str> = result.sorted_metadata().into_keys().collect();
+        assert_eq!(keys, ["agent_name", "csdma_plausibility", "signature_verified", "trace_id"]);
+        assert_eq!(
+            result.extracted_metadata_json(),
+            r#"{"agent_name":"a","csdma_plausibility":"0.9","signature_verified":"true","trace_id":"test-sorted"}"#
+        );
+    }
+
+    #[test]
+    fn test_base64_components_decoded_and_verified() {
+        use base64::{engine::general_purpose, Engine as _};
+        use crate::validation::schema::SchemaOptions;
+
+        let key = register_test_key("base64-components-test", 55);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"x": 1}}]);
+        let encoded = general_purpose::STANDARD.encode(components.to_string());
+        let mut trace = serde_json::json!({
+            "trace_id": "test-base64-components",
+            "components": encoded,
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "base64-components-test"
+        });
+
+        let mut cache = SchemaCache::new();
+        let schema = |version: &str| {
+            (
+                version.to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["THOUGHT_START".to_string()],
+            )
+        };
+        cache.load_from_db_rows_with_options(vec![schema("1.9.3")], vec![], HashMap::new());
+        let mut untouched = trace.clone();
+        assert_eq!(decode_base64_components(&mut untouched, &cache), Ok(false));
+        assert!(untouched["components"].is_string());
+
+        let options = SchemaOptions {
+            components_base64: true,
+            ..Default::default()
+        };
+        cache.load_from_db_rows_with_options(
+            vec![schema("1.9.3")],
+            vec![],
+            HashMap::from([("1.9.3".to_string(), options)]),
+        );
+        assert_eq!(decode_base64_components(&mut trace, &cache), Ok(true));
+        assert_eq!(trace["components"], components);
+
+        let ctx = LogContext::new("test-batch");
+        let batch_ts = DateTime::parse_from_rfc3339("2026-01-29T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let result = verify_trace_signature(&trace, "detailed", batch_ts, 0, &ctx);
+        assert!(result.verified, "{:?}", result.error);
+
+        let mut bad = serde_json::json!({"components": "not base64!"});
+        let err = decode_base64_components(&mut bad, &cache).unwrap_err();
+        assert!(err.starts_with("invalid_base64_components"), "{}", err);
+        let mut not_array = serde_json::json!({"components": general_purpose::STANDARD.encode("{}")});
+        assert!(decode_base64_components(&mut not_array, &cache).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_declared_schema_version() {
+        let mut cache = SchemaCache::new();
+        let trace = serde_json::json!({"schema_version": "9.9.9", "components": []});
+        assert_eq!(unsupported_declared_version(&trace, &cache), None);
+
+        cache.load_from_db_rows(
+            vec![(
+                "1.9.3".to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["THOUGHT_START".to_string()],
+            )],
+            vec![],
+        );
+        assert_eq!(unsupported_declared_version(&trace, &cache).as_deref(), Some("9.9.9"));
+        let supported = serde_json::json!({"schema_version": "1.9.3"});
+        assert_eq!(unsupported_declared_version(&supported, &cache), None);
+        assert_eq!(unsupported_declared_version(&serde_json::json!({}), &cache), None);
+    }
+
+    #[test]
+    fn test_python_number_formatting_matrix() {
+        // (JSON as Python's json.dumps writes it) -> identical bytes back
+        for text in [
+            "0", "-1", "18446744073709551615", "-9223372036854775808",
+            "0.0", "-0.0", "1.5", "123.456", "0.30000000000000004",
+            "0.0001", "1e-05", "1e-07", "1.5e-300", "5e-324",
+            "1000000000000000.0", "1e+16", "1e+20", "-2.5e+22",
+            "1.7976931348623157e+308",
+        ] {
+            let value: Value = serde_json::from_str(text).unwrap();
+            let Value::Number(n) = value else { panic!("{}", text) };
+            assert_eq!(python_number(&n), text);
+        }
+
+        assert_eq!(python_float_repr(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(python_float_repr(-0.0), "-0.0");
+        assert_eq!(python_float_repr(1e20), "1e+20");
+
+        // Integers past u64 become floats in serde_json
+        let huge: Value = serde_json::from_str("100000000000000000000").unwrap();
+        assert_eq!(python_json_dumps(&huge), "1e+20");
+    }
+
+    #[test]
+    fn test_python_number_canonical_variant_verifies() {
+        use base64::{engine::general_purpose, Engine as _};
+        use ed25519_dalek::Signer;
+
+        let key = register_test_key("pynum-test", 57);
+        let components: Value = serde_json::from_str(
+            r#"[{"event_type": "THOUGHT_START", "data": {"score": 1e+20, "tiny": 1e-07, "neg": -0.0, "sum": 0.30000000000000004}}]"#,
+        )
+        .unwrap();
+        // Bytes a Python agent signs for these components
+        let signed = r#"{"components":[{"data":{"neg":-0.0,"score":1e+20,"sum":0.30000000000000004,"tiny":1e-07},"event_type":"THOUGHT_START"}],"trace_level":"detailed"}"#;
+        assert_eq!(build_199_canonical_python_numbers(&components, "detailed"), signed);
+
+        let signature = general_purpose::STANDARD.encode(key.sign(signed.as_bytes()).to_bytes());
+        let trace = serde_json::json!({"components": components});
+        let ctx = LogContext::new("test-batch");
+        let result = verify_components_signature(&trace, "detailed", Utc::now(), &signature, "pynum-test", &ctx);
+        assert!(result.verified, "{:?}", result.error);
+        assert_eq!(result.format.as_deref(), Some("1.9.9-pynum"));
+
+        // Integer-only components produce no extra attempt
+        let plain = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"x": 1}}]);
+        assert_eq!(
+            build_199_canonical_python_numbers(&plain, "detailed"),
+            build_199_canonical(&plain, "detailed")
+        );
+    }
+
+    #[test]
+    fn test_deprecated_schema_status_reported() {
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows(
+            vec![(
+                "1.8.0".to_string(),
+                "old".to_string(),
+                "deprecated".to_string(),
+                vec!["THOUGHT_START".to_string()],
+            )],
+            vec![],
+        );
+        let events = HashSet::from(["THOUGHT_START".to_string()]);
+        let mut config = ExtractionConfig::default();
+        assert!(matched_schema_columns(&cache, "1.8.0", &events, &config).is_empty());
+
+        config.schema_status = true;
+        let columns = matched_schema_columns(&cache, "1.8.0", &events, &config);
+        assert_eq!(columns, [("schema_status", "deprecated".to_string())]);
+        assert!(matched_schema_columns(&cache, "9.9.9", &events, &config).is_empty());
+    }
+
+    #[test]
+    fn test_identical_trace_hits_verification_cache() {
+        use crate::validation::signature::SignatureVerificationResult;
+        use crate::validation::verification_cache::cache_verification;
+
+        let key = register_test_key("verification-cache-test", 56);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"retry": 1}}]);
+        let trace = serde_json::json!({
+            "trace_id": "test-verification-cache",
+            "components": components,
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "verification-cache-test"
+        });
+        let log_ctx = LogContext::new("test-batch");
+        let cache_key = verification_cache_key(
+            "detailed",
+            "verification-cache-test",
+            trace["signature"].as_str().unwrap(),
+            &sort_and_serialize_compact(&trace),
+        );
+
+        let uncached = verify_trace_signature(&trace, "detailed", Utc::now(), 0, &log_ctx);
+        assert!(uncached.verified);
+        assert!(get_cached_verification(&cache_key).is_none());
+
+        let first = verify_trace_signature(&trace, "detailed", Utc::now(), 100, &log_ctx);
+        assert!(first.verified);
+        let cached = get_cached_verification(&cache_key).expect("result cached");
+        assert_eq!(cached.format, first.format);
+
+        // A marker result proves the second occurrence is served from cache
+        let marker = SignatureVerificationResult::invalid("verification-cache-test", "cached marker");
+        cache_verification(cache_key, marker, 100);
+        let second = verify_trace_signature(&trace, "detailed", Utc::now(), 100, &log_ctx);
+        assert_eq!(second.error.as_deref(), Some("cached marker"));
+    }
+
+    #[test]
+    fn test_per_agent_consent_in_one_batch() {
+        use crate::pipeline::consent::{set_agent_consent, AgentConsent};
+
+        let key = register_test_key("consent-test", 53);
+        let event = |trace_id: &str, agent_id_hash: &str| {
+            let components =
+                serde_json::json!([{"event_type": "THOUGHT_START", "data": {"t": trace_id}}]);
+            serde_json::json!({
+                "trace_id": trace_id,
+                "agent_id_hash": agent_id_hash,
+                "components": components,
+                "signature": sign_components(&key, &components),
+                "signature_key_id": "consent-test"
+            })
+            .to_string()
+        };
+        set_agent_consent(HashMap::from([
+            (
+                "consent-test-yes".to_string(),
+                AgentConsent { consented: true, consent_timestamp: None },
+            ),
+            (
+                "consent-test-no".to_string(),
+                AgentConsent { consented: false, consent_timestamp: None },
+            ),
+        ]));
+
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+        let result = process_batch(
+            &ctx,
+            vec![
+                event("test-consent-yes", "consent-test-yes"),
+                event("test-consent-no", "consent-test-no"),
+                event("test-consent-unlisted", "consent-test-unlisted"),
+            ],
+        );
+
+        assert!(result.traces[0].accepted, "{:?}", result.traces[0].rejection_reason);
+        assert_eq!(result.traces[0].extracted_metadata["consent_source"], "agent");
+        assert_eq!(result.traces[0].destination, "production");
+        assert_eq!(result.traces[0].extracted_metadata["is_production"], "true");
+        assert!(!result.traces[1].accepted);
+        assert_eq!(result.traces[1].rejection_reason.as_deref(), Some("no_consent"));
+        assert!(result.traces[2].accepted);
+        assert_eq!(result.traces[2].extracted_metadata["consent_source"], "batch");
+    }
+
+    #[test]
+    fn test_conflicting_embedded_consent_flagged() {
+        let key = register_test_key("consent-conflict-test", 59);
+        let event = |trace_id: &str, consent: Option<&str>| {
+            let components =
+                serde_json::json!([{"event_type": "THOUGHT_START", "data": {"t": trace_id}}]);
+            let mut event = serde_json::json!({
+                "trace_id": trace_id,
+                "components": components,
+                "signature": sign_components(&key, &components),
+                "signature_key_id": "consent-conflict-test"
+            });
+            if let Some(consent) = consent {
+                event["consent_timestamp"] = Value::String(consent.to_string());
+            }
+            event.to_string()
+        };
+
+        let mut ctx = BatchContext::new(
             "2026-01-29T00:00:00Z",
-            None,
+            Some("2026-01-01T00:00:00Z"),
             "detailed",
             None,
         );
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+        let mixed_events = || {
+            vec![
+                event("test-consent-mixed-1", Some("2026-01-01T00:00:00Z")),
+                event("test-consent-mixed-2", Some("2026-01-15T00:00:00Z")),
+                event("test-consent-mixed-3", None),
+            ]
+        };
 
-        let result = process_single_trace(&ctx, r#"{"trace_id": "test-123"}"#);
-        // Without schema cache loaded, this should fail validation
-        assert!(!result.accepted);
-        assert_eq!(result.destination, "malformed");
+        // Off by default
+        let unchecked = process_batch(&ctx, mixed_events());
+        assert!(unchecked
+            .traces
+            .iter()
+            .all(|t| !t.extracted_metadata.contains_key("consent_conflict")));
+
+        ctx.config.batch.flag_consent_conflicts = true;
+        let uniform = process_batch(
+            &ctx,
+            vec![
+                event("test-consent-uniform-1", Some("2026-01-01T00:00:00Z")),
+                event("test-consent-uniform-2", Some("2026-01-01T00:00:00+00:00")),
+            ],
+        );
+        assert!(uniform.traces.iter().all(|t| t.accepted));
+        assert!(uniform
+            .traces
+            .iter()
+            .all(|t| !t.extracted_metadata.contains_key("consent_conflict")));
+
+        let mixed = process_batch(&ctx, mixed_events());
+        assert!(mixed.traces.iter().all(|t| t.accepted));
+        assert_eq!(mixed.traces[0].extracted_metadata["consent_conflict"], "true");
+        assert_eq!(mixed.traces[1].extracted_metadata["consent_conflict"], "true");
+        assert!(!mixed.traces[2].extracted_metadata.contains_key("consent_conflict"));
+    }
+
+    #[test]
+    fn test_copied_signature_reported_as_replay() {
+        let key = register_test_key("replay-detect-test", 60);
+        let original = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"thought_id": "th-a"}}]);
+        let copied_onto = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"thought_id": "th-b"}}]);
+        let signature = sign_components(&key, &original);
+        let event = |trace_id: &str, components: &Value| {
+            serde_json::json!({
+                "trace_id": trace_id,
+                "components": components,
+                "signature": signature,
+                "signature_key_id": "replay-detect-test"
+            })
+            .to_string()
+        };
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.fast_reject.known_malformed_capacity = 0;
+
+        let first = process_single_trace(&ctx, &event("test-replay-detect-a", &original), false);
+        assert!(first.accepted, "{:?}", first.rejection_reason);
+        assert!(!first.extracted_metadata.contains_key("signature_replay_suspected"));
+
+        let copied = process_single_trace(&ctx, &event("test-replay-detect-b", &copied_onto), false);
+        assert!(!copied.accepted);
+        assert_eq!(copied.extracted_metadata["signature_replay_suspected"], "true");
+
+        // Without a prior verification it is an ordinary failure
+        let unseen = sign_components(&key, &serde_json::json!([{"event_type": "THOUGHT_START"}]));
+        let mut forged: Value = serde_json::from_str(&event("test-replay-detect-c", &copied_onto)).unwrap();
+        forged["signature"] = Value::String(unseen);
+        let forged = process_single_trace(&ctx, &forged.to_string(), false);
+        assert!(!forged.accepted);
+        assert!(!forged.extracted_metadata.contains_key("signature_replay_suspected"));
+    }
+
+    #[test]
+    fn test_duplicate_signature_in_batch_flagged() {
+        let key = register_test_key("duplicate-sig-test", 47);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"dup": 1}}]);
+        let signature = sign_components(&key, &components);
+        let event = |trace_id: &str| {
+            serde_json::json!({
+                "trace_id": trace_id,
+                "components": components,
+                "signature": signature,
+                "signature_key_id": "duplicate-sig-test"
+            })
+            .to_string()
+        };
+        let events = vec![event("test-dup-a"), event("test-dup-b")];
+
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        let ignored = process_batch(&ctx, events.clone());
+        assert_eq!(ignored.accepted_count, 2);
+        assert!(!ignored.traces[0].extracted_metadata.contains_key("duplicate_signature_in_batch"));
+
+        ctx.config.signature.duplicate_signature_action = DuplicateSignatureAction::Flag;
+        let flagged = process_batch(&ctx, events.clone());
+        for trace in &flagged.traces {
+            assert_eq!(trace.extracted_metadata["duplicate_signature_in_batch"], "true");
+            assert_ne!(trace.destination, "review");
+        }
+
+        ctx.config.signature.duplicate_signature_action = DuplicateSignatureAction::Review;
+        let reviewed = process_batch(&ctx, events);
+        assert!(reviewed.traces.iter().all(|t| t.destination == "review"));
+        assert!(reviewed.traces.iter().all(|t| t.extracted_metadata["is_production"] == "false"));
+    }
+
+    #[test]
+    fn test_canonical_candidates_decode_to_canonical() {
+        use base64::{engine::general_purpose, Engine as _};
+
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"b": 2, "a": 1}}]);
+        let event = serde_json::json!({"trace_id": "test-dump", "components": components}).to_string();
+
+        let dump = canonical_candidates(&event, "detailed").unwrap();
+        assert_eq!(dump["trace_id"], "test-dump");
+
+        let candidates = dump["candidates"].as_array().unwrap();
+        let formats: Vec<&str> = candidates.iter().map(|c| c["format"].as_str().unwrap()).collect();
+        assert_eq!(formats, ["1.9.9", "1.9.8", "1.9.7", "pre-1.9.7", "envelope"]);
+
+        let expected = build_199_canonical(&components, "detailed");
+        let bytes = general_purpose::STANDARD
+            .decode(candidates[0]["bytes_b64"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), expected);
+        assert_eq!(candidates[0]["length"], expected.len());
+        assert_eq!(
+            candidates[0]["sha256"],
+            crate::validation::signature::compute_hash(&expected)
+        );
+
+        assert!(canonical_candidates(r#"{"trace_id": "x"}"#, "detailed").is_err());
+    }
+
+    #[test]
+    fn test_canonical_forms_match_builders() {
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"b": "", "a": 1}}]);
+        let forms = canonical_forms(&components.to_string(), "generic").unwrap();
+
+        let expected_199 = build_199_canonical(&components, "generic");
+        assert_eq!(forms["1.9.9"]["canonical"], expected_199.as_str());
+        assert_eq!(
+            forms["1.9.9"]["sha256"],
+            crate::validation::signature::compute_hash(&expected_199)
+        );
+        assert_eq!(forms["1.9.7"]["canonical"], sort_and_serialize(&components).as_str());
+        assert_eq!(
+            forms["pre-1.9.7"]["canonical"],
+            sort_and_serialize_legacy(&components).as_str()
+        );
+        assert!(canonical_forms("[", "generic").is_err());
+    }
+
+    #[test]
+    fn test_198_wrapper_without_trace_level_verifies() {
+        use base64::{engine::general_purpose, Engine as _};
+        use ed25519_dalek::Signer;
+
+        let key = register_test_key("format-198-test", 41);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"b": "", "a": 1}}]);
+        let canonical = r#"{"components":[{"data":{"a":1,"b":""},"event_type":"THOUGHT_START"}]}"#;
+        assert_eq!(build_198_canonical(&components), canonical);
+
+        let trace = serde_json::json!({
+            "trace_id": "test-198",
+            "components": components,
+            "signature": general_purpose::STANDARD.encode(key.sign(canonical.as_bytes()).to_bytes()),
+            "signature_key_id": "format-198-test"
+        });
+        let log_ctx = LogContext::new("test-batch");
+
+        let result = verify_trace_signature(&trace, "detailed", Utc::now(), 0, &log_ctx);
+        assert!(result.verified, "{:?}", result.error);
+    }
+
+    #[test]
+    fn test_envelope_signature_verifies() {
+        use base64::{engine::general_purpose, Engine as _};
+        use ed25519_dalek::Signer;
+
+        let key = register_test_key("format-envelope-test", 43);
+        let unsigned = serde_json::json!({
+            "trace_id": "test-envelope",
+            "agent_version": "2.0.1",
+            "timestamp": "2026-01-01T00:00:00Z",
+            "components": [{"event_type": "THOUGHT_START", "data": {"a": 1}}]
+        });
+        let canonical = sort_and_serialize_compact(&unsigned);
+
+        let mut trace = unsigned.clone();
+        trace["signature"] =
+            Value::String(general_purpose::STANDARD.encode(key.sign(canonical.as_bytes()).to_bytes()));
+        trace["signature_key_id"] = Value::String("format-envelope-test".to_string());
+        assert_eq!(build_envelope_canonical(&trace), canonical);
+
+        let log_ctx = LogContext::new("test-batch");
+        let result = verify_trace_signature(&trace, "detailed", Utc::now(), 0, &log_ctx);
+        assert!(result.verified, "{:?}", result.error);
+    }
+
+    /// Captured-shape 2.7.6-stable request body (9952 bytes).
+    const LEGACY_BODY: &str = include_str!("../../tests/fixtures/legacy_2_7_6_body.json");
+
+    #[test]
+    fn test_legacy_canonical_matches_agent_bytes() {
+        assert_eq!(LEGACY_BODY.len(), 9952);
+        let body: Value = serde_json::from_str(LEGACY_BODY).unwrap();
+        let trace = &body["events"][0]["trace"];
+
+        // Expected values from the agent's Python sign_trace canonicalizer
+        let canonical = build_legacy_canonical(trace, "generic").unwrap();
+        assert_eq!(canonical.len(), 3929);
+        let hash = crate::validation::signature::compute_hash(&canonical);
+        assert!(hash.starts_with("c78d25f8f981fd5e"), "hash={}", hash);
+
+        assert!(canonical.contains(r#""trace_level":"detailed""#));
+        assert!(canonical.contains(r#"caf\u00e9"#));
+        assert!(!canonical.contains("parent_thought_id"));
+    }
+
+    #[test]
+    fn test_legacy_signature_verifies_with_format() {
+        use base64::{engine::general_purpose, Engine as _};
+        use ed25519_dalek::Signer;
+
+        let key = register_test_key("format-legacy-test", 44);
+        let body: Value = serde_json::from_str(LEGACY_BODY).unwrap();
+        let mut trace = body["events"][0]["trace"].clone();
+        let canonical = build_legacy_canonical(&trace, "detailed").unwrap();
+        trace["signature"] =
+            Value::String(general_purpose::STANDARD.encode(key.sign(canonical.as_bytes()).to_bytes()));
+        trace["signature_key_id"] = Value::String("format-legacy-test".to_string());
+        let log_ctx = LogContext::new("test-batch");
+
+        let result = verify_trace_signature(&trace, "detailed", Utc::now(), 0, &log_ctx);
+        assert!(result.verified, "{:?}", result.error);
+        assert_eq!(result.format.as_deref(), Some("legacy-2field"));
+
+        // Also found as a last resort without the 2.7.legacy stamp
+        trace["trace_schema_version"] = Value::String("2.7.0".to_string());
+        let result = verify_trace_signature(&trace, "detailed", Utc::now(), 0, &log_ctx);
+        assert_eq!(result.format.as_deref(), Some("legacy-2field"));
+    }
+
+    #[test]
+    fn test_key_outside_window_rejected() {
+        use base64::{engine::general_purpose, Engine as _};
+        use crate::validation::signature::{get_key_cache_mut, KeyMetadata};
+
+        let key = ed25519_dalek::SigningKey::from_bytes(&[49; 32]);
+        let metadata =
+            KeyMetadata::parse(None, Some("2026-01-01T00:00:00Z"), Some("2026-06-30T00:00:00Z"))
+                .unwrap();
+        get_key_cache_mut()
+            .load_key_with_metadata(
+                "window-test",
+                &general_purpose::STANDARD.encode(key.verifying_key().to_bytes()),
+                metadata,
+            )
+            .unwrap();
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"w": 1}}]);
+        let trace = serde_json::json!({
+            "components": components,
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "window-test"
+        });
+        let log_ctx = LogContext::new("test-batch");
+        let at = |ts: &str| DateTime::parse_from_rfc3339(ts).unwrap().with_timezone(&Utc);
+
+        let inside = verify_trace_signature(&trace, "detailed", at("2026-03-01T00:00:00Z"), 0, &log_ctx);
+        assert!(inside.verified, "{:?}", inside.error);
+        let expired = verify_trace_signature(&trace, "detailed", at("2026-07-01T00:00:00Z"), 0, &log_ctx);
+        assert_eq!(expired.error.as_deref(), Some("key_expired"));
+        let early = verify_trace_signature(&trace, "detailed", at("2025-12-01T00:00:00Z"), 0, &log_ctx);
+        assert_eq!(early.error.as_deref(), Some("key_not_yet_valid"));
+    }
+
+    #[test]
+    fn test_formats_tried_reported() {
+        let key = register_test_key("formats-tried-test", 46);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"a": 1}}]);
+        let mut trace = serde_json::json!({
+            "components": components,
+            "signature": sign_components(&key, &components),
+            "signature_key_id": "formats-tried-test"
+        });
+        let log_ctx = LogContext::new("test-batch");
+
+        let result = verify_trace_signature(&trace, "detailed", Utc::now(), 0, &log_ctx);
+        assert!(result.verified, "{:?}", result.error);
+        assert_eq!(result.format.as_deref(), Some("1.9.9"));
+        assert_eq!(result.formats_tried, ["1.9.9"]);
+
+        // Signed over different components: every format is attempted
+        trace["components"] = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"a": 2}}]);
+        let result = verify_trace_signature(&trace, "detailed", Utc::now(), 0, &log_ctx);
+        assert!(!result.verified);
+        assert!(result.format.is_none());
+        assert_eq!(
+            result.formats_tried,
+            ["1.9.9", "1.9.8", "1.9.7", "pre-1.9.7", "envelope"]
+        );
+        assert!(result
+            .error
+            .unwrap()
+            .ends_with("(formats tried: 1.9.9, 1.9.8, 1.9.7, pre-1.9.7, envelope)"));
+    }
+
+    #[test]
+    fn test_199_prefers_trace_embedded_level() {
+        use base64::{engine::general_purpose, Engine as _};
+        use ed25519_dalek::Signer;
+
+        let key = register_test_key("format-199-level-test", 45);
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"a": 1}}]);
+        let canonical = build_199_canonical(&components, "full_traces");
+        let mut trace = serde_json::json!({
+            "trace_id": "test-199-level",
+            "trace_level": "full_traces",
+            "components": components,
+            "signature": general_purpose::STANDARD.encode(key.sign(canonical.as_bytes()).to_bytes()),
+            "signature_key_id": "format-199-level-test"
+        });
+        let log_ctx = LogContext::new("test-batch");
+
+        let result = verify_trace_signature(&trace, "detailed", Utc::now(), 0, &log_ctx);
+        assert!(result.verified, "{:?}", result.error);
+        assert_eq!(result.format.as_deref(), Some("1.9.9"));
+
+        // Only the embedded level was signed: without it, the batch level fails
+        trace.as_object_mut().unwrap().remove("trace_level");
+        assert!(!verify_trace_signature(&trace, "detailed", Utc::now(), 0, &log_ctx).verified);
     }
 }
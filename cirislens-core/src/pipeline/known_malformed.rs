@@ -0,0 +1,163 @@
+//! Fast rejection of repeated malformed payloads.
+//!
+//! When an agent floods identical malformed traces, every copy would
+//! otherwise be parsed, validated and signature-checked again. A bounded
+//! set of recently seen malformed content hashes lets repeats be rejected
+//! up front with reason `known_malformed`.
+//!
+//! Rejection depends on the loaded schemas, keys and config, so the set is
+//! cleared whenever any of those are reloaded. Rejections that depend on
+//! the batch itself (its timestamp or consent) are never remembered: the
+//! same payload may pass in the next batch.
+
+use std::collections::{HashSet, VecDeque};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+use crate::validation::signature::compute_hash;
+
+/// Default number of malformed hashes remembered.
+pub const DEFAULT_KNOWN_MALFORMED_CAPACITY: usize = 10_000;
+
+/// Rejection reasons that depend on the batch rather than the payload.
+/// Matched as substrings, since signature quorum errors embed the last
+/// per-key error.
+const BATCH_DEPENDENT_REASONS: &[&str] = &[
+    "no_consent",
+    "stale_trace",
+    "future_timestamp",
+    "future_batch",
+    "key_expired",
+    "key_not_yet_valid",
+];
+
+/// Bounded FIFO set of content hashes.
+#[derive(Debug, Default)]
+pub struct KnownMalformedSet {
+    hashes: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl KnownMalformedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.hashes.contains(hash)
+    }
+
+    /// Remember a hash, evicting the oldest entries beyond `capacity`.
+    /// A capacity of 0 disables the set.
+    pub fn insert(&mut self, hash: String, capacity: usize) {
+        if capacity == 0 || self.hashes.contains(&hash) {
+            return;
+        }
+        self.hashes.insert(hash.clone());
+        self.order.push_back(hash);
+        while self.order.len() > capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.hashes.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.hashes.clear();
+        self.order.clear();
+    }
+}
+
+lazy_static! {
+    static ref KNOWN_MALFORMED: Mutex<KnownMalformedSet> = Mutex::new(KnownMalformedSet::new());
+}
+
+/// Content hash of a payload as processed at a given trace level.
+///
+/// The trace level is part of the 1.9.9 canonical form, so the same body
+/// can verify at one level and fail at another.
+pub fn payload_hash(trace_level: &str, event_json: &str) -> String {
+    compute_hash(&format!("{}\n{}", trace_level, event_json))
+}
+
+/// Whether a rejection reason follows from the payload alone, so repeats
+/// of it can be rejected without reprocessing.
+pub fn is_payload_intrinsic(reason: &str) -> bool {
+    !BATCH_DEPENDENT_REASONS
+        .iter()
+        .any(|dependent| reason.contains(dependent))
+}
+
+/// Check whether a payload hash was recently rejected as malformed.
+pub fn is_known_malformed(hash: &str) -> bool {
+    KNOWN_MALFORMED.lock().contains(hash)
+}
+
+/// Remember a malformed payload hash.
+pub fn remember_malformed(hash: String, capacity: usize) {
+    KNOWN_MALFORMED.lock().insert(hash, capacity);
+}
+
+/// Forget all remembered hashes (call on any cache or config reload).
+pub fn clear_known_malformed() {
+    let mut set = KNOWN_MALFORMED.lock();
+    if !set.is_empty() {
+        log::info!("KNOWN_MALFORMED_CLEARED entries={}", set.len());
+    }
+    set.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded_eviction() {
+        let mut set = KnownMalformedSet::new();
+        set.insert("a".to_string(), 2);
+        set.insert("b".to_string(), 2);
+        set.insert("a".to_string(), 2);
+        set.insert("c".to_string(), 2);
+
+        assert_eq!(set.len(), 2);
+        assert!(!set.contains("a"));
+        assert!(set.contains("b"));
+        assert!(set.contains("c"));
+    }
+
+    #[test]
+    fn test_zero_capacity_disables() {
+        let mut set = KnownMalformedSet::new();
+        set.insert("a".to_string(), 0);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn test_batch_dependent_reasons_not_intrinsic() {
+        assert!(is_payload_intrinsic("truncated_json: EOF while parsing"));
+        assert!(is_payload_intrinsic("excessive_nesting"));
+        assert!(!is_payload_intrinsic("no_consent"));
+        assert!(!is_payload_intrinsic("stale_trace"));
+        assert!(!is_payload_intrinsic(
+            "Signature quorum not met: 0 of 1 required keys verified, \
+             1 signatures attempted (last error: key_expired)"
+        ));
+    }
+
+    #[test]
+    fn test_hash_depends_on_trace_level() {
+        assert_ne!(
+            payload_hash("detailed", "{}"),
+            payload_hash("full_traces", "{}")
+        );
+    }
+}
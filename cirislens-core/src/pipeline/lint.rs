@@ -0,0 +1,197 @@
+//! Dry-run of candidate policies on a real batch.
+//!
+//! Runs the batch under the current config and under the current config
+//! with a partial policy update applied, and reports per-trace decisions
+//! plus the traces whose outcome would change. Lets operators see the
+//! effect of a stricter policy before rolling it out.
+//!
+//! Both runs use an isolated config (`PipelineConfig::isolate`), so they
+//! write none of the process-wide trackers live ingestion reads: linting a
+//! batch before ingesting it doesn't change how it is ingested. The
+//! per-format signature metrics do count the lint runs' verify attempts.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use super::context::BatchContext;
+use super::ingestion::{process_batch, TraceResult};
+
+/// Outcome of one trace under one policy set.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LintDecision {
+    pub accepted: bool,
+    pub destination: String,
+    pub rejection_reason: Option<String>,
+}
+
+impl From<&TraceResult> for LintDecision {
+    fn from(result: &TraceResult) -> Self {
+        Self {
+            accepted: result.accepted,
+            destination: result.destination.clone(),
+            rejection_reason: result.rejection_reason.clone(),
+        }
+    }
+}
+
+/// One trace's decisions under the current and candidate policies.
+#[derive(Debug, Clone, Serialize)]
+pub struct LintTrace {
+    pub index: usize,
+    pub trace_id: String,
+    pub current: LintDecision,
+    pub candidate: LintDecision,
+}
+
+/// Lint report for a batch.
+#[derive(Debug, Clone, Serialize)]
+pub struct LintReport {
+    pub traces: Vec<LintTrace>,
+    /// Indices accepted now and rejected under the candidate policies.
+    pub newly_rejected: Vec<usize>,
+    /// Indices rejected now and accepted under the candidate policies.
+    pub newly_accepted: Vec<usize>,
+    /// Indices accepted under both but routed to a different destination.
+    pub rerouted: Vec<usize>,
+}
+
+/// Process `events` under `ctx`'s config and under that config with
+/// `policy` (a partial config JSON, as for `configure_pipeline`) applied.
+///
+/// # Errors
+/// `policy` names an unknown key or has a value of the wrong type.
+pub fn lint_batch(ctx: &BatchContext, policy: &Value, events: &[String]) -> Result<LintReport, String> {
+    let mut current_ctx = ctx.clone();
+    current_ctx.fail_fast = false;
+
+    let mut candidate_ctx = current_ctx.clone();
+    candidate_ctx.config.apply_json(policy)?;
+    current_ctx.config.isolate();
+    candidate_ctx.config.isolate();
+
+    let current = process_batch(&current_ctx, events.to_vec());
+    let candidate = process_batch(&candidate_ctx, events.to_vec());
+
+    let mut report = LintReport {
+        traces: Vec::with_capacity(events.len()),
+        newly_rejected: Vec::new(),
+        newly_accepted: Vec::new(),
+        rerouted: Vec::new(),
+    };
+    for (index, (now, next)) in current.traces.iter().zip(&candidate.traces).enumerate() {
+        let trace = LintTrace {
+            index,
+            trace_id: now.trace_id.clone(),
+            current: LintDecision::from(now),
+            candidate: LintDecision::from(next),
+        };
+        match (trace.current.accepted, trace.candidate.accepted) {
+            (true, false) => report.newly_rejected.push(index),
+            (false, true) => report.newly_accepted.push(index),
+            (true, true) if trace.current.destination != trace.candidate.destination => {
+                report.rerouted.push(index)
+            }
+            _ => {}
+        }
+        report.traces.push(trace);
+    }
+
+    log::info!(
+        "[batch={}] LINT_COMPLETE traces={} newly_rejected={} newly_accepted={} rerouted={}",
+        ctx.batch_id,
+        report.traces.len(),
+        report.newly_rejected.len(),
+        report.newly_accepted.len(),
+        report.rerouted.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::ingestion::build_199_canonical;
+
+    fn signed_event(trace_id: &str, key_id: &str, seed: u8) -> String {
+        use base64::{engine::general_purpose, Engine as _};
+        use ed25519_dalek::Signer;
+
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[seed; 32]);
+        crate::validation::signature::get_key_cache_mut()
+            .load_key(
+                key_id,
+                &general_purpose::STANDARD.encode(signing_key.verifying_key().to_bytes()),
+            )
+            .unwrap();
+        let components = serde_json::json!([{"event_type": "THOUGHT_START", "data": {"lint": 1}}]);
+        let canonical = build_199_canonical(&components, "detailed");
+        serde_json::json!({
+            "trace_id": trace_id,
+            "components": components,
+            "signature": general_purpose::STANDARD.encode(signing_key.sign(canonical.as_bytes()).to_bytes()),
+            "signature_key_id": key_id
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_strict_quorum_newly_rejects() {
+        let ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        let events = vec![signed_event("test-lint-1", "lint-test", 61), "not json".to_string()];
+        let policy = serde_json::json!({"signature": {"quorum_threshold": 2}});
+
+        let report = lint_batch(&ctx, &policy, &events).unwrap();
+
+        assert_eq!(report.newly_rejected, [0]);
+        assert!(report.newly_accepted.is_empty());
+        assert!(report.traces[0].current.accepted);
+        assert!(report.traces[0]
+            .candidate
+            .rejection_reason
+            .as_deref()
+            .unwrap()
+            .starts_with("Signature quorum not met"));
+        // Already rejected: no change
+        assert!(!report.traces[1].current.accepted && !report.traces[1].candidate.accepted);
+    }
+
+    #[test]
+    fn test_lint_leaves_ingestion_unchanged() {
+        let with_agent = |agent_id_hash: &str| {
+            let mut event: Value = serde_json::from_str(&signed_event("test-lint-isolated", "lint-isolated", 69)).unwrap();
+            event["agent_id_hash"] = Value::from(agent_id_hash);
+            event["seq"] = Value::from(1);
+            event.to_string()
+        };
+        let mut ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        ctx.config.sequence.tracked_agents = 100;
+        let policy = serde_json::json!({"signature": {"quorum_threshold": 2}});
+        let activity = || {
+            crate::validation::signature::get_key_activity()
+                .into_iter()
+                .find(|entry| entry.key_id == "lint-isolated")
+                .map(|entry| (entry.verified, entry.failed))
+        };
+
+        let mut alone = process_batch(&ctx, vec![with_agent("lint-agent-alone")]);
+        let activity_before = activity();
+        lint_batch(&ctx, &policy, &[with_agent("lint-agent-linted")]).unwrap();
+        assert_eq!(activity(), activity_before);
+        let mut linted = process_batch(&ctx, vec![with_agent("lint-agent-linted")]);
+
+        let (mut alone, mut linted) = (alone.traces.remove(0), linted.traces.remove(0));
+        alone.extracted_metadata.remove("agent_id_hash");
+        linted.extracted_metadata.remove("agent_id_hash");
+        assert!(linted.accepted);
+        assert_eq!(LintDecision::from(&linted), LintDecision::from(&alone));
+        assert_eq!(linted.extracted_metadata, alone.extracted_metadata);
+        assert!(!linted.extracted_metadata.contains_key("seq_regression"));
+    }
+
+    #[test]
+    fn test_unknown_policy_key_is_an_error() {
+        let ctx = BatchContext::new("2026-01-29T00:00:00Z", None, "detailed", None);
+        let policy = serde_json::json!({"signature": {"no_such_option": true}});
+        assert!(lint_batch(&ctx, &policy, &[]).is_err());
+    }
+}
@@ -8,8 +8,15 @@
 //! - Field extraction
 //! - Routing decisions
 
+pub mod benchmark;
+pub mod consent;
 pub mod context;
+pub mod decompress;
 pub mod ingestion;
+pub mod known_malformed;
+pub mod lint;
+pub mod recent_rejections;
+pub mod sequence;
 
 pub use context::*;
 pub use ingestion::*;
@@ -0,0 +1,161 @@
+//! Ring buffer of recently rejected traces for live debugging.
+//!
+//! Lets operators inspect a handful of recent rejections (via
+//! `get_recent_rejections`) without turning on debug logging across the
+//! fleet. Only rejected traces are recorded, and only a truncated preview
+//! of the body is kept.
+
+use std::collections::VecDeque;
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::logging::structured::safe_truncate;
+use crate::validation::signature::compute_hash;
+
+/// Default number of rejections kept.
+pub const DEFAULT_RECENT_REJECTIONS: usize = 100;
+
+/// Default preview length, in characters.
+pub const DEFAULT_REJECTION_PREVIEW_CHARS: usize = 200;
+
+/// One rejected trace.
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectionRecord {
+    pub trace_id: String,
+    pub reason: String,
+    pub content_hash: String,
+    pub event_types: Vec<String>,
+    pub key_id: Option<String>,
+    pub preview: String,
+    pub rejected_at: String,
+}
+
+impl RejectionRecord {
+    /// Build a record from the raw event and its rejection reason.
+    pub fn from_event(event_json: &str, trace_id: &str, reason: &str, preview_chars: usize) -> Self {
+        let parsed: Option<Value> = serde_json::from_str(event_json).ok();
+        let event_types = parsed
+            .as_ref()
+            .and_then(|t| t.get("components"))
+            .and_then(|c| c.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|c| c.get("event_type").and_then(|e| e.as_str()))
+                    .map(|s| s.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let key_id = parsed
+            .as_ref()
+            .and_then(|t| t.get("signature_key_id"))
+            .and_then(|k| k.as_str())
+            .map(|k| k.to_string());
+
+        Self {
+            trace_id: trace_id.to_string(),
+            reason: reason.to_string(),
+            content_hash: compute_hash(event_json),
+            event_types,
+            key_id,
+            preview: safe_truncate(event_json, preview_chars).to_string(),
+            rejected_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// Bounded FIFO of rejection records.
+#[derive(Debug, Default)]
+pub struct RejectionBuffer {
+    records: VecDeque<RejectionRecord>,
+}
+
+impl RejectionBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Add a record, dropping the oldest beyond `capacity`.
+    /// A capacity of 0 disables recording.
+    pub fn push(&mut self, record: RejectionRecord, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        self.records.push_back(record);
+        while self.records.len() > capacity {
+            self.records.pop_front();
+        }
+    }
+
+    /// Records, oldest first.
+    pub fn records(&self) -> Vec<RejectionRecord> {
+        self.records.iter().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+lazy_static! {
+    static ref RECENT_REJECTIONS: Mutex<RejectionBuffer> = Mutex::new(RejectionBuffer::new());
+}
+
+/// Record a rejection in the global buffer.
+pub fn record_rejection(record: RejectionRecord, capacity: usize) {
+    RECENT_REJECTIONS.lock().push(record, capacity);
+}
+
+/// Recent rejections, oldest first.
+pub fn get_recent_rejections() -> Vec<RejectionRecord> {
+    RECENT_REJECTIONS.lock().records()
+}
+
+/// Clear the global buffer.
+pub fn clear_recent_rejections() {
+    RECENT_REJECTIONS.lock().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_from_event() {
+        let event = r#"{"trace_id": "t-1", "signature_key_id": "k-1", "components": [{"event_type": "THOUGHT_START"}]}"#;
+        let record = RejectionRecord::from_event(event, "t-1", "Unknown signer key", 10);
+
+        assert_eq!(record.event_types, ["THOUGHT_START"]);
+        assert_eq!(record.key_id.as_deref(), Some("k-1"));
+        assert_eq!(record.preview.chars().count(), 10);
+        assert_eq!(record.content_hash, compute_hash(event));
+    }
+
+    #[test]
+    fn test_buffer_respects_capacity() {
+        let mut buffer = RejectionBuffer::new();
+        for i in 0..5 {
+            let record = RejectionRecord::from_event("{}", &format!("t-{}", i), "invalid_json", 20);
+            buffer.push(record, 3);
+        }
+
+        let ids: Vec<String> = buffer.records().into_iter().map(|r| r.trace_id).collect();
+        assert_eq!(ids, ["t-2", "t-3", "t-4"]);
+
+        buffer.push(RejectionRecord::from_event("{}", "t-5", "x", 20), 0);
+        assert_eq!(buffer.len(), 3);
+
+        buffer.clear();
+        assert!(buffer.is_empty());
+    }
+}
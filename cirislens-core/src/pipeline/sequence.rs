@@ -0,0 +1,134 @@
+//! Per-agent sequence number checks.
+//!
+//! Agents may stamp each trace with a per-agent `seq` that increases by
+//! one. A bounded map from `agent_id_hash` to the last seen `seq` lets
+//! gaps (dropped traces) and regressions (replayed or reordered traces)
+//! be flagged in metadata. Only accepted traces advance the sequence, so
+//! a forged trace can't reset an agent's position.
+
+use std::collections::{HashMap, VecDeque};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+/// How an incoming `seq` relates to the agent's last seen one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceStatus {
+    /// No prior `seq` for the agent.
+    First,
+    /// Exactly the previous `seq` + 1.
+    InOrder,
+    /// Skipped ahead of the previous `seq` + 1.
+    Gap { previous: u64 },
+    /// At or below the previous `seq`.
+    Regression { previous: u64 },
+}
+
+/// Bounded FIFO map of agent -> last seen `seq`.
+#[derive(Debug, Default)]
+pub struct AgentSequences {
+    last_seen: HashMap<String, u64>,
+    order: VecDeque<String>,
+}
+
+impl AgentSequences {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.last_seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.last_seen.is_empty()
+    }
+
+    /// Compare `seq` with the agent's last seen one and remember the
+    /// highest, evicting the oldest agents beyond `capacity`. A capacity
+    /// of 0 disables tracking.
+    pub fn observe(&mut self, agent_id_hash: &str, seq: u64, capacity: usize) -> SequenceStatus {
+        if capacity == 0 {
+            return SequenceStatus::First;
+        }
+        let status = match self.last_seen.get(agent_id_hash) {
+            None => SequenceStatus::First,
+            Some(&previous) if seq <= previous => return SequenceStatus::Regression { previous },
+            Some(&previous) if seq == previous + 1 => SequenceStatus::InOrder,
+            Some(&previous) => SequenceStatus::Gap { previous },
+        };
+        if self.last_seen.insert(agent_id_hash.to_string(), seq).is_none() {
+            self.order.push_back(agent_id_hash.to_string());
+        }
+        while self.order.len() > capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.last_seen.remove(&oldest);
+            }
+        }
+        status
+    }
+
+    pub fn clear(&mut self) {
+        self.last_seen.clear();
+        self.order.clear();
+    }
+}
+
+lazy_static! {
+    static ref AGENT_SEQUENCES: Mutex<AgentSequences> = Mutex::new(AgentSequences::new());
+}
+
+/// Check an accepted trace's `seq` against the global map.
+pub fn observe_agent_sequence(agent_id_hash: &str, seq: u64, capacity: usize) -> SequenceStatus {
+    AGENT_SEQUENCES.lock().observe(agent_id_hash, seq, capacity)
+}
+
+/// Forget all tracked sequences.
+pub fn clear_agent_sequences() {
+    AGENT_SEQUENCES.lock().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_order_sequence() {
+        let mut sequences = AgentSequences::new();
+        assert_eq!(sequences.observe("agent-a", 1, 10), SequenceStatus::First);
+        assert_eq!(sequences.observe("agent-a", 2, 10), SequenceStatus::InOrder);
+        assert_eq!(sequences.observe("agent-a", 3, 10), SequenceStatus::InOrder);
+        assert_eq!(sequences.observe("agent-b", 7, 10), SequenceStatus::First);
+    }
+
+    #[test]
+    fn test_gapped_sequence() {
+        let mut sequences = AgentSequences::new();
+        sequences.observe("agent-a", 1, 10);
+        assert_eq!(sequences.observe("agent-a", 4, 10), SequenceStatus::Gap { previous: 1 });
+        assert_eq!(sequences.observe("agent-a", 5, 10), SequenceStatus::InOrder);
+    }
+
+    #[test]
+    fn test_regressed_sequence() {
+        let mut sequences = AgentSequences::new();
+        sequences.observe("agent-a", 5, 10);
+        assert_eq!(sequences.observe("agent-a", 5, 10), SequenceStatus::Regression { previous: 5 });
+        assert_eq!(sequences.observe("agent-a", 2, 10), SequenceStatus::Regression { previous: 5 });
+        // A regression doesn't move the agent's position back
+        assert_eq!(sequences.observe("agent-a", 6, 10), SequenceStatus::InOrder);
+    }
+
+    #[test]
+    fn test_bounded_tracking() {
+        let mut sequences = AgentSequences::new();
+        sequences.observe("agent-a", 1, 2);
+        sequences.observe("agent-b", 1, 2);
+        sequences.observe("agent-c", 1, 2);
+        assert_eq!(sequences.len(), 2);
+        assert_eq!(sequences.observe("agent-a", 9, 2), SequenceStatus::First);
+
+        assert_eq!(sequences.observe("agent-d", 1, 0), SequenceStatus::First);
+        assert_eq!(sequences.observe("agent-d", 5, 0), SequenceStatus::First);
+    }
+}
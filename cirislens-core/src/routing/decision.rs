@@ -4,8 +4,10 @@
 
 use std::collections::HashMap;
 
+use crate::config::RoutingConfig;
 use crate::logging::structured::LogContext;
-use crate::routing::mock_detection::is_mock_trace;
+use crate::routing::mock_detection::{contains_mock_model, parse_models_used};
+use crate::routing::sampling::keep_sampled;
 
 /// Routing decision for a trace.
 #[derive(Debug, Clone, PartialEq)]
@@ -14,6 +16,9 @@ pub enum RoutingDecision {
     Mock,
     Connectivity,
     Malformed(String), // reason
+    Review(String),    // reason; valid but held for manual review
+    Quarantine(String), // reason; valid but isolated until the cause is resolved
+    SampledOut,         // production trace dropped by sampling; accepted, not stored
 }
 
 impl RoutingDecision {
@@ -23,21 +28,38 @@ impl RoutingDecision {
             RoutingDecision::Mock => "mock",
             RoutingDecision::Connectivity => "connectivity",
             RoutingDecision::Malformed(_) => "malformed",
+            RoutingDecision::Review(_) => "review",
+            RoutingDecision::Quarantine(_) => "quarantine",
+            RoutingDecision::SampledOut => "sampled_out",
         }
     }
+
+    /// Value of the `is_production` metadata column.
+    pub fn is_production(&self) -> bool {
+        *self == RoutingDecision::Production
+    }
 }
 
 /// Determine routing for a trace based on extracted metadata.
 ///
 /// # Decision Tree
-/// 1. If schema_version == "connectivity" -> Connectivity
-/// 2. If models_used contains "mock" -> Mock (unless generic level)
-/// 3. Otherwise -> Production
+/// 1. If a policy gave a `quarantine_reason` -> Quarantine
+/// 2. If schema_version == "connectivity" -> Connectivity
+/// 3. If any model in models_used is a mock model -> Mock (unless generic level)
+/// 4. Otherwise -> Production, or SampledOut if the trace id falls outside
+///    `config.production_sample_rate`
 pub fn determine_routing(
     metadata: &HashMap<String, String>,
     trace_level: &str,
+    quarantine_reason: Option<&str>,
+    config: &RoutingConfig,
     ctx: &LogContext,
 ) -> RoutingDecision {
+    if let Some(reason) = quarantine_reason {
+        log::info!("{} ROUTING_DECISION destination=quarantine reason={}", ctx, reason);
+        return RoutingDecision::Quarantine(reason.to_string());
+    }
+
     // Check for connectivity events
     if let Some(schema) = metadata.get("schema_version") {
         if schema == "connectivity" {
@@ -56,7 +78,7 @@ pub fn determine_routing(
             .map(|s| s.as_str())
             .unwrap_or("[]");
 
-        if is_mock_trace(models_used) {
+        if contains_mock_model(&parse_models_used(models_used)) {
             log::info!(
                 "{} ROUTING_DECISION destination=mock models_used={}",
                 ctx,
@@ -66,7 +88,15 @@ pub fn determine_routing(
         }
     }
 
-    // Default to production
+    // Default to production, subject to sampling
+    let trace_id = metadata.get("trace_id").or(ctx.trace_id.as_ref());
+    let rate = config.production_sample_rate;
+    if let Some(trace_id) = trace_id {
+        if !keep_sampled(trace_id, rate) {
+            log::debug!("{} ROUTING_DECISION destination=sampled_out rate={}", ctx, rate);
+            return RoutingDecision::SampledOut;
+        }
+    }
     log::debug!("{} ROUTING_DECISION destination=production", ctx);
     RoutingDecision::Production
 }
@@ -80,7 +110,7 @@ mod tests {
         let ctx = LogContext::new("test-batch");
         let metadata: HashMap<String, String> = HashMap::new();
 
-        let decision = determine_routing(&metadata, "detailed", &ctx);
+        let decision = determine_routing(&metadata, "detailed", None, &RoutingConfig::default(), &ctx);
         assert_eq!(decision, RoutingDecision::Production);
     }
 
@@ -90,8 +120,20 @@ mod tests {
         let mut metadata = HashMap::new();
         metadata.insert("models_used".to_string(), r#"["llama4scout (mock)"]"#.to_string());
 
-        let decision = determine_routing(&metadata, "detailed", &ctx);
+        let decision = determine_routing(&metadata, "detailed", None, &RoutingConfig::default(), &ctx);
         assert_eq!(decision, RoutingDecision::Mock);
+        assert!(!decision.is_production());
+        assert!(RoutingDecision::Production.is_production());
+    }
+
+    #[test]
+    fn test_mockingbird_routes_to_production() {
+        let ctx = LogContext::new("test-batch");
+        let mut metadata: HashMap<String, String> = HashMap::new();
+        metadata.insert("models_used".to_string(), r#"["mockingbird-prod"]"#.to_string());
+
+        let decision = determine_routing(&metadata, "detailed", None, &RoutingConfig::default(), &ctx);
+        assert_eq!(decision, RoutingDecision::Production);
     }
 
     #[test]
@@ -101,17 +143,50 @@ mod tests {
         metadata.insert("models_used".to_string(), r#"["mock-model"]"#.to_string());
 
         // Generic level should go to production even with mock models
-        let decision = determine_routing(&metadata, "generic", &ctx);
+        let decision = determine_routing(&metadata, "generic", None, &RoutingConfig::default(), &ctx);
         assert_eq!(decision, RoutingDecision::Production);
     }
 
+    #[test]
+    fn test_quarantine_routing() {
+        let ctx = LogContext::new("test-batch");
+        let mut metadata = HashMap::new();
+        metadata.insert("models_used".to_string(), r#"["mock-model"]"#.to_string());
+
+        let decision = determine_routing(
+            &metadata,
+            "detailed",
+            Some("schema_version_unsupported"),
+            &RoutingConfig::default(),
+            &ctx,
+        );
+        assert_eq!(decision, RoutingDecision::Quarantine("schema_version_unsupported".to_string()));
+        assert_eq!(decision.as_str(), "quarantine");
+    }
+
+    #[test]
+    fn test_sampled_out_routing() {
+        let ctx = LogContext::new("test-batch");
+        let metadata = HashMap::from([("trace_id".to_string(), "test-sampled".to_string())]);
+        let config = RoutingConfig {
+            production_sample_rate: 0.0,
+            ..Default::default()
+        };
+
+        let decision = determine_routing(&metadata, "detailed", None, &config, &ctx);
+        assert_eq!(decision, RoutingDecision::SampledOut);
+        let mut mock = metadata.clone();
+        mock.insert("models_used".to_string(), r#"["mock-model"]"#.to_string());
+        assert_eq!(determine_routing(&mock, "detailed", None, &config, &ctx), RoutingDecision::Mock);
+    }
+
     #[test]
     fn test_connectivity_routing() {
         let ctx = LogContext::new("test-batch");
         let mut metadata = HashMap::new();
         metadata.insert("schema_version".to_string(), "connectivity".to_string());
 
-        let decision = determine_routing(&metadata, "detailed", &ctx);
+        let decision = determine_routing(&metadata, "detailed", None, &RoutingConfig::default(), &ctx);
         assert_eq!(decision, RoutingDecision::Connectivity);
     }
 }
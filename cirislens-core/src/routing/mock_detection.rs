@@ -2,18 +2,32 @@
 //!
 //! Detects traces from test/mock LLM models to route them to the mock table.
 
-/// Check if a models_used string indicates a mock trace.
+/// Check if a models_used JSON array string indicates a mock trace.
 ///
-/// Returns true if any model name contains "mock" (case-insensitive).
+/// The array is parsed and each model name checked with `is_mock_model`,
+/// so "mock" elsewhere in the string (or inside an unrelated model name)
+/// does not count.
 ///
 /// # Examples
 /// ```
+/// use cirislens_core::routing::is_mock_trace;
 /// assert!(is_mock_trace(r#"["llama4scout (mock)"]"#));
 /// assert!(is_mock_trace(r#"["mock-model"]"#));
 /// assert!(!is_mock_trace(r#"["claude-3-sonnet"]"#));
+/// assert!(!is_mock_trace(r#"["mockingbird-prod"]"#));
 /// ```
 pub fn is_mock_trace(models_used: &str) -> bool {
-    models_used.to_lowercase().contains("mock")
+    contains_mock_model(&parse_models_used(models_used))
+}
+
+/// Check if a single model name is a mock model: a `(mock)` suffix, a
+/// `mock-` / `mock_` prefix, or exactly `mock` (case-insensitive).
+pub fn is_mock_model(name: &str) -> bool {
+    let name = name.trim().to_lowercase();
+    name == "mock"
+        || name.ends_with("(mock)")
+        || name.starts_with("mock-")
+        || name.starts_with("mock_")
 }
 
 /// Extract model names from a JSON array string.
@@ -24,9 +38,7 @@ pub fn parse_models_used(models_json: &str) -> Vec<String> {
 
 /// Check if any model in the list is a mock model.
 pub fn contains_mock_model(models: &[String]) -> bool {
-    models
-        .iter()
-        .any(|m| m.to_lowercase().contains("mock"))
+    models.iter().any(|m| is_mock_model(m))
 }
 
 #[cfg(test)]
@@ -43,6 +55,18 @@ mod tests {
         assert!(!is_mock_trace(r#"[]"#));
     }
 
+    #[test]
+    fn test_mock_substring_not_mock() {
+        assert!(!is_mock_trace(r#"["mockingbird-prod"]"#));
+        assert!(!is_mock_trace(r#"["gpt-4", "hammock-7b"]"#));
+        // Not a models array: no model names to match
+        assert!(!is_mock_trace("please mock this"));
+
+        assert!(is_mock_model("MOCK"));
+        assert!(is_mock_model("mock_llm"));
+        assert!(!is_mock_model("mockingbird-prod"));
+    }
+
     #[test]
     fn test_parse_models_used() {
         let models = parse_models_used(r#"["claude-3", "gpt-4"]"#);
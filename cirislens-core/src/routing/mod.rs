@@ -5,9 +5,13 @@
 //! - Mock table (accord_traces_mock)
 //! - Connectivity events table
 //! - Malformed traces table
+//!
+//! Production traces may be sampled out (not stored) at a configured rate.
 
 pub mod decision;
 pub mod mock_detection;
+pub mod sampling;
 
 pub use decision::*;
 pub use mock_detection::*;
+pub use sampling::*;
@@ -0,0 +1,74 @@
+//! Deterministic sampling of production traces.
+//!
+//! High-traffic deployments can store only a fraction of production
+//! traces. Whether a trace is kept depends only on a hash of its
+//! `trace_id`, so retries of the same trace are always kept or always
+//! dropped. Mock, connectivity and malformed traces are never sampled.
+//!
+//! The rate is `routing.production_sample_rate` in the pipeline config.
+
+use crate::validation::signature::compute_hash;
+
+/// Check that `rate` is a usable sample rate.
+///
+/// # Errors
+/// If `rate` is not within 0.0–1.0.
+pub fn check_sample_rate(rate: f64) -> Result<(), String> {
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(format!("sample rate must be within 0.0-1.0, got {}", rate));
+    }
+    Ok(())
+}
+
+/// Whether a production trace is kept at `rate`.
+///
+/// The first 64 bits of the trace id's SHA-256 map it to a point in
+/// [0, 1); traces below `rate` are kept.
+pub fn keep_sampled(trace_id: &str, rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    if rate <= 0.0 {
+        return false;
+    }
+    let hash = compute_hash(trace_id);
+    let bucket = u64::from_str_radix(&hash[..16], 16).unwrap_or(0);
+    (bucket as f64 / u64::MAX as f64) < rate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling_is_deterministic() {
+        for i in 0..100 {
+            let trace_id = format!("trace-{}", i);
+            assert_eq!(keep_sampled(&trace_id, 0.3), keep_sampled(&trace_id, 0.3));
+        }
+        assert!(keep_sampled("any", 1.0));
+        assert!(!keep_sampled("any", 0.0));
+    }
+
+    #[test]
+    fn test_sampling_approximates_rate() {
+        let kept = (0..10_000)
+            .filter(|i| keep_sampled(&format!("trace-{}", i), 0.25))
+            .count();
+        assert!((2_250..=2_750).contains(&kept), "kept {}", kept);
+
+        // A lower rate keeps a subset of a higher one
+        assert!((0..1_000)
+            .map(|i| format!("trace-{}", i))
+            .filter(|id| keep_sampled(id, 0.1))
+            .all(|id| keep_sampled(&id, 0.5)));
+    }
+
+    #[test]
+    fn test_rejects_out_of_range_rate() {
+        assert!(check_sample_rate(0.5).is_ok());
+        assert!(check_sample_rate(1.5).is_err());
+        assert!(check_sample_rate(-0.1).is_err());
+        assert!(check_sample_rate(f64::NAN).is_err());
+    }
+}
@@ -41,6 +41,7 @@ pub enum TraceLevel {
 }
 
 impl TraceLevel {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self, ScrubError> {
         match s {
             "generic" => Ok(Self::Generic),
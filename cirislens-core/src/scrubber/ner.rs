@@ -159,17 +159,7 @@ pub fn scrub_batch(texts: &[String], stats: &mut ScrubStats) -> Result<Vec<Strin
     // Run NER on the deduped misses only (single batched forward pass).
     if !deduped_misses.is_empty() {
         stats.ner_cache_misses += deduped_misses.len();
-        #[cfg(feature = "ner")]
-        let computed = match backend_choice() {
-            #[cfg(feature = "ner-ort")]
-            BackendChoice::Ort => ort_backend::scrub_batch(&deduped_misses, stats)?,
-            BackendChoice::Candle => backend::scrub_batch(&deduped_misses, stats)?,
-        };
-        #[cfg(not(feature = "ner"))]
-        let computed: Vec<String> = {
-            let _ = stats;
-            return Err(ScrubError::NerNotConfigured);
-        };
+        let computed = run_backend(&deduped_misses, stats)?;
 
         // Populate cache and broadcast the deduped results back to all
         // occurrences in the input order.
@@ -189,6 +179,21 @@ pub fn scrub_batch(texts: &[String], stats: &mut ScrubStats) -> Result<Vec<Strin
     Ok(out.into_iter().map(|x| x.unwrap_or_default()).collect())
 }
 
+/// Run the selected backend over texts that missed the cache.
+#[cfg(feature = "ner")]
+fn run_backend(texts: &[String], stats: &mut ScrubStats) -> Result<Vec<String>, ScrubError> {
+    match backend_choice() {
+        #[cfg(feature = "ner-ort")]
+        BackendChoice::Ort => ort_backend::scrub_batch(texts, stats),
+        BackendChoice::Candle => backend::scrub_batch(texts, stats),
+    }
+}
+
+#[cfg(not(feature = "ner"))]
+fn run_backend(_texts: &[String], _stats: &mut ScrubStats) -> Result<Vec<String>, ScrubError> {
+    Err(ScrubError::NerNotConfigured)
+}
+
 // ───────────────────────────────────────────────────────────────────────────
 // Content cache
 //
@@ -152,10 +152,8 @@ fn collect_ner_inputs(
         return Err(ScrubError::WalkerDepthExceeded(depth));
     }
     match value {
-        Value::String(s) => {
-            if in_scope && !s.trim().is_empty() && !looks_like_schema_label(s) {
-                inputs.push(s.clone());
-            }
+        Value::String(s) if in_scope && !s.trim().is_empty() && !looks_like_schema_label(s) => {
+            inputs.push(s.clone());
         }
         Value::Array(arr) => {
             for item in arr {
@@ -6,17 +6,25 @@
 //! - Command injection patterns
 //! - Path traversal patterns
 
+use std::collections::HashSet;
+use std::sync::RwLock;
+
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::logging::structured::LogContext;
+use crate::validation::signature::compute_hash;
 
 /// Size limits for trace data.
 pub const MAX_FIELD_SIZE: usize = 100_000;  // 100KB per field
 pub const MAX_COMPONENT_SIZE: usize = 1_000_000;  // 1MB per component
 pub const MAX_TRACE_SIZE: usize = 10_000_000;  // 10MB per trace
 
+/// Deepest object/array nesting traversed; deeper subtrees are not scanned.
+pub const MAX_JSON_DEPTH: usize = 64;
+
 lazy_static! {
     /// XSS detection patterns
     static ref XSS_PATTERNS: Vec<Regex> = vec![
@@ -50,6 +58,84 @@ lazy_static! {
         Regex::new(r"[\\/]etc[\\/](passwd|shadow)").unwrap(),
         Regex::new(r"[\\/](proc|sys)[\\/]").unwrap(),
     ];
+    /// XSS constructs replaced under `XssMode::Neutralize`: whole script
+    /// blocks, remaining script/iframe/object/embed tags, `javascript:`
+    /// URIs and inline event handlers. The handler pattern needs a word
+    /// boundary so identifiers like `json_data=` are left alone.
+    static ref XSS_NEUTRALIZE_PATTERNS: Vec<Regex> = vec![
+        Regex::new(r"(?is)<script[^>]*>.*?</script\s*>").unwrap(),
+        Regex::new(r"(?i)<(script|iframe|object|embed)[^>]*>").unwrap(),
+        Regex::new(r"(?i)javascript:").unwrap(),
+        Regex::new(r"(?i)\bon[a-z]+\s*=").unwrap(),
+    ];
+}
+
+/// Replacement for neutralized XSS constructs.
+pub const XSS_BLOCKED_TOKEN: &str = "[BLOCKED_XSS]";
+
+lazy_static! {
+    /// Field names whose values (whole subtree) are skipped by the scanner.
+    /// Loaded from the database; empty means scan everything.
+    static ref SCAN_EXCLUDED_FIELDS: RwLock<HashSet<String>> = RwLock::new(HashSet::new());
+}
+
+/// Replace the set of fields excluded from security scanning.
+pub fn set_scan_excluded_fields(fields: Vec<String>) {
+    let mut excluded = SCAN_EXCLUDED_FIELDS
+        .write()
+        .expect("Scan exclusion lock poisoned");
+    *excluded = fields.into_iter().collect();
+}
+
+/// Get a copy of the fields currently excluded from security scanning.
+pub fn get_scan_excluded_fields() -> HashSet<String> {
+    SCAN_EXCLUDED_FIELDS
+        .read()
+        .expect("Scan exclusion lock poisoned")
+        .clone()
+}
+
+/// Handling of string fields larger than `MAX_FIELD_SIZE`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OversizeMode {
+    /// Log and keep the value as-is.
+    #[default]
+    Keep,
+    /// Replace the value with `[OVERSIZE:sha256=...,len=...]`.
+    HashAndDrop,
+}
+
+/// Handling of strings with detected XSS patterns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum XssMode {
+    /// Log detections and keep the value as-is.
+    #[default]
+    Log,
+    /// Log and replace script tags, `javascript:` URIs and event handlers
+    /// with `[BLOCKED_XSS]`.
+    Neutralize,
+}
+
+/// Per-call sanitizer settings.
+#[derive(Debug, Clone, Default)]
+pub struct SanitizeOptions {
+    /// Field names whose subtrees are not scanned.
+    pub excluded_fields: HashSet<String>,
+    pub oversize: OversizeMode,
+    pub xss: XssMode,
+}
+
+impl SanitizeOptions {
+    /// Options using the DB-loaded exclusion list.
+    pub fn from_globals(oversize: OversizeMode, xss: XssMode) -> Self {
+        Self {
+            excluded_fields: get_scan_excluded_fields(),
+            oversize,
+            xss,
+        }
+    }
 }
 
 /// Security detection result.
@@ -60,20 +146,58 @@ pub struct SanitizationResult {
     pub cmd_detections: usize,
     pub path_detections: usize,
     pub oversized_fields: usize,
+    /// Subtrees nested deeper than `MAX_JSON_DEPTH` (not traversed).
+    pub depth_exceeded: usize,
     pub total_detections: usize,
+    /// Excluded fields whose subtrees were not scanned.
+    pub skipped_fields: usize,
 }
 
 impl SanitizationResult {
     pub fn has_detections(&self) -> bool {
         self.total_detections > 0
     }
+
+    /// Per-category detection counts keyed by metadata column name.
+    pub fn category_columns(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            ("security_xss_count", self.xss_detections),
+            ("security_sql_count", self.sql_detections),
+            ("security_cmd_count", self.cmd_detections),
+            ("security_path_count", self.path_detections),
+            ("security_oversized_count", self.oversized_fields),
+        ]
+    }
 }
 
 /// Sanitize a trace by detecting and neutralizing security threats.
 ///
 /// Returns the sanitized trace (threats are logged but not removed,
-/// as we want to preserve the original data for analysis).
-pub fn sanitize_trace(trace: &Value, ctx: &LogContext) -> Value {
+/// as we want to preserve the original data for analysis) and the
+/// detection counts. Fields in the DB-loaded exclusion list are not
+/// scanned.
+pub fn sanitize_trace(trace: &Value, ctx: &LogContext) -> (Value, SanitizationResult) {
+    sanitize_trace_with(
+        trace,
+        &SanitizeOptions::from_globals(OversizeMode::Keep, XssMode::Log),
+        ctx,
+    )
+}
+
+/// Sanitize a trace with explicit options.
+///
+/// Excluded fields trade coverage for throughput on large blob fields
+/// (e.g. `system_snapshot`) that rarely carry injected markup. With
+/// `OversizeMode::HashAndDrop`, oversize strings are replaced by their
+/// hash and length so the rest of the trace stays usable.
+/// With `XssMode::Neutralize`, XSS constructs in scanned fields are
+/// replaced by `[BLOCKED_XSS]`; a trace without detections is returned
+/// unchanged.
+pub fn sanitize_trace_with(
+    trace: &Value,
+    options: &SanitizeOptions,
+    ctx: &LogContext,
+) -> (Value, SanitizationResult) {
     log::debug!("{} SANITIZE_START", ctx);
 
     let mut result = SanitizationResult::default();
@@ -91,43 +215,146 @@ pub fn sanitize_trace(trace: &Value, ctx: &LogContext) -> Value {
     }
 
     // Scan for security patterns
-    scan_value(trace, ctx, &mut result);
+    scan_value(trace, &options.excluded_fields, 0, ctx, &mut result);
+
+    if result.skipped_fields > 0 {
+        log::debug!(
+            "{} SANITIZE_FIELDS_SKIPPED count={}",
+            ctx,
+            result.skipped_fields
+        );
+    }
 
     if result.has_detections() {
         log::warn!(
-            "{} SECURITY_DETECTIONS xss={} sql={} cmd={} path={} oversized={}",
+            "{} SECURITY_DETECTIONS xss={} sql={} cmd={} path={} oversized={} depth_exceeded={}",
             ctx,
             result.xss_detections,
             result.sql_detections,
             result.cmd_detections,
             result.path_detections,
-            result.oversized_fields
+            result.oversized_fields,
+            result.depth_exceeded
         );
     } else {
         log::debug!("{} SANITIZE_COMPLETE detections=0", ctx);
     }
 
-    // Return trace as-is (we log detections but don't modify)
-    trace.clone()
+    // Return trace as-is (we log detections but don't modify), except
+    // for oversize fields when hash-and-drop is enabled and XSS when
+    // neutralizing
+    let mut sanitized = trace.clone();
+    if options.xss == XssMode::Neutralize && result.xss_detections > 0 {
+        let neutralized = neutralize_xss(&mut sanitized, &options.excluded_fields);
+        log::warn!("{} XSS_NEUTRALIZED count={}", ctx, neutralized);
+    }
+    if options.oversize == OversizeMode::HashAndDrop && result.oversized_fields > 0 {
+        let replaced = replace_oversize_strings(&mut sanitized, MAX_FIELD_SIZE);
+        log::warn!(
+            "{} OVERSIZE_FIELDS_REPLACED count={} limit={}",
+            ctx,
+            replaced,
+            MAX_FIELD_SIZE
+        );
+    }
+    (sanitized, result)
+}
+
+/// Replace every string longer than `limit` bytes with a hash marker.
+/// Returns the number of values replaced.
+fn replace_oversize_strings(value: &mut Value, limit: usize) -> usize {
+    match value {
+        Value::String(s) if s.len() > limit => {
+            *s = format!("[OVERSIZE:sha256={},len={}]", compute_hash(s), s.len());
+            1
+        }
+        Value::Array(arr) => arr
+            .iter_mut()
+            .map(|v| replace_oversize_strings(v, limit))
+            .sum(),
+        Value::Object(obj) => obj
+            .values_mut()
+            .map(|v| replace_oversize_strings(v, limit))
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// Replace XSS constructs in every scanned string value with
+/// `[BLOCKED_XSS]`. Returns the number of strings changed.
+///
+/// Keys are not rewritten, and excluded fields are skipped as in
+/// `scan_value`.
+fn neutralize_xss(value: &mut Value, excluded_fields: &HashSet<String>) -> usize {
+    match value {
+        Value::String(s) => {
+            let mut neutralized = std::borrow::Cow::Borrowed(s.as_str());
+            for pattern in XSS_NEUTRALIZE_PATTERNS.iter() {
+                if pattern.is_match(&neutralized) {
+                    neutralized = std::borrow::Cow::Owned(
+                        pattern.replace_all(&neutralized, XSS_BLOCKED_TOKEN).into_owned(),
+                    );
+                }
+            }
+            match neutralized {
+                std::borrow::Cow::Owned(replaced) => {
+                    *s = replaced;
+                    1
+                }
+                std::borrow::Cow::Borrowed(_) => 0,
+            }
+        }
+        Value::Array(arr) => arr
+            .iter_mut()
+            .map(|v| neutralize_xss(v, excluded_fields))
+            .sum(),
+        Value::Object(obj) => obj
+            .iter_mut()
+            .filter(|(key, _)| !excluded_fields.contains(key.as_str()))
+            .map(|(_, v)| neutralize_xss(v, excluded_fields))
+            .sum(),
+        _ => 0,
+    }
 }
 
 /// Recursively scan a JSON value for security patterns.
-fn scan_value(value: &Value, ctx: &LogContext, result: &mut SanitizationResult) {
+fn scan_value(
+    value: &Value,
+    excluded_fields: &HashSet<String>,
+    depth: usize,
+    ctx: &LogContext,
+    result: &mut SanitizationResult,
+) {
+    if matches!(value, Value::Array(_) | Value::Object(_)) && depth >= MAX_JSON_DEPTH {
+        log::debug!(
+            "{} SIZE_LIMIT_EXCEEDED type=depth limit={}",
+            ctx,
+            MAX_JSON_DEPTH
+        );
+        result.depth_exceeded += 1;
+        result.total_detections += 1;
+        return;
+    }
     match value {
         Value::String(s) => {
             scan_string(s, ctx, result);
         }
         Value::Array(arr) => {
             for item in arr {
-                scan_value(item, ctx, result);
+                scan_value(item, excluded_fields, depth + 1, ctx, result);
             }
         }
         Value::Object(obj) => {
             for (key, val) in obj {
                 // Check key for injection
                 scan_string(key, ctx, result);
-                // Check value
-                scan_value(val, ctx, result);
+                // Check value unless the whole field is excluded
+                if excluded_fields.contains(key) {
+                    log::debug!("{} SANITIZE_FIELD_SKIPPED field={}", ctx, key);
+                    result.skipped_fields += 1;
+                    continue;
+                }
+                scan_value(val, excluded_fields, depth + 1, ctx, result);
             }
         }
         _ => {}
@@ -212,9 +439,10 @@ mod tests {
             "content": "<script>alert('xss')</script>"
         });
 
-        let result = sanitize_trace(&trace, &ctx);
+        let (sanitized, result) = sanitize_trace(&trace, &ctx);
         // Should detect but not modify
-        assert_eq!(result, trace);
+        assert_eq!(sanitized, trace);
+        assert_eq!(result.xss_detections, 1);
     }
 
     #[test]
@@ -224,8 +452,8 @@ mod tests {
             "query": "SELECT * FROM users WHERE id = 1; DROP TABLE users;"
         });
 
-        sanitize_trace(&trace, &ctx);
-        // Just verify it runs without panic
+        let (_, result) = sanitize_trace(&trace, &ctx);
+        assert!(result.sql_detections > 0);
     }
 
     #[test]
@@ -236,7 +464,110 @@ mod tests {
             "reasoning": "This is a normal trace without any security issues."
         });
 
-        let result = sanitize_trace(&trace, &ctx);
-        assert_eq!(result, trace);
+        let (sanitized, result) = sanitize_trace(&trace, &ctx);
+        assert_eq!(sanitized, trace);
+        assert!(!result.has_detections());
+    }
+
+    #[test]
+    fn test_excluded_field_not_scanned() {
+        let ctx = LogContext::new("test-batch");
+        let trace = serde_json::json!({
+            "system_snapshot": {"html": "<script>alert('xss')</script>"},
+            "reasoning": "normal text"
+        });
+        let excluded = HashSet::from(["system_snapshot".to_string()]);
+
+        let mut result = SanitizationResult::default();
+        scan_value(&trace, &excluded, 0, &ctx, &mut result);
+        assert_eq!(result.xss_detections, 0);
+        assert_eq!(result.skipped_fields, 1);
+
+        // Same trace without the exclusion is flagged
+        let mut result = SanitizationResult::default();
+        scan_value(&trace, &HashSet::new(), 0, &ctx, &mut result);
+        assert_eq!(result.xss_detections, 1);
+        assert_eq!(result.skipped_fields, 0);
+    }
+
+    #[test]
+    fn test_xss_neutralized() {
+        let ctx = LogContext::new("test-batch");
+        let trace = serde_json::json!({
+            "content": "hi <script>alert('xss')</script> there",
+            "link": "<a href=\"javascript:void(0)\" onclick = \"x()\">",
+            "json_data=": "identifier, not a handler",
+            "system_snapshot": {"html": "<script>kept</script>"}
+        });
+        let options = SanitizeOptions {
+            excluded_fields: HashSet::from(["system_snapshot".to_string()]),
+            xss: XssMode::Neutralize,
+            ..Default::default()
+        };
+
+        let (result, _) = sanitize_trace_with(&trace, &options, &ctx);
+        assert_eq!(result["content"], "hi [BLOCKED_XSS] there");
+        assert_eq!(result["link"], "<a href=\"[BLOCKED_XSS]void(0)\" [BLOCKED_XSS] \"x()\">");
+        assert_eq!(result["json_data="], "identifier, not a handler");
+        assert_eq!(result["system_snapshot"]["html"], "<script>kept</script>");
+    }
+
+    #[test]
+    fn test_clean_trace_unchanged_when_neutralizing() {
+        let ctx = LogContext::new("test-batch");
+        let trace = serde_json::json!({
+            "thought_id": "test-123",
+            "reasoning": "Normal text; the question = whether to continue.",
+            "scores": [0.5, 1]
+        });
+        let options = SanitizeOptions {
+            xss: XssMode::Neutralize,
+            ..Default::default()
+        };
+
+        let (result, _) = sanitize_trace_with(&trace, &options, &ctx);
+        assert_eq!(result.to_string(), trace.to_string());
+    }
+
+    #[test]
+    fn test_oversize_field_hashed_and_dropped() {
+        let ctx = LogContext::new("test-batch");
+        let big = "x".repeat(MAX_FIELD_SIZE + 1);
+        let trace = serde_json::json!({
+            "components": [{"data": {"system_snapshot": big, "reasoning": "kept"}}]
+        });
+
+        let (kept, result) = sanitize_trace_with(&trace, &SanitizeOptions::default(), &ctx);
+        assert_eq!(kept, trace);
+        assert_eq!(result.oversized_fields, 1);
+
+        let options = SanitizeOptions {
+            oversize: OversizeMode::HashAndDrop,
+            ..Default::default()
+        };
+        let (dropped, _) = sanitize_trace_with(&trace, &options, &ctx);
+        let data = &dropped["components"][0]["data"];
+        assert_eq!(
+            data["system_snapshot"],
+            format!("[OVERSIZE:sha256={},len={}]", compute_hash(&big), big.len())
+        );
+        assert_eq!(data["reasoning"], "kept");
+    }
+
+    #[test]
+    fn test_deep_nesting_stops_traversal() {
+        let ctx = LogContext::new("test-batch");
+        let mut trace = serde_json::json!("<script>alert(1)</script>");
+        for _ in 0..200 {
+            trace = serde_json::json!([trace]);
+        }
+
+        let (_, result) = sanitize_trace(&trace, &ctx);
+        assert_eq!(result.depth_exceeded, 1);
+        assert_eq!(result.xss_detections, 0);
+
+        let shallow = serde_json::json!([[["x"]]]);
+        let (_, result) = sanitize_trace(&shallow, &ctx);
+        assert_eq!(result.depth_exceeded, 0);
     }
 }
@@ -82,6 +82,22 @@ pub fn get_trace_columns() -> Vec<(&'static str, &'static str)> {
     ]
 }
 
+/// accord_traces columns the pipeline fills for every trace, whatever
+/// the schema's field rules.
+pub const STANDARD_TRACE_COLUMNS: &[&str] = &[
+    "trace_id",
+    "timestamp",
+    "trace_level",
+    "schema_version",
+    "batch_timestamp",
+    "consent_timestamp",
+    "signature",
+    "signature_key_id",
+    "signature_verified",
+    "pii_scrubbed",
+    "original_content_hash",
+];
+
 /// Build INSERT query for accord_traces.
 pub fn build_trace_insert() -> String {
     let columns = get_trace_columns();
@@ -95,6 +111,86 @@ pub fn build_trace_insert() -> String {
     )
 }
 
+/// PostgreSQL's limit on bind parameters per statement.
+pub const MAX_BIND_PARAMETERS: usize = 65_535;
+
+/// Build a multi-row INSERT query for accord_traces.
+///
+/// Row `i` (0-based) uses placeholders `$(i*60+1)..$(i*60+60)` in
+/// `get_trace_columns` order, so callers flatten each row's parameters in
+/// that order, row after row.
+///
+/// # Returns
+/// The query and its total placeholder count.
+///
+/// # Errors
+/// If `row_count` is 0 or needs more than `MAX_BIND_PARAMETERS`
+/// placeholders.
+pub fn build_trace_insert_batch(row_count: usize) -> Result<(String, usize), String> {
+    if row_count == 0 {
+        return Err("row_count must be at least 1".to_string());
+    }
+    let columns = get_trace_columns();
+    let placeholder_count = row_count * columns.len();
+    if placeholder_count > MAX_BIND_PARAMETERS {
+        return Err(format!(
+            "{} rows need {} placeholders, over the limit of {}",
+            row_count, placeholder_count, MAX_BIND_PARAMETERS
+        ));
+    }
+
+    let col_names: Vec<&str> = columns.iter().map(|(name, _)| *name).collect();
+    let rows: Vec<String> = (0..row_count)
+        .map(|row| {
+            let offset = row * columns.len();
+            let placeholders: Vec<String> =
+                (1..=columns.len()).map(|i| format!("${}", offset + i)).collect();
+            format!("({})", placeholders.join(", "))
+        })
+        .collect();
+
+    let query = format!(
+        "INSERT INTO cirislens.accord_traces ({}) VALUES {} ON CONFLICT (trace_id) DO NOTHING",
+        col_names.join(", "),
+        rows.join(", ")
+    );
+    Ok((query, placeholder_count))
+}
+
+/// Build an UPDATE query refreshing a subset of accord_traces columns.
+///
+/// For reprocessing stored traces after a schema's extraction rules
+/// change: only `columns` are set, so unrelated data is kept. `trace_id`
+/// is `$1`; `columns[i]` is `$(i+2)`.
+///
+/// # Errors
+/// If `columns` is empty, repeats a column, names `trace_id`, or names a
+/// column not in `get_trace_columns`.
+pub fn build_trace_update(columns: &[&str]) -> Result<String, String> {
+    if columns.is_empty() {
+        return Err("no columns to update".to_string());
+    }
+    let known = get_trace_columns();
+    let mut assignments = Vec::with_capacity(columns.len());
+    for (i, column) in columns.iter().enumerate() {
+        if *column == "trace_id" {
+            return Err("trace_id identifies the row and cannot be updated".to_string());
+        }
+        if !known.iter().any(|(name, _)| name == column) {
+            return Err(format!("unknown accord_traces column: {:?}", column));
+        }
+        if columns[..i].contains(column) {
+            return Err(format!("column listed twice: {}", column));
+        }
+        assignments.push(format!("{} = ${}", column, i + 2));
+    }
+
+    Ok(format!(
+        "UPDATE cirislens.accord_traces SET {} WHERE trace_id = $1",
+        assignments.join(", ")
+    ))
+}
+
 /// Build INSERT query for connectivity_events.
 pub fn build_connectivity_insert() -> &'static str {
     r#"
@@ -115,6 +211,36 @@ pub fn build_malformed_insert() -> &'static str {
     "#
 }
 
+/// Default table for quarantined traces.
+pub const DEFAULT_QUARANTINE_TABLE: &str = "cirislens.quarantined_traces";
+
+/// Build INSERT query for quarantined traces.
+///
+/// Mirrors the malformed insert, plus the context needed to triage and
+/// release the trace: the policy reason, matched schema version, signing
+/// key and extracted metadata. `table` comes from config, so it must be a
+/// plain `[schema.]table` identifier.
+pub fn build_quarantine_insert(table: &str) -> Result<String, String> {
+    let valid_part = |part: &str| {
+        part.chars().next().is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+            && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    };
+    let parts: Vec<&str> = table.split('.').collect();
+    if parts.len() > 2 || !parts.iter().all(|part| valid_part(part)) {
+        return Err(format!("invalid quarantine table name: {}", table));
+    }
+
+    Ok(format!(
+        r#"
+    INSERT INTO {}
+        (trace_id, content_hash, policy_reason, event_types, trace_level, received_at,
+         schema_version, signature_key_id, extracted_metadata)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+    "#,
+        table
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -127,6 +253,43 @@ mod tests {
         assert!(query.contains("ON CONFLICT"));
     }
 
+    #[test]
+    fn test_trace_insert_batch_numbering() {
+        let (query, count) = build_trace_insert_batch(3).unwrap();
+        assert_eq!(count, 180);
+        assert!(query.ends_with("ON CONFLICT (trace_id) DO NOTHING"));
+
+        let values = &query[query.find("VALUES").unwrap()..];
+        let numbers: Vec<usize> = values
+            .split('$')
+            .skip(1)
+            .map(|p| p.chars().take_while(|c| c.is_ascii_digit()).collect::<String>().parse().unwrap())
+            .collect();
+        assert_eq!(numbers, (1..=180).collect::<Vec<_>>());
+        assert!(values.contains("$60), ($61"));
+
+        // One row matches the single-row builder
+        assert_eq!(build_trace_insert_batch(1).unwrap().0, build_trace_insert());
+        assert!(build_trace_insert_batch(0).is_err());
+        assert!(build_trace_insert_batch(1_093).is_err());
+        assert!(build_trace_insert_batch(1_092).is_ok());
+    }
+
+    #[test]
+    fn test_trace_update_query() {
+        let query = build_trace_update(&["idma_k_eff", "selected_action"]).unwrap();
+        assert_eq!(
+            query,
+            "UPDATE cirislens.accord_traces SET idma_k_eff = $2, selected_action = $3 WHERE trace_id = $1"
+        );
+
+        let err = build_trace_update(&["idma_k_eff", "k_eff; DROP TABLE x"]).unwrap_err();
+        assert!(err.contains("unknown accord_traces column"), "{}", err);
+        assert!(build_trace_update(&[]).is_err());
+        assert!(build_trace_update(&["trace_id"]).is_err());
+        assert!(build_trace_update(&["idma_k_eff", "idma_k_eff"]).is_err());
+    }
+
     #[test]
     fn test_connectivity_insert_query() {
         let query = build_connectivity_insert();
@@ -134,6 +297,29 @@ mod tests {
         assert!(query.contains("event_type"));
     }
 
+    #[test]
+    fn test_quarantine_insert_query() {
+        let query = build_quarantine_insert(DEFAULT_QUARANTINE_TABLE).unwrap();
+        assert!(query.contains("INSERT INTO cirislens.quarantined_traces"));
+        for column in ["policy_reason", "schema_version", "signature_key_id"] {
+            assert!(query.contains(column), "{}", column);
+        }
+        assert!(query.contains("$9)"));
+
+        assert!(build_quarantine_insert("held_traces").is_ok());
+        for bad in ["", "a.b.c", "x; DROP TABLE y", "Traces", "cirislens."] {
+            assert!(build_quarantine_insert(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_standard_columns_are_trace_columns() {
+        let columns = get_trace_columns();
+        for standard in STANDARD_TRACE_COLUMNS {
+            assert!(columns.iter().any(|(name, _)| name == standard), "{}", standard);
+        }
+    }
+
     #[test]
     fn test_column_count() {
         let columns = get_trace_columns();
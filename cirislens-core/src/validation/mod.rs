@@ -7,7 +7,9 @@
 
 pub mod schema;
 pub mod schema_cache;
+pub mod replay_detection;
 pub mod signature;
+pub mod verification_cache;
 
 pub use schema::*;
 // schema_cache re-exports from schema module
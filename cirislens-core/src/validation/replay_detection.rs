@@ -0,0 +1,105 @@
+//! Detection of signatures copied from another trace.
+//!
+//! Ed25519 already guarantees a signature over one components array
+//! fails for another, but an agent bug that copies a prior trace's
+//! signature onto a new trace then shows up as an ordinary verification
+//! failure. A bounded map from recently verified signatures to a hash of
+//! the components they signed lets such failures be reported as
+//! `SIGNATURE_REPLAY_SUSPECTED` instead.
+
+use std::collections::{HashMap, VecDeque};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+/// Default number of verified signatures remembered.
+pub const DEFAULT_REPLAY_WINDOW: usize = 10_000;
+
+/// Bounded FIFO map of signature -> components hash.
+#[derive(Debug, Default)]
+pub struct RecentSignatures {
+    components: HashMap<String, String>,
+    order: VecDeque<String>,
+}
+
+impl RecentSignatures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /// Remember the components hash a signature verified over, evicting
+    /// the oldest entries beyond `capacity`. A capacity of 0 disables it.
+    pub fn insert(&mut self, signature: String, components_hash: String, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        if self.components.insert(signature.clone(), components_hash).is_none() {
+            self.order.push_back(signature);
+        }
+        while self.order.len() > capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.components.remove(&oldest);
+            }
+        }
+    }
+
+    /// Whether `signature` recently verified over components other than
+    /// `components_hash`.
+    pub fn verified_elsewhere(&self, signature: &str, components_hash: &str) -> bool {
+        self.components
+            .get(signature)
+            .is_some_and(|hash| hash != components_hash)
+    }
+
+    pub fn clear(&mut self) {
+        self.components.clear();
+        self.order.clear();
+    }
+}
+
+lazy_static! {
+    static ref RECENT_SIGNATURES: Mutex<RecentSignatures> = Mutex::new(RecentSignatures::new());
+}
+
+/// Remember a successfully verified signature.
+pub fn remember_verified_signature(signature: &str, components_hash: &str, capacity: usize) {
+    RECENT_SIGNATURES
+        .lock()
+        .insert(signature.to_string(), components_hash.to_string(), capacity);
+}
+
+/// Whether a failed signature recently verified over other components.
+pub fn is_replayed_signature(signature: &str, components_hash: &str) -> bool {
+    RECENT_SIGNATURES.lock().verified_elsewhere(signature, components_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verified_elsewhere() {
+        let mut recent = RecentSignatures::new();
+        recent.insert("sig-a".to_string(), "hash-1".to_string(), 2);
+
+        assert!(recent.verified_elsewhere("sig-a", "hash-2"));
+        assert!(!recent.verified_elsewhere("sig-a", "hash-1"));
+        assert!(!recent.verified_elsewhere("sig-b", "hash-1"));
+
+        recent.insert("sig-b".to_string(), "hash-2".to_string(), 2);
+        recent.insert("sig-c".to_string(), "hash-3".to_string(), 2);
+        assert_eq!(recent.len(), 2);
+        assert!(!recent.verified_elsewhere("sig-a", "hash-2"));
+
+        recent.insert("sig-d".to_string(), "hash-4".to_string(), 0);
+        assert!(!recent.verified_elsewhere("sig-d", "hash-0"));
+    }
+}
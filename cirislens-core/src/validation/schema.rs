@@ -3,16 +3,20 @@
 //! Provides schema detection and validation using schemas loaded from database.
 //! No hardcoded schema definitions - everything comes from trace_schemas table.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::sync::RwLock;
 use std::time::{Duration, Instant};
 
 use lazy_static::lazy_static;
+use serde::Deserialize;
 
+use crate::config::MixedConnectivityAction;
 use crate::logging::structured::LogContext;
+use crate::security::pii::get_pii_target_fields;
+use crate::storage::queries::STANDARD_TRACE_COLUMNS;
 
-/// Cache TTL - 5 minutes
-const CACHE_TTL_SECS: u64 = 300;
+/// Default cache TTL - 5 minutes
+pub const CACHE_TTL_SECS: u64 = 300;
 
 /// Field extraction rule loaded from database.
 #[derive(Debug, Clone)]
@@ -22,6 +26,42 @@ pub struct FieldExtractionRule {
     pub data_type: String, // string, float, int, boolean, json, timestamp
     pub required: bool,
     pub db_column: String,
+    /// Values the column may hold; None = unrestricted.
+    pub allowed_values: Option<HashSet<String>>,
+    /// Stored (converted to `data_type`) when the path is absent.
+    pub default_value: Option<String>,
+}
+
+/// A trace_schema_fields row: (schema_ver, event_type, field_name,
+/// json_path, data_type, required, db_column, default_value).
+pub type FieldRow = (String, String, String, String, String, bool, String, Option<String>);
+
+/// Optional per-schema settings beyond the core trace_schemas columns.
+///
+/// Loaded as a JSON object per schema version; every key is optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchemaOptions {
+    /// Event types a trace must contain to be valid for this schema.
+    /// Defaults to the signature event types when absent.
+    pub required_event_types: Option<Vec<String>>,
+    /// Event types whose presence rules this schema out, to tell apart
+    /// schemas sharing the same signature events.
+    pub forbidden_event_types: Option<Vec<String>>,
+    /// Distinct registered keys that must verify a trace (M of N).
+    /// Falls back to the global `signature.quorum_threshold` when absent.
+    pub signature_quorum: Option<usize>,
+    /// Allowed values per db_column for enum-like string fields
+    /// (e.g. `cognitive_state`, `selected_action`).
+    pub allowed_values: Option<HashMap<String, Vec<String>>>,
+    /// PII target fields for this schema at full_traces. Merged with the
+    /// global list unless `pii_target_override` is set.
+    pub pii_target_fields: Option<Vec<String>>,
+    /// Use `pii_target_fields` instead of the global list.
+    pub pii_target_override: bool,
+    /// Accept `components` as a base64 string of the JSON array. It is
+    /// decoded before validation; signatures cover the decoded array.
+    pub components_base64: bool,
 }
 
 /// Schema definition loaded from database.
@@ -31,15 +71,40 @@ pub struct SchemaDefinition {
     pub description: String,
     pub status: String, // current, supported, deprecated
     pub signature_event_types: HashSet<String>,
+    pub required_event_types: HashSet<String>, // superset check for validity, not signing
+    pub required_from_signature: bool, // required_event_types defaulted to the signature events
+    pub forbidden_event_types: HashSet<String>, // any present = no match
+    pub signature_quorum: Option<usize>, // None = use global threshold
+    pub pii_target_fields: Option<HashSet<String>>, // None = scrub the whole trace
+    pub pii_target_override: bool, // pii_target_fields replaces the global list
     pub field_extractions: HashMap<String, Vec<FieldExtractionRule>>, // event_type -> rules
     pub match_mode: String, // "all" or "any"
     pub special_handling: bool,
+    pub components_base64: bool, // components may arrive base64-encoded
 }
 
 impl SchemaDefinition {
+    /// The fields to scrub under this schema, or None to scrub the whole
+    /// trace. Merges the global PII list as it stands now, so fields
+    /// loaded after the schemas still apply.
+    pub fn effective_pii_target_fields(&self) -> Option<HashSet<String>> {
+        self.pii_target_fields.as_ref().map(|fields| {
+            let mut targets = fields.clone();
+            if !self.pii_target_override {
+                targets.extend(get_pii_target_fields());
+            }
+            targets
+        })
+    }
+
     /// Check if this schema matches the given event types.
+    ///
+    /// Any forbidden event type present rules the schema out, whatever
+    /// the match mode.
     pub fn matches(&self, event_types: &HashSet<String>) -> bool {
-        if self.match_mode == "any" {
+        if !event_types.is_disjoint(&self.forbidden_event_types) {
+            false
+        } else if self.match_mode == "any" {
             // Any signature event present = match (for connectivity)
             !event_types.is_disjoint(&self.signature_event_types)
         } else {
@@ -47,10 +112,43 @@ impl SchemaDefinition {
             event_types.is_superset(&self.signature_event_types)
         }
     }
+
+    /// How closely the trace's event types fit this schema: the share of
+    /// them that are signature events. 1.0 is an exact match; a trace
+    /// carrying many extra event types scores lower.
+    pub fn match_confidence(&self, event_types: &HashSet<String>) -> f64 {
+        if event_types.is_empty() {
+            return 0.0;
+        }
+        let matched = event_types.intersection(&self.signature_event_types).count();
+        matched as f64 / event_types.len() as f64
+    }
+
+    /// Required event types missing from the given event types (sorted).
+    ///
+    /// When the required set defaulted to the signature events, it follows
+    /// the match mode: an any-mode (connectivity) schema then needs only
+    /// one of them, so a lone startup event is valid. An operator-set list
+    /// is always a superset check.
+    pub fn missing_required_events(&self, event_types: &HashSet<String>) -> Vec<String> {
+        if self.required_from_signature
+            && self.match_mode == "any"
+            && !event_types.is_disjoint(&self.required_event_types)
+        {
+            return Vec::new();
+        }
+        let mut missing: Vec<String> = self
+            .required_event_types
+            .difference(event_types)
+            .cloned()
+            .collect();
+        missing.sort();
+        missing
+    }
 }
 
 /// In-memory cache for trace schemas.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct SchemaCache {
     schemas: HashMap<String, SchemaDefinition>,
     schemas_by_priority: Vec<SchemaDefinition>,
@@ -58,17 +156,6 @@ pub struct SchemaCache {
     loaded_at: Option<Instant>,
 }
 
-impl Default for SchemaCache {
-    fn default() -> Self {
-        Self {
-            schemas: HashMap::new(),
-            schemas_by_priority: Vec::new(),
-            loaded: false,
-            loaded_at: None,
-        }
-    }
-}
-
 impl SchemaCache {
     pub fn new() -> Self {
         Self::default()
@@ -78,13 +165,18 @@ impl SchemaCache {
         self.loaded
     }
 
-    /// Check if cache needs refresh (not loaded or TTL expired).
+    /// Check if cache needs refresh (not loaded or default TTL expired).
     pub fn needs_refresh(&self) -> bool {
+        self.needs_refresh_after(CACHE_TTL_SECS)
+    }
+
+    /// Check if cache needs refresh (not loaded or `ttl_secs` expired).
+    pub fn needs_refresh_after(&self, ttl_secs: u64) -> bool {
         if !self.loaded {
             return true;
         }
         match self.loaded_at {
-            Some(loaded_at) => loaded_at.elapsed() > Duration::from_secs(CACHE_TTL_SECS),
+            Some(loaded_at) => loaded_at.elapsed() > Duration::from_secs(ttl_secs),
             None => true,
         }
     }
@@ -106,6 +198,11 @@ impl SchemaCache {
         &self.schemas_by_priority
     }
 
+    /// Whether any loaded schema accepts base64-encoded components.
+    pub fn accepts_base64_components(&self) -> bool {
+        self.schemas_by_priority.iter().any(|s| s.components_base64)
+    }
+
     /// Detect schema version from event types.
     pub fn detect_schema_version(
         &self,
@@ -140,6 +237,40 @@ impl SchemaCache {
         None
     }
 
+    /// Pick the schema for a trace matching both the connectivity schema
+    /// and a regular one, per `action`.
+    ///
+    /// `Ok(None)` when the trace isn't mixed (use `detect_schema_version`);
+    /// `Err` with the rejection reason when `action` rejects it.
+    pub fn resolve_mixed_connectivity(
+        &self,
+        event_types: &HashSet<String>,
+        action: MixedConnectivityAction,
+        ctx: &LogContext,
+    ) -> Result<Option<&SchemaDefinition>, String> {
+        let first_match = |special: bool| {
+            self.schemas_by_priority
+                .iter()
+                .find(|s| s.special_handling == special && s.matches(event_types))
+        };
+        let (Some(connectivity), Some(regular)) = (first_match(true), first_match(false)) else {
+            return Ok(None);
+        };
+
+        log::warn!(
+            "{} SCHEMA_MIXED_CONNECTIVITY regular={} connectivity={} action={:?}",
+            ctx,
+            regular.version,
+            connectivity.version,
+            action
+        );
+        match action {
+            MixedConnectivityAction::RegularWins => Ok(Some(regular)),
+            MixedConnectivityAction::ConnectivityWins => Ok(Some(connectivity)),
+            MixedConnectivityAction::Reject => Err("ambiguous_connectivity_trace".to_string()),
+        }
+    }
+
     /// Get field extraction rules for a schema/event_type.
     pub fn get_field_rules(&self, version: &str, event_type: &str) -> Vec<&FieldExtractionRule> {
         self.schemas
@@ -149,21 +280,51 @@ impl SchemaCache {
             .unwrap_or_default()
     }
 
+    /// Distinct `db_column`s a schema's field rules populate, plus the
+    /// standard columns filled for every trace, sorted. `None` for an
+    /// unknown version.
+    pub fn schema_columns(&self, version: &str) -> Option<Vec<String>> {
+        let schema = self.schemas.get(version)?;
+        let columns: BTreeSet<&str> = schema
+            .field_extractions
+            .values()
+            .flatten()
+            .map(|rule| rule.db_column.as_str())
+            .chain(STANDARD_TRACE_COLUMNS.iter().copied())
+            .collect();
+        Some(columns.into_iter().map(str::to_string).collect())
+    }
+
     /// Load schemas from database rows.
     ///
     /// # Arguments
     /// * `schemas` - (version, description, status, signature_events)
-    /// * `fields` - (schema_ver, event_type, field_name, json_path, data_type, required, db_column)
+    /// * `fields` - see `FieldRow`
     pub fn load_from_db_rows(
         &mut self,
         schemas: Vec<(String, String, String, Vec<String>)>,
-        fields: Vec<(String, String, String, String, String, bool, String)>,
+        fields: Vec<FieldRow>,
+    ) {
+        self.load_from_db_rows_with_options(schemas, fields, HashMap::new());
+    }
+
+    /// Load schemas from database rows plus per-schema options.
+    ///
+    /// # Arguments
+    /// * `schemas` - (version, description, status, signature_events)
+    /// * `fields` - see `FieldRow`
+    /// * `options` - schema version -> options; absent versions use defaults
+    pub fn load_from_db_rows_with_options(
+        &mut self,
+        schemas: Vec<(String, String, String, Vec<String>)>,
+        fields: Vec<FieldRow>,
+        mut options: HashMap<String, SchemaOptions>,
     ) {
         // Group fields by (schema_version, event_type)
         let mut fields_by_schema: HashMap<String, HashMap<String, Vec<FieldExtractionRule>>> =
             HashMap::new();
 
-        for (schema_ver, event_type, field_name, json_path, data_type, required, db_column) in
+        for (schema_ver, event_type, field_name, json_path, data_type, required, db_column, default_value) in
             fields
         {
             let rule = FieldExtractionRule {
@@ -172,6 +333,8 @@ impl SchemaCache {
                 data_type,
                 required,
                 db_column,
+                allowed_values: None,
+                default_value,
             };
 
             fields_by_schema
@@ -185,8 +348,24 @@ impl SchemaCache {
         // Build schema definitions
         let mut defs = Vec::new();
         for (version, description, status, signature_events) in schemas {
-            let field_extractions = fields_by_schema.remove(&version).unwrap_or_default();
+            let mut field_extractions = fields_by_schema.remove(&version).unwrap_or_default();
             let signature_event_types: HashSet<String> = signature_events.into_iter().collect();
+            let schema_options = options.remove(&version).unwrap_or_default();
+            if let Some(ref allowed) = schema_options.allowed_values {
+                for rule in field_extractions.values_mut().flatten() {
+                    if let Some(values) = allowed.get(&rule.db_column) {
+                        rule.allowed_values = Some(values.iter().cloned().collect());
+                    }
+                }
+            }
+            let pii_target_fields = schema_options
+                .pii_target_fields
+                .map(|fields| fields.into_iter().collect());
+            let required_from_signature = schema_options.required_event_types.is_none();
+            let required_event_types = match schema_options.required_event_types {
+                Some(required) => required.into_iter().collect(),
+                None => signature_event_types.clone(),
+            };
 
             // Detect match mode based on schema version
             let match_mode = if version == "connectivity" {
@@ -195,6 +374,18 @@ impl SchemaCache {
                 "all".to_string()
             };
 
+            // An empty set is a subset of every trace's event types, so an
+            // all-mode schema would match everything (and an any-mode one
+            // nothing). Always a misconfiguration: skip it.
+            if signature_event_types.is_empty() {
+                log::error!(
+                    "SCHEMA_REJECTED version={} match_mode={} reason=empty_signature_event_types",
+                    version,
+                    match_mode
+                );
+                continue;
+            }
+
             let special_handling = version == "connectivity";
 
             let def = SchemaDefinition {
@@ -202,14 +393,26 @@ impl SchemaCache {
                 description,
                 status: status.clone(),
                 signature_event_types,
+                required_event_types,
+                required_from_signature,
+                forbidden_event_types: schema_options
+                    .forbidden_event_types
+                    .map(|forbidden| forbidden.into_iter().collect())
+                    .unwrap_or_default(),
+                signature_quorum: schema_options.signature_quorum,
+                pii_target_fields,
+                pii_target_override: schema_options.pii_target_override,
                 field_extractions,
                 match_mode,
                 special_handling,
+                components_base64: schema_options.components_base64,
             };
             defs.push(def);
         }
 
-        // Sort by priority: current > supported > deprecated
+        // Sort by priority: current > supported > deprecated, then most
+        // specific (largest signature set) first, then version descending,
+        // so detection doesn't depend on row order.
         defs.sort_by(|a, b| {
             let priority = |s: &str| match s {
                 "current" => 0,
@@ -217,7 +420,10 @@ impl SchemaCache {
                 "deprecated" => 2,
                 _ => 3,
             };
-            priority(&a.status).cmp(&priority(&b.status))
+            priority(&a.status)
+                .cmp(&priority(&b.status))
+                .then_with(|| b.signature_event_types.len().cmp(&a.signature_event_types.len()))
+                .then_with(|| b.version.cmp(&a.version))
         });
 
         self.schemas = defs.iter().map(|d| (d.version.clone(), d.clone())).collect();
@@ -306,9 +512,16 @@ mod tests {
                 "THOUGHT_START".to_string(),
                 "DMA_RESULTS".to_string(),
             ]),
+            required_event_types: HashSet::new(),
+            required_from_signature: false,
+            forbidden_event_types: HashSet::new(),
+            signature_quorum: None,
+            pii_target_fields: None,
+            pii_target_override: false,
             field_extractions: HashMap::new(),
             match_mode: "all".to_string(),
             special_handling: false,
+            components_base64: false,
         };
 
         // Should match when all signature events present
@@ -334,9 +547,16 @@ mod tests {
                 "startup".to_string(),
                 "shutdown".to_string(),
             ]),
+            required_event_types: HashSet::new(),
+            required_from_signature: false,
+            forbidden_event_types: HashSet::new(),
+            signature_quorum: None,
+            pii_target_fields: None,
+            pii_target_override: false,
             field_extractions: HashMap::new(),
             match_mode: "any".to_string(),
             special_handling: true,
+            components_base64: false,
         };
 
         // Should match when any signature event present
@@ -350,4 +570,390 @@ mod tests {
         let events = HashSet::from(["other".to_string()]);
         assert!(!schema.matches(&events));
     }
+
+    #[test]
+    fn test_forbidden_event_types_disambiguate_schemas() {
+        let signature = || vec!["THOUGHT_START".to_string(), "ACTION_RESULT".to_string()];
+        let options = HashMap::from([(
+            "1.9.3".to_string(),
+            SchemaOptions {
+                forbidden_event_types: Some(vec!["LLM_CALL".to_string()]),
+                ..Default::default()
+            },
+        )]);
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows_with_options(
+            vec![
+                ("1.9.3".to_string(), "old".to_string(), "current".to_string(), signature()),
+                ("1.9.4".to_string(), "new".to_string(), "supported".to_string(), signature()),
+            ],
+            vec![],
+            options,
+        );
+        let ctx = LogContext::new("test-batch");
+
+        let old_trace = HashSet::from(["THOUGHT_START".to_string(), "ACTION_RESULT".to_string()]);
+        let new_trace = HashSet::from([
+            "THOUGHT_START".to_string(),
+            "ACTION_RESULT".to_string(),
+            "LLM_CALL".to_string(),
+        ]);
+        assert_eq!(cache.detect_schema_version(&old_trace, &ctx).unwrap().version, "1.9.3");
+        assert_eq!(cache.detect_schema_version(&new_trace, &ctx).unwrap().version, "1.9.4");
+        assert!(cache.get_schema("1.9.4").unwrap().forbidden_event_types.is_empty());
+    }
+
+    #[test]
+    fn test_same_status_prefers_more_specific_schema() {
+        let schema = |version: &str, events: &[&str]| {
+            (
+                version.to_string(),
+                "test".to_string(),
+                "supported".to_string(),
+                events.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            )
+        };
+        let rows = vec![
+            schema("1.8.0", &["THOUGHT_START"]),
+            schema("1.8.1", &["THOUGHT_START", "ACTION_RESULT"]),
+            schema("1.7.0", &["THOUGHT_START"]),
+        ];
+        let ctx = LogContext::new("test-batch");
+        let trace = HashSet::from(["THOUGHT_START".to_string(), "ACTION_RESULT".to_string()]);
+
+        for rows in [rows.clone(), rows.into_iter().rev().collect()] {
+            let mut cache = SchemaCache::new();
+            cache.load_from_db_rows(rows, vec![]);
+            let order: Vec<&str> = cache.schemas_by_priority().iter().map(|s| s.version.as_str()).collect();
+            assert_eq!(order, ["1.8.1", "1.8.0", "1.7.0"]);
+            assert_eq!(cache.detect_schema_version(&trace, &ctx).unwrap().version, "1.8.1");
+        }
+    }
+
+    #[test]
+    fn test_mixed_connectivity_policy() {
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows(
+            vec![
+                (
+                    "connectivity".to_string(),
+                    "test".to_string(),
+                    "current".to_string(),
+                    vec!["startup".to_string(), "shutdown".to_string()],
+                ),
+                (
+                    "1.9.3".to_string(),
+                    "test".to_string(),
+                    "current".to_string(),
+                    vec!["THOUGHT_START".to_string()],
+                ),
+            ],
+            vec![],
+        );
+        let ctx = LogContext::new("test-batch");
+        let mixed = HashSet::from(["startup".to_string(), "THOUGHT_START".to_string()]);
+        let resolve = |action| {
+            cache
+                .resolve_mixed_connectivity(&mixed, action, &ctx)
+                .map(|schema| schema.map(|s| s.version.as_str()))
+        };
+
+        assert_eq!(resolve(MixedConnectivityAction::RegularWins), Ok(Some("1.9.3")));
+        assert_eq!(resolve(MixedConnectivityAction::ConnectivityWins), Ok(Some("connectivity")));
+        assert_eq!(
+            resolve(MixedConnectivityAction::Reject),
+            Err("ambiguous_connectivity_trace".to_string())
+        );
+
+        let startup_only = HashSet::from(["startup".to_string()]);
+        assert!(cache
+            .resolve_mixed_connectivity(&startup_only, MixedConnectivityAction::Reject, &ctx)
+            .is_ok_and(|schema| schema.is_none()));
+    }
+
+    #[test]
+    fn test_required_event_types_default_to_signature_events() {
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows(
+            vec![(
+                "1.9.3".to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["THOUGHT_START".to_string()],
+            )],
+            vec![],
+        );
+
+        let schema = cache.get_schema("1.9.3").unwrap();
+        assert_eq!(schema.required_event_types, schema.signature_event_types);
+    }
+
+    #[test]
+    fn test_any_mode_requires_one_required_event() {
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows(
+            vec![(
+                "connectivity".to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["startup".to_string(), "shutdown".to_string()],
+            )],
+            vec![],
+        );
+        let ctx = LogContext::new("test-batch");
+
+        // A lone startup event matches the any-mode schema and is valid
+        let events = HashSet::from(["startup".to_string()]);
+        let schema = cache.detect_schema_version(&events, &ctx).unwrap();
+        assert_eq!(schema.version, "connectivity");
+        assert_eq!(schema.required_event_types, schema.signature_event_types);
+        assert!(schema.missing_required_events(&events).is_empty());
+
+        let events = HashSet::from(["heartbeat".to_string()]);
+        assert_eq!(schema.missing_required_events(&events), vec!["shutdown", "startup"]);
+    }
+
+    #[test]
+    fn test_any_mode_configured_required_events_are_a_superset() {
+        let options = HashMap::from([(
+            "connectivity".to_string(),
+            SchemaOptions {
+                required_event_types: Some(vec!["startup".to_string(), "shutdown".to_string()]),
+                ..Default::default()
+            },
+        )]);
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows_with_options(
+            vec![(
+                "connectivity".to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["startup".to_string(), "shutdown".to_string()],
+            )],
+            vec![],
+            options,
+        );
+        let ctx = LogContext::new("test-batch");
+
+        let events = HashSet::from(["startup".to_string()]);
+        let schema = cache.detect_schema_version(&events, &ctx).unwrap();
+        assert_eq!(schema.missing_required_events(&events), vec!["shutdown"]);
+    }
+
+    #[test]
+    fn test_required_event_types_beyond_signature_events() {
+        let options = HashMap::from([(
+            "1.9.3".to_string(),
+            SchemaOptions {
+                required_event_types: Some(vec![
+                    "THOUGHT_START".to_string(),
+                    "ACTION_RESULT".to_string(),
+                ]),
+                ..Default::default()
+            },
+        )]);
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows_with_options(
+            vec![(
+                "1.9.3".to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["THOUGHT_START".to_string()],
+            )],
+            vec![],
+            options,
+        );
+        let ctx = LogContext::new("test-batch");
+
+        // Identified by the signature events alone...
+        let events = HashSet::from(["THOUGHT_START".to_string()]);
+        let schema = cache.detect_schema_version(&events, &ctx).unwrap();
+        // ...but not valid without the extra required event type.
+        assert_eq!(schema.missing_required_events(&events), vec!["ACTION_RESULT"]);
+
+        let events = HashSet::from(["THOUGHT_START".to_string(), "ACTION_RESULT".to_string()]);
+        assert!(schema.missing_required_events(&events).is_empty());
+    }
+
+    #[test]
+    fn test_allowed_values_attached_to_rules() {
+        let options = HashMap::from([(
+            "1.9.3".to_string(),
+            SchemaOptions {
+                allowed_values: Some(HashMap::from([(
+                    "selected_action".to_string(),
+                    vec!["speak".to_string(), "ponder".to_string()],
+                )])),
+                ..Default::default()
+            },
+        )]);
+        let field = |name: &str| {
+            (
+                "1.9.3".to_string(),
+                "ACTION_RESULT".to_string(),
+                name.to_string(),
+                name.to_string(),
+                "string".to_string(),
+                false,
+                name.to_string(),
+                None,
+            )
+        };
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows_with_options(
+            vec![(
+                "1.9.3".to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["ACTION_RESULT".to_string()],
+            )],
+            vec![field("selected_action"), field("action_rationale")],
+            options,
+        );
+
+        let rules = cache.get_field_rules("1.9.3", "ACTION_RESULT");
+        let action = rules.iter().find(|r| r.db_column == "selected_action").unwrap();
+        assert!(action.allowed_values.as_ref().unwrap().contains("ponder"));
+        let rationale = rules.iter().find(|r| r.db_column == "action_rationale").unwrap();
+        assert!(rationale.allowed_values.is_none());
+    }
+
+    #[test]
+    fn test_schema_columns() {
+        let field = |event_type: &str, name: &str, column: &str| {
+            (
+                "1.9.3".to_string(),
+                event_type.to_string(),
+                name.to_string(),
+                name.to_string(),
+                "string".to_string(),
+                false,
+                column.to_string(),
+                None,
+            )
+        };
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows(
+            vec![(
+                "1.9.3".to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec!["ACTION_RESULT".to_string(), "IDMA_RESULT".to_string()],
+            )],
+            vec![
+                field("ACTION_RESULT", "selected_action", "selected_action"),
+                field("ACTION_RESULT", "action", "selected_action"),
+                field("IDMA_RESULT", "k_eff", "idma_k_eff"),
+                field("IDMA_RESULT", "trace", "trace_id"),
+            ],
+        );
+
+        let columns = cache.schema_columns("1.9.3").unwrap();
+        let mut expected: Vec<&str> = STANDARD_TRACE_COLUMNS.to_vec();
+        expected.extend(["selected_action", "idma_k_eff"]);
+        expected.sort();
+        assert_eq!(columns, expected);
+        assert!(cache.schema_columns("9.9.9").is_none());
+    }
+
+    #[test]
+    fn test_pii_target_fields_merge_or_override() {
+        let schema_row = |version: &str| {
+            (
+                version.to_string(),
+                "test".to_string(),
+                "current".to_string(),
+                vec![format!("EVENT_{}", version)],
+            )
+        };
+        let options = HashMap::from([
+            (
+                "merged".to_string(),
+                SchemaOptions {
+                    pii_target_fields: Some(vec!["operator_notes".to_string()]),
+                    ..Default::default()
+                },
+            ),
+            (
+                "override".to_string(),
+                SchemaOptions {
+                    pii_target_fields: Some(vec!["operator_notes".to_string()]),
+                    pii_target_override: true,
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows_with_options(
+            vec![schema_row("merged"), schema_row("override"), schema_row("plain")],
+            vec![],
+            options,
+        );
+
+        let merged = cache.get_schema("merged").unwrap().effective_pii_target_fields().unwrap();
+        assert!(merged.contains("operator_notes"));
+        assert!(get_pii_target_fields().iter().all(|field| merged.contains(field)));
+        let overridden = cache.get_schema("override").unwrap().effective_pii_target_fields().unwrap();
+        assert_eq!(overridden, HashSet::from(["operator_notes".to_string()]));
+        assert!(cache.get_schema("plain").unwrap().effective_pii_target_fields().is_none());
+    }
+
+    #[test]
+    fn test_empty_signature_event_types_refused() {
+        let mut cache = SchemaCache::new();
+        cache.load_from_db_rows(
+            vec![
+                ("catch-all".to_string(), "test".to_string(), "current".to_string(), vec![]),
+                ("connectivity".to_string(), "test".to_string(), "current".to_string(), vec![]),
+                (
+                    "1.9.3".to_string(),
+                    "test".to_string(),
+                    "supported".to_string(),
+                    vec!["THOUGHT_START".to_string()],
+                ),
+            ],
+            vec![],
+        );
+        let ctx = LogContext::new("test-batch");
+
+        assert_eq!(cache.schema_versions(), vec!["1.9.3"]);
+        let events = HashSet::from(["THOUGHT_START".to_string()]);
+        assert_eq!(cache.detect_schema_version(&events, &ctx).unwrap().version, "1.9.3");
+    }
+
+    #[test]
+    fn test_match_confidence_exact_vs_superset() {
+        let schema = SchemaDefinition {
+            version: "1.9.3".to_string(),
+            description: "test".to_string(),
+            status: "current".to_string(),
+            signature_event_types: HashSet::from([
+                "THOUGHT_START".to_string(),
+                "ACTION_RESULT".to_string(),
+            ]),
+            required_event_types: HashSet::new(),
+            required_from_signature: false,
+            forbidden_event_types: HashSet::new(),
+            signature_quorum: None,
+            pii_target_fields: None,
+            pii_target_override: false,
+            field_extractions: HashMap::new(),
+            match_mode: "all".to_string(),
+            special_handling: false,
+            components_base64: false,
+        };
+
+        let exact = HashSet::from(["THOUGHT_START".to_string(), "ACTION_RESULT".to_string()]);
+        let superset = HashSet::from([
+            "THOUGHT_START".to_string(),
+            "ACTION_RESULT".to_string(),
+            "DMA_RESULTS".to_string(),
+            "IDMA_RESULT".to_string(),
+        ]);
+
+        assert!(schema.matches(&superset));
+        assert_eq!(schema.match_confidence(&exact), 1.0);
+        assert_eq!(schema.match_confidence(&superset), 0.5);
+        assert!(schema.match_confidence(&superset) < schema.match_confidence(&exact));
+    }
 }
@@ -2,34 +2,57 @@
 //!
 //! Verifies trace signatures using public keys loaded from database.
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::RwLock;
 use std::time::{Duration, Instant};
 
 use base64::{engine::general_purpose, Engine as _};
-use ed25519_dalek::{Signature, VerifyingKey, Verifier};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, VerifyingKey, Verifier, SIGNATURE_LENGTH};
 use lazy_static::lazy_static;
-use sha2::{Digest, Sha256};
+use parking_lot::Mutex;
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha512};
 
 use crate::logging::structured::LogContext;
 
-/// Cache TTL - 5 minutes
-const KEY_CACHE_TTL_SECS: u64 = 300;
+/// Default cache TTL - 5 minutes
+pub const KEY_CACHE_TTL_SECS: u64 = 300;
 
 /// Signature verification result.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SignatureVerificationResult {
     pub verified: bool,
     pub key_id: Option<String>,
     pub error: Option<String>,
+    /// Canonical format that verified (e.g. "1.9.9", "legacy-2field").
+    pub format: Option<String>,
+    /// Canonical formats attempted, in order (the last one verified when
+    /// `verified` is true).
+    pub formats_tried: Vec<String>,
 }
 
 impl SignatureVerificationResult {
+    /// Record which canonical format produced this result.
+    pub fn with_format(mut self, format: &str) -> Self {
+        self.format = Some(format.to_string());
+        self
+    }
+
+    /// Record the canonical formats attempted for this result.
+    pub fn with_formats_tried(mut self, formats_tried: Vec<String>) -> Self {
+        self.formats_tried = formats_tried;
+        self
+    }
+
     pub fn verified(key_id: &str) -> Self {
         Self {
             verified: true,
             key_id: Some(key_id.to_string()),
             error: None,
+            format: None,
+            formats_tried: Vec::new(),
         }
     }
 
@@ -38,6 +61,8 @@ impl SignatureVerificationResult {
             verified: false,
             key_id: None,
             error: Some("No signature provided".to_string()),
+            format: None,
+            formats_tried: Vec::new(),
         }
     }
 
@@ -46,6 +71,8 @@ impl SignatureVerificationResult {
             verified: false,
             key_id: Some(key_id.to_string()),
             error: Some("Unknown signer key".to_string()),
+            format: None,
+            formats_tried: Vec::new(),
         }
     }
 
@@ -54,31 +81,170 @@ impl SignatureVerificationResult {
             verified: false,
             key_id: Some(key_id.to_string()),
             error: Some(error.to_string()),
+            format: None,
+            formats_tried: Vec::new(),
         }
     }
 }
 
-/// Cache for public keys.
-#[derive(Debug)]
-pub struct PublicKeyCache {
-    keys: HashMap<String, VerifyingKey>,
-    loaded_at: Option<Instant>,
+/// Ed25519 signing mode used by the agent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SignatureMode {
+    /// PureEdDSA over the full message.
+    #[default]
+    Pure,
+    /// Ed25519ph: signature over the SHA-512 digest of the message.
+    Prehashed,
+}
+
+impl SignatureMode {
+    /// Parse the trace's `signature_mode` field; absent means pure.
+    pub fn from_field(value: Option<&str>) -> Result<Self, String> {
+        match value.map(|v| v.trim().to_lowercase()).as_deref() {
+            None | Some("pure") | Some("ed25519") => Ok(SignatureMode::Pure),
+            Some("prehashed") | Some("ed25519ph") => Ok(SignatureMode::Prehashed),
+            Some(other) => Err(format!("Unsupported signature_mode: {}", other)),
+        }
+    }
+}
+
+/// Algorithm tag of the keys Lens can verify.
+pub const KEY_ALGORITHM_ED25519: &str = "ed25519";
+
+/// Algorithm, validity window and signing domain stored alongside a
+/// public key.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KeyMetadata {
+    pub algorithm: String,
+    pub not_before: Option<DateTime<Utc>>,
+    pub not_after: Option<DateTime<Utc>>,
+    /// Domain-separation tag (e.g. `CIRIS-TRACE-v1\n`) the key's agent
+    /// prepends to the canonical message before signing.
+    pub domain_prefix: Option<String>,
 }
 
-impl Default for PublicKeyCache {
+impl Default for KeyMetadata {
     fn default() -> Self {
         Self {
-            keys: HashMap::new(),
-            loaded_at: None,
+            algorithm: KEY_ALGORITHM_ED25519.to_string(),
+            not_before: None,
+            not_after: None,
+            domain_prefix: None,
+        }
+    }
+}
+
+impl KeyMetadata {
+    /// Parse an optional algorithm tag (default `ed25519`) and RFC3339
+    /// window bounds.
+    ///
+    /// # Errors
+    /// Unsupported algorithm or an invalid timestamp.
+    pub fn parse(
+        algorithm: Option<&str>,
+        not_before: Option<&str>,
+        not_after: Option<&str>,
+    ) -> Result<Self, String> {
+        let algorithm = algorithm
+            .map(|a| a.trim().to_lowercase())
+            .unwrap_or_else(|| KEY_ALGORITHM_ED25519.to_string());
+        if algorithm != KEY_ALGORITHM_ED25519 {
+            return Err(format!("Unsupported key algorithm: {}", algorithm));
+        }
+        let parse = |field: &str, value: Option<&str>| {
+            value
+                .map(|v| {
+                    DateTime::parse_from_rfc3339(v)
+                        .map(|dt| dt.with_timezone(&Utc))
+                        .map_err(|e| format!("Invalid {} {:?}: {}", field, v, e))
+                })
+                .transpose()
+        };
+        Ok(Self {
+            algorithm,
+            not_before: parse("not_before", not_before)?,
+            not_after: parse("not_after", not_after)?,
+            domain_prefix: None,
+        })
+    }
+
+    /// Check `at` against the window: `key_not_yet_valid` before
+    /// `not_before`, `key_expired` after `not_after`.
+    pub fn check_validity(&self, at: DateTime<Utc>) -> Result<(), &'static str> {
+        if self.not_before.is_some_and(|nb| at < nb) {
+            return Err("key_not_yet_valid");
         }
+        if self.not_after.is_some_and(|na| at > na) {
+            return Err("key_expired");
+        }
+        Ok(())
     }
 }
 
+/// Cache for public keys.
+#[derive(Debug, Default)]
+pub struct PublicKeyCache {
+    keys: HashMap<String, VerifyingKey>,
+    metadata: HashMap<String, KeyMetadata>,
+    loaded_at: Option<Instant>,
+    /// Lowercase key ids on load and lookup (opt-in; ids are
+    /// case-sensitive by default).
+    case_insensitive_ids: bool,
+    /// Accept hex-encoded signatures after the base64 attempts (opt-in).
+    accept_hex_signatures: bool,
+    /// Domain-separation tag tried for every key (see
+    /// `KeyMetadata::domain_prefix`).
+    domain_prefix: Option<String>,
+}
+
 impl PublicKeyCache {
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Set whether key ids are matched case-insensitively.
+    ///
+    /// Applies to keys loaded afterwards, so set it before loading.
+    pub fn set_case_insensitive_ids(&mut self, enabled: bool) {
+        self.case_insensitive_ids = enabled;
+    }
+
+    /// Set whether hex-encoded signatures are accepted.
+    pub fn set_accept_hex_signatures(&mut self, enabled: bool) {
+        self.accept_hex_signatures = enabled;
+    }
+
+    /// Set the domain-separation tag tried for every key.
+    pub fn set_domain_prefix(&mut self, prefix: Option<String>) {
+        self.domain_prefix = prefix.filter(|p| !p.is_empty());
+    }
+
+    /// Domain-separation tags to try for a key after the bare message:
+    /// the key's own, then the global one.
+    pub fn domain_prefixes(&self, key_id: &str) -> Vec<&str> {
+        let mut prefixes: Vec<&str> = Vec::new();
+        let own = self
+            .get_key_metadata(key_id)
+            .and_then(|m| m.domain_prefix.as_deref());
+        for prefix in own.into_iter().chain(self.domain_prefix.as_deref()) {
+            if !prefix.is_empty() && !prefixes.contains(&prefix) {
+                prefixes.push(prefix);
+            }
+        }
+        prefixes
+    }
+
+    /// Normalize a key id for storage/lookup: surrounding whitespace is
+    /// always trimmed; case is folded only when enabled.
+    pub fn normalize_key_id<'a>(&self, key_id: &'a str) -> Cow<'a, str> {
+        let trimmed = key_id.trim();
+        if self.case_insensitive_ids && trimmed.chars().any(|c| c.is_uppercase()) {
+            Cow::Owned(trimmed.to_lowercase())
+        } else {
+            Cow::Borrowed(trimmed)
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.keys.is_empty()
     }
@@ -87,13 +253,18 @@ impl PublicKeyCache {
         self.keys.len()
     }
 
-    /// Check if cache needs refresh (empty or TTL expired).
+    /// Check if cache needs refresh (empty or default TTL expired).
     pub fn needs_refresh(&self) -> bool {
+        self.needs_refresh_after(KEY_CACHE_TTL_SECS)
+    }
+
+    /// Check if cache needs refresh (empty or `ttl_secs` expired).
+    pub fn needs_refresh_after(&self, ttl_secs: u64) -> bool {
         if self.keys.is_empty() {
             return true;
         }
         match self.loaded_at {
-            Some(loaded_at) => loaded_at.elapsed() > Duration::from_secs(KEY_CACHE_TTL_SECS),
+            Some(loaded_at) => loaded_at.elapsed() > Duration::from_secs(ttl_secs),
             None => true,
         }
     }
@@ -109,15 +280,40 @@ impl PublicKeyCache {
     }
 
     pub fn has_key(&self, key_id: &str) -> bool {
-        self.keys.contains_key(key_id)
+        self.keys.contains_key(self.normalize_key_id(key_id).as_ref())
     }
 
     pub fn get_key(&self, key_id: &str) -> Option<&VerifyingKey> {
-        self.keys.get(key_id)
+        self.keys.get(self.normalize_key_id(key_id).as_ref())
     }
 
-    /// Load public key from base64-encoded bytes.
+    /// Algorithm and validity window of a loaded key.
+    pub fn get_key_metadata(&self, key_id: &str) -> Option<&KeyMetadata> {
+        self.metadata.get(self.normalize_key_id(key_id).as_ref())
+    }
+
+    /// Check a loaded key's validity window at `at`; unknown keys pass
+    /// (the lookup at verification reports them).
+    pub fn check_key_validity(&self, key_id: &str, at: DateTime<Utc>) -> Result<(), &'static str> {
+        match self.get_key_metadata(key_id) {
+            Some(metadata) => metadata.check_validity(at),
+            None => Ok(()),
+        }
+    }
+
+    /// Load public key from base64-encoded bytes, valid indefinitely.
     pub fn load_key(&mut self, key_id: &str, public_key_base64: &str) -> Result<(), String> {
+        self.load_key_with_metadata(key_id, public_key_base64, KeyMetadata::default())
+    }
+
+    /// Load public key from base64-encoded bytes with its algorithm and
+    /// validity window.
+    pub fn load_key_with_metadata(
+        &mut self,
+        key_id: &str,
+        public_key_base64: &str,
+        metadata: KeyMetadata,
+    ) -> Result<(), String> {
         let key_bytes = general_purpose::STANDARD
             .decode(public_key_base64)
             .map_err(|e| format!("Failed to decode base64: {}", e))?;
@@ -136,13 +332,24 @@ impl PublicKeyCache {
         let verifying_key = VerifyingKey::from_bytes(&key_array)
             .map_err(|e| format!("Invalid public key: {}", e))?;
 
-        self.keys.insert(key_id.to_string(), verifying_key);
+        let normalized = self.normalize_key_id(key_id);
+        if normalized != key_id {
+            log::info!(
+                "PUBLIC_KEY_ID_NORMALIZED original={:?} normalized={:?}",
+                key_id,
+                normalized
+            );
+        }
+        let normalized = normalized.into_owned();
+        self.metadata.insert(normalized.clone(), metadata);
+        self.keys.insert(normalized, verifying_key);
         Ok(())
     }
 
     /// Clear all keys.
     pub fn clear(&mut self) {
         self.keys.clear();
+        self.metadata.clear();
         self.loaded_at = None;
         log::info!("PUBLIC_KEY_CACHE_CLEARED");
     }
@@ -174,6 +381,20 @@ pub fn verify_signature(
     signature_base64: &str,
     key_id: &str,
     ctx: &LogContext,
+) -> SignatureVerificationResult {
+    verify_signature_with_mode(message, signature_base64, key_id, SignatureMode::Pure, ctx)
+}
+
+/// Verify an Ed25519 signature in the given mode.
+///
+/// Prehashed mode verifies Ed25519ph over the SHA-512 digest of `message`
+/// with an empty context.
+pub fn verify_signature_with_mode(
+    message: &str,
+    signature_base64: &str,
+    key_id: &str,
+    mode: SignatureMode,
+    ctx: &LogContext,
 ) -> SignatureVerificationResult {
     let cache = get_key_cache();
 
@@ -188,9 +409,21 @@ pub fn verify_signature(
             verified: false,
             key_id: Some(key_id.to_string()),
             error: Some("No public keys loaded - cannot verify signature".to_string()),
+            format: None,
+            formats_tried: Vec::new(),
         };
     }
 
+    let normalized = cache.normalize_key_id(key_id);
+    if normalized != key_id {
+        log::debug!(
+            "{} SIGNATURE_KEY_ID_NORMALIZED original={:?} normalized={:?}",
+            ctx,
+            key_id,
+            normalized
+        );
+    }
+
     // Look up the key
     let verifying_key = match cache.get_key(key_id) {
         Some(key) => key,
@@ -210,10 +443,8 @@ pub fn verify_signature(
         key_id
     );
 
-    // Decode signature (try URL-safe first, then standard base64)
-    let signature_bytes = general_purpose::URL_SAFE_NO_PAD
-        .decode(signature_base64)
-        .or_else(|_| general_purpose::STANDARD.decode(signature_base64));
+    // Decode signature (try URL-safe first, then standard base64, then hex)
+    let signature_bytes = decode_signature(signature_base64, cache.accept_hex_signatures);
 
     let signature_bytes = match signature_bytes {
         Ok(bytes) => bytes,
@@ -248,13 +479,34 @@ pub fn verify_signature(
         }
     };
 
-    // Verify
-    match verifying_key.verify(message.as_bytes(), &signature) {
+    // Verify the bare message, then with each domain-separation tag
+    let verify_message = |message: &[u8]| match mode {
+        SignatureMode::Pure => verifying_key.verify(message, &signature),
+        SignatureMode::Prehashed => {
+            let mut digest = Sha512::new();
+            digest.update(message);
+            verifying_key.verify_prehashed(digest, None, &signature)
+        }
+    };
+    let mut verified = verify_message(message.as_bytes());
+    let mut domain_prefix = None;
+    if verified.is_err() {
+        for prefix in cache.domain_prefixes(key_id) {
+            if verify_message(format!("{}{}", prefix, message).as_bytes()).is_ok() {
+                verified = Ok(());
+                domain_prefix = Some(prefix);
+                break;
+            }
+        }
+    }
+    match verified {
         Ok(()) => {
             log::info!(
-                "{} SIGNATURE_VERIFY key_id={} valid=true",
+                "{} SIGNATURE_VERIFY key_id={} mode={:?} domain_prefix={:?} valid=true",
                 ctx,
-                key_id
+                key_id,
+                mode,
+                domain_prefix
             );
             SignatureVerificationResult::verified(key_id)
         }
@@ -270,6 +522,197 @@ pub fn verify_signature(
     }
 }
 
+/// One (message, signature, key) triple for `verify_signatures_batch`.
+#[derive(Debug, Clone, Copy)]
+pub struct BatchVerifyItem<'a> {
+    pub message: &'a str,
+    pub signature: &'a str,
+    pub key_id: &'a str,
+}
+
+/// Verify many PureEdDSA signatures with one `ed25519_dalek::verify_batch`
+/// call; returns whether each item verified, in input order.
+///
+/// Items with an unknown key or an undecodable signature are false
+/// without entering the batch. When the batch fails, each item is
+/// verified individually to pinpoint the bad ones.
+///
+/// Batch verification can accept a few non-canonical edge-case
+/// signatures that individual verification rejects, so this is an
+/// opt-in fast path.
+pub fn verify_signatures_batch(items: &[BatchVerifyItem], ctx: &LogContext) -> Vec<bool> {
+    let cache = get_key_cache();
+    let mut verified = vec![false; items.len()];
+
+    let mut indices = Vec::new();
+    let mut messages: Vec<&[u8]> = Vec::new();
+    let mut signatures = Vec::new();
+    let mut keys = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        let key = match cache.get_key(item.key_id) {
+            Some(key) => *key,
+            None => continue,
+        };
+        let signature = match decode_signature(item.signature, cache.accept_hex_signatures)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        {
+            Some(signature) => signature,
+            None => continue,
+        };
+        indices.push(i);
+        messages.push(item.message.as_bytes());
+        signatures.push(signature);
+        keys.push(key);
+    }
+
+    if indices.is_empty() {
+        return verified;
+    }
+
+    if ed25519_dalek::verify_batch(&messages, &signatures, &keys).is_ok() {
+        for &i in &indices {
+            verified[i] = true;
+        }
+    } else {
+        for (j, &i) in indices.iter().enumerate() {
+            verified[i] = keys[j].verify(messages[j], &signatures[j]).is_ok();
+        }
+    }
+
+    log::debug!(
+        "{} SIGNATURE_BATCH_VERIFY items={} batched={} verified={}",
+        ctx,
+        items.len(),
+        indices.len(),
+        verified.iter().filter(|v| **v).count()
+    );
+    verified
+}
+
+/// Decode a signature string: URL-safe base64, then standard base64, then
+/// (when enabled) hex.
+///
+/// A 128-char hex string is also valid base64 and decodes to 96 bytes, so
+/// hex is tried whenever base64 doesn't yield exactly 64 bytes and the
+/// input looks like hex.
+fn decode_signature(encoded: &str, accept_hex: bool) -> Result<Vec<u8>, base64::DecodeError> {
+    let base64_result = general_purpose::URL_SAFE_NO_PAD
+        .decode(encoded)
+        .or_else(|_| general_purpose::STANDARD.decode(encoded));
+
+    if let Ok(ref bytes) = base64_result {
+        if bytes.len() == SIGNATURE_LENGTH {
+            return base64_result;
+        }
+    }
+
+    let looks_like_hex =
+        encoded.len() == SIGNATURE_LENGTH * 2 && encoded.chars().all(|c| c.is_ascii_hexdigit());
+    if accept_hex && looks_like_hex {
+        if let Ok(bytes) = hex::decode(encoded) {
+            return Ok(bytes);
+        }
+    }
+
+    base64_result
+}
+
+/// Aggregate verification cost for one canonical format.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FormatVerifyMetrics {
+    /// Verification attempts using this format.
+    pub attempts: u64,
+    /// Attempts that verified.
+    pub verified: u64,
+    /// Total time spent on attempts (canonicalization + verify), in micros.
+    pub total_micros: u64,
+}
+
+lazy_static! {
+    /// Per-format verify metrics, keyed by format name (`1.9.9`, `1.9.7`, ...).
+    static ref SIGNATURE_METRICS: Mutex<BTreeMap<String, FormatVerifyMetrics>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Record one verification attempt for a canonical format.
+pub fn record_format_attempt(format: &str, verified: bool, elapsed: Duration) {
+    let mut metrics = SIGNATURE_METRICS.lock();
+    let entry = metrics.entry(format.to_string()).or_default();
+    entry.attempts += 1;
+    if verified {
+        entry.verified += 1;
+    }
+    entry.total_micros += elapsed.as_micros() as u64;
+}
+
+/// Snapshot of the per-format verify metrics since process start.
+pub fn get_signature_metrics() -> BTreeMap<String, FormatVerifyMetrics> {
+    SIGNATURE_METRICS.lock().clone()
+}
+
+/// Keys tracked in the activity index; the least recently seen key is
+/// dropped to make room beyond this.
+pub const MAX_KEY_ACTIVITY_ENTRIES: usize = 10_000;
+
+/// Verification activity for one key since the last key cache refresh.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyActivity {
+    pub key_id: String,
+    pub last_seen: DateTime<Utc>,
+    pub verified: u64,
+    pub failed: u64,
+    /// Canonical formats this key's signatures verified with.
+    pub formats: BTreeSet<String>,
+}
+
+lazy_static! {
+    /// Per-key verification activity, keyed by (normalized) key id.
+    static ref KEY_ACTIVITY: Mutex<HashMap<String, KeyActivity>> = Mutex::new(HashMap::new());
+}
+
+/// Record one signature check against a key.
+pub fn record_key_activity(key_id: &str, verified: bool, format: Option<&str>) {
+    let mut activity = KEY_ACTIVITY.lock();
+    if !activity.contains_key(key_id) && activity.len() >= MAX_KEY_ACTIVITY_ENTRIES {
+        let stalest = activity
+            .values()
+            .min_by_key(|entry| entry.last_seen)
+            .map(|entry| entry.key_id.clone());
+        if let Some(stalest) = stalest {
+            activity.remove(&stalest);
+        }
+    }
+    let entry = activity.entry(key_id.to_string()).or_insert_with(|| KeyActivity {
+        key_id: key_id.to_string(),
+        last_seen: Utc::now(),
+        verified: 0,
+        failed: 0,
+        formats: BTreeSet::new(),
+    });
+    entry.last_seen = Utc::now();
+    if verified {
+        entry.verified += 1;
+    } else {
+        entry.failed += 1;
+    }
+    if let Some(format) = format {
+        entry.formats.insert(format.to_string());
+    }
+}
+
+/// Snapshot of per-key activity, ordered by key id.
+pub fn get_key_activity() -> Vec<KeyActivity> {
+    let mut activity: Vec<KeyActivity> = KEY_ACTIVITY.lock().values().cloned().collect();
+    activity.sort_by(|a, b| a.key_id.cmp(&b.key_id));
+    activity
+}
+
+/// Forget all key activity (call on any key reload or refresh).
+pub fn clear_key_activity() {
+    KEY_ACTIVITY.lock().clear();
+}
+
 /// Compute SHA256 hash of content.
 pub fn compute_hash(content: &str) -> String {
     let mut hasher = Sha256::new();
@@ -288,6 +731,96 @@ mod tests {
         assert_eq!(hash.len(), 64); // SHA256 produces 64 hex chars
     }
 
+    #[test]
+    fn test_verify_signatures_batch_pinpoints_bad_signature() {
+        use ed25519_dalek::Signer;
+
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[51; 32]);
+        get_key_cache_mut()
+            .load_key(
+                "batch-verify-test",
+                &general_purpose::STANDARD.encode(signing_key.verifying_key().to_bytes()),
+            )
+            .unwrap();
+        let messages = ["m-0", "m-1", "m-2", "m-3"];
+        let mut signatures: Vec<String> = messages
+            .iter()
+            .map(|m| general_purpose::STANDARD.encode(signing_key.sign(m.as_bytes()).to_bytes()))
+            .collect();
+        // Valid signature, wrong message
+        signatures[2] = signatures[0].clone();
+        let items: Vec<BatchVerifyItem> = messages
+            .iter()
+            .zip(&signatures)
+            .map(|(message, signature)| BatchVerifyItem {
+                message,
+                signature,
+                key_id: "batch-verify-test",
+            })
+            .collect();
+        let ctx = LogContext::new("test-batch");
+
+        assert_eq!(verify_signatures_batch(&items, &ctx), [true, true, false, true]);
+
+        let mut all_good = items.clone();
+        all_good.remove(2);
+        assert_eq!(verify_signatures_batch(&all_good, &ctx), [true, true, true]);
+
+        let unknown = [BatchVerifyItem {
+            key_id: "batch-verify-unknown",
+            ..items[0]
+        }];
+        assert_eq!(verify_signatures_batch(&unknown, &ctx), [false]);
+    }
+
+    #[test]
+    fn test_key_validity_window() {
+        let at = |ts: &str| DateTime::parse_from_rfc3339(ts).unwrap().with_timezone(&Utc);
+        let metadata = KeyMetadata::parse(
+            Some("Ed25519"),
+            Some("2026-01-01T00:00:00Z"),
+            Some("2026-06-30T00:00:00Z"),
+        )
+        .unwrap();
+
+        assert_eq!(metadata.algorithm, "ed25519");
+        assert_eq!(metadata.check_validity(at("2025-12-31T23:59:59Z")), Err("key_not_yet_valid"));
+        assert_eq!(metadata.check_validity(at("2026-03-01T00:00:00Z")), Ok(()));
+        assert_eq!(metadata.check_validity(at("2026-07-01T00:00:00Z")), Err("key_expired"));
+        assert_eq!(KeyMetadata::default().check_validity(at("2099-01-01T00:00:00Z")), Ok(()));
+
+        assert!(KeyMetadata::parse(Some("rsa"), None, None).is_err());
+        assert!(KeyMetadata::parse(None, Some("yesterday"), None).is_err());
+
+        let mut cache = PublicKeyCache::new();
+        let public_b64 = general_purpose::STANDARD
+            .encode(ed25519_dalek::SigningKey::from_bytes(&[52; 32]).verifying_key().to_bytes());
+        cache.load_key_with_metadata("window-key", &public_b64, metadata).unwrap();
+        assert_eq!(
+            cache.check_key_validity("window-key", at("2026-07-01T00:00:00Z")),
+            Err("key_expired")
+        );
+        assert!(cache.get_key_metadata("window-key").is_some());
+        cache.clear();
+        assert!(cache.get_key_metadata("window-key").is_none());
+    }
+
+    #[test]
+    fn test_record_format_attempt() {
+        let before = get_signature_metrics()
+            .get("test-format")
+            .cloned()
+            .unwrap_or_default();
+
+        record_format_attempt("test-format", false, Duration::from_micros(10));
+        record_format_attempt("test-format", true, Duration::from_micros(5));
+
+        let after = get_signature_metrics()["test-format"].clone();
+        assert_eq!(after.attempts, before.attempts + 2);
+        assert_eq!(after.verified, before.verified + 1);
+        assert!(after.total_micros >= before.total_micros + 15);
+    }
+
     #[test]
     fn test_key_cache() {
         let mut cache = PublicKeyCache::new();
@@ -304,4 +837,161 @@ mod tests {
 
         assert!(!cache.has_key("test-key"));
     }
+
+    /// A valid Ed25519 public key (base64) derived from a fixed seed.
+    fn test_public_key() -> String {
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        general_purpose::STANDARD.encode(signing_key.verifying_key().to_bytes())
+    }
+
+    #[test]
+    fn test_key_id_trailing_space_trimmed() {
+        let mut cache = PublicKeyCache::new();
+        cache.load_key("agent-key ", &test_public_key()).unwrap();
+
+        assert!(cache.has_key("agent-key"));
+        assert!(cache.has_key(" agent-key\t"));
+    }
+
+    #[test]
+    fn test_key_id_case_opt_in() {
+        let mut cache = PublicKeyCache::new();
+        cache.load_key("Agent-Key", &test_public_key()).unwrap();
+        assert!(cache.has_key("Agent-Key"));
+        assert!(!cache.has_key("agent-key"));
+
+        let mut cache = PublicKeyCache::new();
+        cache.set_case_insensitive_ids(true);
+        cache.load_key("Agent-Key", &test_public_key()).unwrap();
+        assert!(cache.has_key("agent-key"));
+        assert!(cache.has_key("AGENT-KEY "));
+    }
+
+    #[test]
+    fn test_prehashed_signature_requires_prehash_mode() {
+        use ed25519_dalek::SigningKey;
+
+        let signing_key = SigningKey::from_bytes(&[9; 32]);
+        get_key_cache_mut()
+            .load_key(
+                "prehash-test",
+                &general_purpose::STANDARD.encode(signing_key.verifying_key().to_bytes()),
+            )
+            .unwrap();
+
+        let message = r#"{"components":[],"trace_level":"detailed"}"#;
+        let mut digest = Sha512::new();
+        digest.update(message.as_bytes());
+        let signature = signing_key.sign_prehashed(digest, None).unwrap();
+        let signature_b64 = general_purpose::STANDARD.encode(signature.to_bytes());
+        let ctx = LogContext::new("test-batch");
+
+        let prehashed = verify_signature_with_mode(
+            message,
+            &signature_b64,
+            "prehash-test",
+            SignatureMode::Prehashed,
+            &ctx,
+        );
+        assert!(prehashed.verified, "{:?}", prehashed.error);
+
+        let pure = verify_signature(message, &signature_b64, "prehash-test", &ctx);
+        assert!(!pure.verified);
+    }
+
+    #[test]
+    fn test_domain_prefixed_signature() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let signing_key = SigningKey::from_bytes(&[62; 32]);
+        let metadata = KeyMetadata {
+            domain_prefix: Some("CIRIS-TRACE-v1\n".to_string()),
+            ..KeyMetadata::default()
+        };
+        get_key_cache_mut()
+            .load_key_with_metadata(
+                "domain-prefix-test",
+                &general_purpose::STANDARD.encode(signing_key.verifying_key().to_bytes()),
+                metadata,
+            )
+            .unwrap();
+
+        let message = r#"{"components":[],"trace_level":"detailed"}"#;
+        let sign = |m: &str| general_purpose::STANDARD.encode(signing_key.sign(m.as_bytes()).to_bytes());
+        let ctx = LogContext::new("test-batch");
+
+        let prefixed = sign(&format!("CIRIS-TRACE-v1\n{}", message));
+        assert!(verify_signature(message, &prefixed, "domain-prefix-test", &ctx).verified);
+        // Bare signatures still verify
+        assert!(verify_signature(message, &sign(message), "domain-prefix-test", &ctx).verified);
+        let other_domain = sign(&format!("OTHER-PROTOCOL\n{}", message));
+        assert!(!verify_signature(message, &other_domain, "domain-prefix-test", &ctx).verified);
+
+        let mut cache = PublicKeyCache::new();
+        cache.set_domain_prefix(Some("CIRIS-TRACE-v1\n".to_string()));
+        cache
+            .load_key_with_metadata(
+                "k",
+                &general_purpose::STANDARD.encode(signing_key.verifying_key().to_bytes()),
+                KeyMetadata {
+                    domain_prefix: Some("CIRIS-AGENT-v2\n".to_string()),
+                    ..KeyMetadata::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(cache.domain_prefixes("k"), ["CIRIS-AGENT-v2\n", "CIRIS-TRACE-v1\n"]);
+        assert_eq!(cache.domain_prefixes("unknown"), ["CIRIS-TRACE-v1\n"]);
+    }
+
+    #[test]
+    fn test_signature_mode_from_field() {
+        assert_eq!(SignatureMode::from_field(None), Ok(SignatureMode::Pure));
+        assert_eq!(
+            SignatureMode::from_field(Some("Ed25519ph")),
+            Ok(SignatureMode::Prehashed)
+        );
+        assert!(SignatureMode::from_field(Some("rsa")).is_err());
+    }
+
+    #[test]
+    fn test_hex_encoded_signature() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let signing_key = SigningKey::from_bytes(&[10; 32]);
+        let message = r#"{"components":[],"trace_level":"detailed"}"#;
+        let signature = signing_key.sign(message.as_bytes()).to_bytes();
+        let signature_hex = hex::encode(signature);
+        assert_eq!(signature_hex.len(), 128);
+
+        assert_eq!(decode_signature(&signature_hex, true).unwrap(), signature.to_vec());
+        // Without hex acceptance the base64 reading (96 bytes) is returned
+        assert_eq!(decode_signature(&signature_hex, false).unwrap().len(), 96);
+        // Base64 still wins when it yields a full signature
+        let signature_b64 = general_purpose::STANDARD.encode(signature);
+        assert_eq!(decode_signature(&signature_b64, true).unwrap(), signature.to_vec());
+
+        let mut cache = get_key_cache_mut();
+        cache
+            .load_key(
+                "hex-sig-test",
+                &general_purpose::STANDARD.encode(signing_key.verifying_key().to_bytes()),
+            )
+            .unwrap();
+        cache.set_accept_hex_signatures(true);
+        drop(cache);
+
+        // The cache is process-wide: turn hex acceptance back off even if
+        // an assertion fails, so other tests run with the default
+        struct RestoreHexDefault;
+        impl Drop for RestoreHexDefault {
+            fn drop(&mut self) {
+                get_key_cache_mut().set_accept_hex_signatures(false);
+            }
+        }
+        let _restore = RestoreHexDefault;
+
+        let ctx = LogContext::new("test-batch");
+        let result = verify_signature(message, &signature_hex, "hex-sig-test", &ctx);
+        assert!(result.verified, "{:?}", result.error);
+    }
 }
@@ -0,0 +1,120 @@
+//! Cache of signature verification results.
+//!
+//! Retried traces carry the same body, signature and key, and re-running
+//! the Ed25519 format cascade for them is wasted work. A bounded map from
+//! a hash of (trace level, key id, signature, canonical body) to the
+//! verification result lets repeats skip it.
+//!
+//! Results depend on the loaded keys and signature settings, so the cache
+//! is cleared whenever keys are reloaded or refreshed and when hex
+//! signature acceptance changes.
+
+use std::collections::{HashMap, VecDeque};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+use super::signature::{compute_hash, SignatureVerificationResult};
+
+/// Default number of verification results remembered.
+pub const DEFAULT_VERIFICATION_CACHE_CAPACITY: usize = 10_000;
+
+/// Bounded FIFO map of verification results.
+#[derive(Debug, Default)]
+pub struct VerificationCache {
+    entries: HashMap<String, SignatureVerificationResult>,
+    order: VecDeque<String>,
+}
+
+impl VerificationCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, cache_key: &str) -> Option<SignatureVerificationResult> {
+        self.entries.get(cache_key).cloned()
+    }
+
+    /// Remember a result, evicting the oldest entries beyond `capacity`.
+    /// A capacity of 0 disables the cache.
+    pub fn insert(&mut self, cache_key: String, result: SignatureVerificationResult, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        if self.entries.insert(cache_key.clone(), result).is_none() {
+            self.order.push_back(cache_key);
+        }
+        while self.order.len() > capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+lazy_static! {
+    static ref VERIFICATION_CACHE: Mutex<VerificationCache> = Mutex::new(VerificationCache::new());
+}
+
+/// Cache key for one signature over a trace body at a trace level.
+///
+/// `body` is the trace's canonical serialization: the envelope format
+/// signs the whole trace, so the components alone don't determine the
+/// outcome.
+pub fn verification_cache_key(trace_level: &str, key_id: &str, signature: &str, body: &str) -> String {
+    compute_hash(&format!("{}\n{}\n{}\n{}", trace_level, key_id, signature, body))
+}
+
+/// A cached verification result, if any.
+pub fn get_cached_verification(cache_key: &str) -> Option<SignatureVerificationResult> {
+    VERIFICATION_CACHE.lock().get(cache_key)
+}
+
+/// Remember a verification result.
+pub fn cache_verification(cache_key: String, result: SignatureVerificationResult, capacity: usize) {
+    VERIFICATION_CACHE.lock().insert(cache_key, result, capacity);
+}
+
+/// Forget all cached results (call on any key reload or signature
+/// setting change).
+pub fn clear_verification_cache() {
+    let mut cache = VERIFICATION_CACHE.lock();
+    if !cache.is_empty() {
+        log::info!("SIGNATURE_CACHE_CLEARED entries={}", cache.len());
+    }
+    cache.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded_eviction() {
+        let mut cache = VerificationCache::new();
+        cache.insert("a".to_string(), SignatureVerificationResult::verified("k1"), 2);
+        cache.insert("b".to_string(), SignatureVerificationResult::verified("k2"), 2);
+        cache.insert("a".to_string(), SignatureVerificationResult::verified("k1"), 2);
+        cache.insert("c".to_string(), SignatureVerificationResult::verified("k1"), 2);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some_and(|r| r.verified));
+
+        cache.insert("d".to_string(), SignatureVerificationResult::verified("k1"), 0);
+        assert!(cache.get("d").is_none());
+    }
+}
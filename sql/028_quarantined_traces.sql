@@ -0,0 +1,30 @@
+-- Migration 028: quarantined_traces table
+-- Valid traces isolated by a routing policy (e.g. an unsupported declared
+-- schema version) until the cause is resolved. Unlike malformed_traces,
+-- the extracted metadata is kept so the trace can be released later.
+
+CREATE TABLE IF NOT EXISTS cirislens.quarantined_traces (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    trace_id VARCHAR(256),
+    content_hash VARCHAR(64) NOT NULL,
+
+    -- Why the trace was quarantined
+    policy_reason TEXT NOT NULL,
+    event_types TEXT[],
+    trace_level VARCHAR(20),
+    received_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    -- Context for triage
+    schema_version VARCHAR(50),
+    signature_key_id VARCHAR(128),
+    extracted_metadata JSONB
+);
+
+CREATE INDEX IF NOT EXISTS idx_quarantined_traces_received
+ON cirislens.quarantined_traces (received_at DESC);
+
+CREATE INDEX IF NOT EXISTS idx_quarantined_traces_reason
+ON cirislens.quarantined_traces (policy_reason, received_at DESC);
+
+COMMENT ON TABLE cirislens.quarantined_traces IS 'Valid traces held by a routing policy until released';
+COMMENT ON COLUMN cirislens.quarantined_traces.policy_reason IS 'Routing policy that quarantined the trace, e.g. schema_version_unsupported';
@@ -0,0 +1,8 @@
+-- Migration 029: default values for schema field extraction rules
+-- Stored (converted to the rule's data_type) when the json_path is absent
+-- from a trace, so NOT NULL columns get a sensible value.
+
+ALTER TABLE cirislens.trace_schema_fields
+    ADD COLUMN IF NOT EXISTS default_value TEXT;
+
+COMMENT ON COLUMN cirislens.trace_schema_fields.default_value IS 'Value used when json_path is absent, as a JSON literal (false, 0.5) or plain string';